@@ -0,0 +1,539 @@
+//! Compare `hashmap::HashMap` with `std::collections::HashMap` and
+//! `BTreeMap`, and with `IntMap` and `SparseSet` on `u32` keys.
+//!
+//! Run with `cargo bench --bench compare -- [options]`:
+//!
+//!   --format csv|json   report format written to stdout (default csv)
+//!   --max-size N        largest map size to run (default 1000000)
+//!   --maps a,b,...      subset of: hashmap, std, btree, intmap, sparseset
+//!   --keys a,b,...      subset of: int, short, long, u32
+//!
+//! `intmap` and `sparseset` only run with `u32` keys, which are dense
+//! small integers. `sparseset` runs the set operations against a
+//! `HashMap<u32, ()>`.
+//!
+//! The 10 million entry runs need several GB of memory with `long` keys,
+//! so they only happen with `--max-size 10000000`.
+//!
+//! Timings are nanoseconds per operation, averaged over enough rounds to
+//! run for at least `MIN_ROUND_TIME`. Save the reports from two commits and
+//! diff them to spot regressions.
+
+use std::{
+    collections::{BTreeMap, HashMap as StdHashMap},
+    env,
+    hash::Hash,
+    hint::black_box,
+    process,
+    time::{Duration, Instant},
+};
+
+use hashmap::{
+    int::{IntMap, SparseSet},
+    HashMap,
+};
+
+const SIZES: [usize; 7] = [10, 100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+const DEFAULT_MAX_SIZE: usize = 1_000_000;
+const MIN_ROUND_TIME: Duration = Duration::from_millis(50);
+
+/// The operations every map under test has to provide
+trait BenchMap<K>: Sized {
+    const NAME: &'static str;
+
+    fn empty() -> Self;
+    fn from_pairs(pairs: Vec<(K, u64)>) -> Self;
+    fn put(&mut self, key: K, value: u64);
+    fn lookup(&self, key: &K) -> Option<&u64>;
+    fn delete(&mut self, key: &K) -> Option<u64>;
+    fn upsert(&mut self, key: K);
+    fn sum_values(&self) -> u64;
+}
+
+impl<K: Hash + Eq> BenchMap<K> for HashMap<K, u64> {
+    const NAME: &'static str = "hashmap";
+
+    fn empty() -> Self {
+        HashMap::new()
+    }
+    fn from_pairs(pairs: Vec<(K, u64)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn put(&mut self, key: K, value: u64) {
+        self.insert(key, value);
+    }
+    fn lookup(&self, key: &K) -> Option<&u64> {
+        self.get(key)
+    }
+    fn delete(&mut self, key: &K) -> Option<u64> {
+        self.remove(key)
+    }
+    fn upsert(&mut self, key: K) {
+        *self.entry(key).or_insert(0) += 1;
+    }
+    fn sum_values(&self) -> u64 {
+        self.into_iter().map(|(_, v)| v).sum()
+    }
+}
+
+impl<K: Hash + Eq> BenchMap<K> for StdHashMap<K, u64> {
+    const NAME: &'static str = "std";
+
+    fn empty() -> Self {
+        StdHashMap::new()
+    }
+    fn from_pairs(pairs: Vec<(K, u64)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn put(&mut self, key: K, value: u64) {
+        self.insert(key, value);
+    }
+    fn lookup(&self, key: &K) -> Option<&u64> {
+        self.get(key)
+    }
+    fn delete(&mut self, key: &K) -> Option<u64> {
+        self.remove(key)
+    }
+    fn upsert(&mut self, key: K) {
+        *self.entry(key).or_insert(0) += 1;
+    }
+    fn sum_values(&self) -> u64 {
+        self.values().sum()
+    }
+}
+
+impl<K: Ord> BenchMap<K> for BTreeMap<K, u64> {
+    const NAME: &'static str = "btree";
+
+    fn empty() -> Self {
+        BTreeMap::new()
+    }
+    fn from_pairs(pairs: Vec<(K, u64)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn put(&mut self, key: K, value: u64) {
+        self.insert(key, value);
+    }
+    fn lookup(&self, key: &K) -> Option<&u64> {
+        self.get(key)
+    }
+    fn delete(&mut self, key: &K) -> Option<u64> {
+        self.remove(key)
+    }
+    fn upsert(&mut self, key: K) {
+        *self.entry(key).or_insert(0) += 1;
+    }
+    fn sum_values(&self) -> u64 {
+        self.values().sum()
+    }
+}
+
+impl BenchMap<u32> for IntMap<u64> {
+    const NAME: &'static str = "intmap";
+
+    fn empty() -> Self {
+        IntMap::new()
+    }
+    fn from_pairs(pairs: Vec<(u32, u64)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn put(&mut self, key: u32, value: u64) {
+        self.insert(key, value);
+    }
+    fn lookup(&self, key: &u32) -> Option<&u64> {
+        self.get(key)
+    }
+    fn delete(&mut self, key: &u32) -> Option<u64> {
+        self.remove(key)
+    }
+    fn upsert(&mut self, key: u32) {
+        *self.entry(key).or_insert(0) += 1;
+    }
+    fn sum_values(&self) -> u64 {
+        self.iter().map(|(_, v)| v).sum()
+    }
+}
+
+/// The operations compared between `SparseSet` and a `HashMap` used as a set
+trait BenchSet: Sized {
+    const NAME: &'static str;
+
+    fn empty() -> Self;
+    fn add(&mut self, value: u32);
+    fn has(&self, value: &u32) -> bool;
+    fn delete(&mut self, value: &u32) -> bool;
+    fn clear_all(&mut self);
+    fn sum(&self) -> u64;
+}
+
+impl BenchSet for SparseSet {
+    const NAME: &'static str = "sparseset";
+
+    fn empty() -> Self {
+        SparseSet::new()
+    }
+    fn add(&mut self, value: u32) {
+        self.insert(value);
+    }
+    fn has(&self, value: &u32) -> bool {
+        self.contains(value)
+    }
+    fn delete(&mut self, value: &u32) -> bool {
+        self.remove(value)
+    }
+    fn clear_all(&mut self) {
+        self.clear();
+    }
+    fn sum(&self) -> u64 {
+        self.iter().map(|&v| v as u64).sum()
+    }
+}
+
+impl BenchSet for HashMap<u32, ()> {
+    const NAME: &'static str = "hashmap";
+
+    fn empty() -> Self {
+        HashMap::new()
+    }
+    fn add(&mut self, value: u32) {
+        self.insert(value, ());
+    }
+    fn has(&self, value: &u32) -> bool {
+        self.contains_key(value)
+    }
+    fn delete(&mut self, value: &u32) -> bool {
+        self.remove(value).is_some()
+    }
+    fn clear_all(&mut self) {
+        self.retain(|_, _| false);
+    }
+    fn sum(&self) -> u64 {
+        self.iter().map(|(&v, _)| v as u64).sum()
+    }
+}
+
+/// Deterministic key generators, so that every run sees the same keys
+trait BenchKey: Clone + Hash + Ord {
+    const NAME: &'static str;
+
+    fn make(i: u64) -> Self;
+
+    /// Run a map that only takes `u32` keys. Other key types skip it.
+    fn bench_int(_map: &str, _size: usize, _out: &mut Vec<Sample>) {}
+}
+
+// splitmix64, used to scatter sequential indexes
+fn mix(i: u64) -> u64 {
+    let mut z = i.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl BenchKey for u64 {
+    const NAME: &'static str = "int";
+
+    fn make(i: u64) -> Self {
+        mix(i)
+    }
+}
+
+/// Dense small integers, the keys `IntMap` and `SparseSet` are built for
+impl BenchKey for u32 {
+    const NAME: &'static str = "u32";
+
+    fn make(i: u64) -> Self {
+        i as u32
+    }
+
+    fn bench_int(map: &str, size: usize, out: &mut Vec<Sample>) {
+        match map {
+            "intmap" => bench::<u32, IntMap<u64>>(size, out),
+            _ => {
+                bench_set::<SparseSet>(size, out);
+                bench_set::<HashMap<u32, ()>>(size, out);
+            }
+        }
+    }
+}
+
+/// 16 byte identifier-like string
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct Short(String);
+
+impl BenchKey for Short {
+    const NAME: &'static str = "short";
+
+    fn make(i: u64) -> Self {
+        Short(format!("{:016x}", mix(i)))
+    }
+}
+
+/// 128 byte path-like string sharing a long common prefix
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+struct Long(String);
+
+impl BenchKey for Long {
+    const NAME: &'static str = "long";
+
+    fn make(i: u64) -> Self {
+        Long(format!("/srv/data/objects/{:0>110x}", mix(i)))
+    }
+}
+
+struct Sample {
+    map: &'static str,
+    key: &'static str,
+    size: usize,
+    op: &'static str,
+    ns_per_op: f64,
+}
+
+/// Run `round` until `MIN_ROUND_TIME` has passed and return the mean time
+/// per operation. `round` returns how long its timed part took.
+fn measure(ops_per_round: usize, mut round: impl FnMut() -> Duration) -> f64 {
+    let mut total = Duration::ZERO;
+    let mut rounds = 0u32;
+    while total < MIN_ROUND_TIME || rounds == 0 {
+        total += round();
+        rounds += 1;
+    }
+    total.as_nanos() as f64 / (rounds as f64 * ops_per_round.max(1) as f64)
+}
+
+fn timed(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn bench<K: BenchKey, M: BenchMap<K>>(size: usize, out: &mut Vec<Sample>) {
+    let keys: Vec<K> = (0..size as u64).map(K::make).collect();
+    let misses: Vec<K> = (size as u64..2 * size as u64).map(K::make).collect();
+    let pairs = || keys.iter().cloned().zip(0..).collect::<Vec<_>>();
+    let mut record = |op, ns_per_op| {
+        out.push(Sample {
+            map: M::NAME,
+            key: K::NAME,
+            size,
+            op,
+            ns_per_op,
+        })
+    };
+
+    record(
+        "insert",
+        measure(size, || {
+            let input = pairs();
+            let mut map = M::empty();
+            let t = timed(|| input.into_iter().for_each(|(k, v)| map.put(k, v)));
+            black_box(&map);
+            t
+        }),
+    );
+
+    record(
+        "from_iter",
+        measure(size, || {
+            let input = pairs();
+            let mut map = None;
+            let t = timed(|| map = Some(M::from_pairs(input)));
+            black_box(&map);
+            t
+        }),
+    );
+
+    let map = M::from_pairs(pairs());
+    record(
+        "get_hit",
+        measure(size, || {
+            timed(|| keys.iter().for_each(|k| assert!(map.lookup(k).is_some())))
+        }),
+    );
+    record(
+        "get_miss",
+        measure(size, || {
+            timed(|| misses.iter().for_each(|k| assert!(map.lookup(k).is_none())))
+        }),
+    );
+    record(
+        "iterate",
+        measure(size, || {
+            timed(|| {
+                black_box(map.sum_values());
+            })
+        }),
+    );
+    drop(map);
+
+    record(
+        "remove",
+        measure(size, || {
+            let mut map = M::from_pairs(pairs());
+            timed(|| keys.iter().for_each(|k| assert!(map.delete(k).is_some())))
+        }),
+    );
+
+    record(
+        "entry_upsert",
+        measure(size, || {
+            // every key is hit twice: once vacant, once occupied
+            let input: Vec<K> = keys.iter().chain(&keys).cloned().collect();
+            let mut map = M::empty();
+            let t = timed(|| input.into_iter().for_each(|k| map.upsert(k)));
+            black_box(&map);
+            t
+        }) / 2.0,
+    );
+}
+
+fn bench_set<S: BenchSet>(size: usize, out: &mut Vec<Sample>) {
+    let values: Vec<u32> = (0..size as u32).collect();
+    let full = || {
+        let mut set = S::empty();
+        values.iter().for_each(|&v| set.add(v));
+        set
+    };
+    let mut record = |op, ns_per_op| {
+        out.push(Sample {
+            map: S::NAME,
+            key: "u32",
+            size,
+            op,
+            ns_per_op,
+        })
+    };
+
+    record(
+        "set_insert",
+        measure(size, || {
+            let mut set = S::empty();
+            let t = timed(|| values.iter().for_each(|&v| set.add(v)));
+            black_box(&set);
+            t
+        }),
+    );
+    let set = full();
+    record(
+        "set_contains",
+        measure(size, || {
+            timed(|| values.iter().for_each(|v| assert!(set.has(v))))
+        }),
+    );
+    record(
+        "set_iterate",
+        measure(size, || {
+            timed(|| {
+                black_box(set.sum());
+            })
+        }),
+    );
+    drop(set);
+    record(
+        "set_remove",
+        measure(size, || {
+            let mut set = full();
+            timed(|| values.iter().for_each(|v| assert!(set.delete(v))))
+        }),
+    );
+    record(
+        "set_clear",
+        measure(1, || {
+            let mut set = full();
+            timed(|| set.clear_all())
+        }),
+    );
+}
+
+fn bench_maps<K: BenchKey>(maps: &[String], size: usize, out: &mut Vec<Sample>) {
+    for map in maps {
+        match map.as_str() {
+            "hashmap" => bench::<K, HashMap<K, u64>>(size, out),
+            "std" => bench::<K, StdHashMap<K, u64>>(size, out),
+            "btree" => bench::<K, BTreeMap<K, u64>>(size, out),
+            "intmap" | "sparseset" => K::bench_int(map, size, out),
+            other => usage(&format!("unknown map {other}")),
+        }
+    }
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("error: {error}");
+    eprintln!(
+        "usage: compare [--format csv|json] [--max-size N] [--maps hashmap,std,btree,intmap,sparseset] [--keys int,short,long,u32]"
+    );
+    process::exit(2)
+}
+
+fn list(arg: Option<String>) -> Vec<String> {
+    match arg {
+        Some(arg) => arg.split(',').map(str::to_string).collect(),
+        None => usage("missing list"),
+    }
+}
+
+fn write_csv(samples: &[Sample]) {
+    println!("map,key,size,op,ns_per_op");
+    for s in samples {
+        println!("{},{},{},{},{:.2}", s.map, s.key, s.size, s.op, s.ns_per_op);
+    }
+}
+
+fn write_json(samples: &[Sample]) {
+    println!("[");
+    for (i, s) in samples.iter().enumerate() {
+        let comma = if i + 1 == samples.len() { "" } else { "," };
+        println!(
+            "  {{\"map\": \"{}\", \"key\": \"{}\", \"size\": {}, \"op\": \"{}\", \"ns_per_op\": {:.2}}}{comma}",
+            s.map, s.key, s.size, s.op, s.ns_per_op
+        );
+    }
+    println!("]");
+}
+
+fn main() {
+    let mut format = "csv".to_string();
+    let mut max_size = DEFAULT_MAX_SIZE;
+    let mut maps = list(Some("hashmap,std,btree,intmap,sparseset".into()));
+    let mut keys = list(Some("int,short,long,u32".into()));
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = args.next().unwrap_or_else(|| usage("missing format")),
+            "--max-size" => {
+                max_size = match args.next().map(|n| n.parse()) {
+                    Some(Ok(n)) => n,
+                    _ => usage("--max-size takes a number"),
+                }
+            }
+            "--maps" => maps = list(args.next()),
+            "--keys" => keys = list(args.next()),
+            // passed by `cargo bench`
+            "--bench" => {}
+            other => usage(&format!("unknown argument {other}")),
+        }
+    }
+    if format != "csv" && format != "json" {
+        usage("format must be csv or json");
+    }
+
+    let mut samples = Vec::new();
+    for &size in SIZES.iter().filter(|&&s| s <= max_size) {
+        for key in &keys {
+            match key.as_str() {
+                "int" => bench_maps::<u64>(&maps, size, &mut samples),
+                "short" => bench_maps::<Short>(&maps, size, &mut samples),
+                "long" => bench_maps::<Long>(&maps, size, &mut samples),
+                "u32" => bench_maps::<u32>(&maps, size, &mut samples),
+                other => usage(&format!("unknown key type {other}")),
+            }
+            eprintln!("done: {key} keys, size {size}");
+        }
+    }
+
+    match format.as_str() {
+        "json" => write_json(&samples),
+        _ => write_csv(&samples),
+    }
+}
@@ -0,0 +1,41 @@
+//! Animate a growing map: insert keys one at a time and write the layout
+//! as a DOT file after every resize.
+//!
+//! ```text
+//! cargo run --example resize-dot -- [out-dir] [keys]
+//! for f in out-dir/*.dot; do dot -Tpng "$f" -o "${f%.dot}.png"; done
+//! ```
+
+use std::{env, fs, path::PathBuf};
+
+use hashmap::HashMap;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let dir = PathBuf::from(args.next().unwrap_or_else(|| "resize-dot".to_string()));
+    let keys: usize = args
+        .next()
+        .map(|n| n.parse().expect("keys must be a number"))
+        .unwrap_or(40);
+    fs::create_dir_all(&dir).expect("can't create the output directory");
+
+    let mut map = HashMap::new();
+    let mut step = 0;
+    for i in 0..keys {
+        let before = map.bucket_count();
+        map.insert(format!("key{i}"), i);
+        if map.bucket_count() != before {
+            // the frame shows the table right after the insert that grew it
+            let path = dir.join(format!("step-{step:02}.dot"));
+            fs::write(&path, map.to_dot()).expect("can't write the DOT file");
+            println!(
+                "{}: {} buckets after {} inserts",
+                path.display(),
+                map.bucket_count(),
+                map.len()
+            );
+            step += 1;
+        }
+    }
+    print!("\n{}", map.dump_layout());
+}
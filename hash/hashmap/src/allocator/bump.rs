@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    fmt,
+    ptr::{self, NonNull},
+};
+
+use super::{AllocError, Allocator, Global};
+
+const MIN_CHUNK: usize = 4096;
+const CHUNK_ALIGN: usize = 16;
+
+/// An arena that hands out memory by bumping a pointer.
+///
+/// Memory comes from chunks that double in size as the arena fills up.
+/// Freeing is a no-op, except that the most recent allocation can be given
+/// back or grown in place, which keeps a growing `Vec` at the end of the
+/// arena cheap. Everything is released by `reset` or by dropping the arena.
+///
+/// Containers borrow the arena as `&Bump`, so the borrow checker makes
+/// sure none of them outlive a `reset`.
+pub struct Bump {
+    // every chunk with its layout, the current one last
+    chunks: RefCell<Vec<(NonNull<u8>, Layout)>>,
+    // free space in the current chunk
+    ptr: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+}
+
+// SAFETY: the arena owns its chunks, and `&Bump` is not Send because of the
+// cells, so it is only ever used from one thread at a time
+unsafe impl Send for Bump {}
+
+impl Bump {
+    pub fn new() -> Self {
+        Self {
+            chunks: RefCell::new(Vec::new()),
+            ptr: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Bytes obtained from the global allocator, used or not
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.borrow().iter().map(|(_, l)| l.size()).sum()
+    }
+
+    /// Free every allocation at once. The biggest chunk is kept for reuse.
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if let Some(last) = chunks.pop() {
+            for (chunk, layout) in chunks.drain(..) {
+                // SAFETY: allocated by start_chunk with this layout
+                unsafe { Global.deallocate(chunk, layout) };
+            }
+            chunks.push(last);
+            self.ptr.set(last.0.as_ptr());
+            // SAFETY: the end of the chunk
+            self.end.set(unsafe { last.0.as_ptr().add(last.1.size()) });
+        }
+    }
+
+    /// Start a chunk with room for `layout`
+    fn start_chunk(&self, layout: Layout) -> Result<(), AllocError> {
+        let mut chunks = self.chunks.borrow_mut();
+        let last = chunks.last().map_or(0, |(_, l)| l.size());
+        let size = (last * 2)
+            .max(MIN_CHUNK)
+            .max(layout.size() + layout.align());
+        let chunk_layout = Layout::from_size_align(size, CHUNK_ALIGN).map_err(|_| AllocError)?;
+        let chunk = Global.allocate(chunk_layout)?;
+        chunks.push((chunk, chunk_layout));
+        self.ptr.set(chunk.as_ptr());
+        // SAFETY: the end of the chunk
+        self.end.set(unsafe { chunk.as_ptr().add(size) });
+        Ok(())
+    }
+
+    /// Carve `layout` out of the current chunk, if it fits
+    fn bump(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = self.ptr.get();
+        if ptr.is_null() {
+            return None;
+        }
+        let offset = ptr.align_offset(layout.align());
+        let room = self.end.get() as usize - ptr as usize;
+        if offset.checked_add(layout.size())? > room {
+            return None;
+        }
+        // SAFETY: both offsets were just checked to be in the chunk
+        unsafe {
+            let start = ptr.add(offset);
+            self.ptr.set(start.add(layout.size()));
+            Some(NonNull::new_unchecked(start))
+        }
+    }
+
+    /// Whether `ptr` with `layout` is the last thing handed out
+    fn is_last(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        ptr.as_ptr().wrapping_add(layout.size()) == self.ptr.get()
+    }
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bump")
+            .field("chunks", &self.chunks.borrow().len())
+            .field("allocated_bytes", &self.allocated_bytes())
+            .finish()
+    }
+}
+
+unsafe impl Allocator for Bump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        if let Some(ptr) = self.bump(layout) {
+            return Ok(ptr);
+        }
+        self.start_chunk(layout)?;
+        Ok(self.bump(layout).expect("a new chunk has room"))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if self.is_last(ptr, layout) {
+            self.ptr.set(ptr.as_ptr());
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        if self.is_last(ptr, old_layout) {
+            let room = self.end.get() as usize - ptr.as_ptr() as usize;
+            if new_layout.size() <= room {
+                self.ptr.set(ptr.as_ptr().add(new_layout.size()));
+                return Ok(ptr);
+            }
+        }
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), old_layout.size());
+        Ok(new)
+    }
+}
+
+impl Drop for Bump {
+    fn drop(&mut self) {
+        for (chunk, layout) in self.chunks.get_mut().drain(..) {
+            // SAFETY: allocated by start_chunk with this layout
+            unsafe { Global.deallocate(chunk, layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alignment_and_chunks() {
+        let bump = Bump::new();
+        let a = bump.allocate(Layout::new::<u8>()).unwrap();
+        let b = bump.allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(b.as_ptr() as usize % 8, 0);
+        assert!(b.as_ptr() > a.as_ptr());
+        assert_eq!(bump.allocated_bytes(), MIN_CHUNK);
+        // too big for the current chunk
+        let big = Layout::from_size_align(3 * MIN_CHUNK, 64).unwrap();
+        let c = bump.allocate(big).unwrap();
+        assert_eq!(c.as_ptr() as usize % 64, 0);
+        assert_eq!(bump.chunks.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_last_allocation_is_reused() {
+        let bump = Bump::new();
+        let layout = Layout::array::<u32>(4).unwrap();
+        let a = bump.allocate(layout).unwrap();
+        let bigger = Layout::array::<u32>(8).unwrap();
+        // grows in place at the end of the chunk
+        let a = unsafe { bump.grow(a, layout, bigger) }.unwrap();
+        unsafe { bump.deallocate(a, bigger) };
+        assert_eq!(bump.allocate(layout).unwrap(), a);
+    }
+
+    #[test]
+    fn test_reset_keeps_the_biggest_chunk() {
+        let mut bump = Bump::new();
+        for _ in 0..100 {
+            bump.allocate(Layout::new::<[u8; 512]>()).unwrap();
+        }
+        let chunks = bump.chunks.borrow().len();
+        assert!(chunks > 1);
+        let biggest = bump.chunks.borrow().last().unwrap().1.size();
+        bump.reset();
+        assert_eq!(bump.allocated_bytes(), biggest);
+        let first = bump.allocate(Layout::new::<u8>()).unwrap();
+        assert_eq!(first, bump.chunks.borrow()[0].0);
+    }
+}
@@ -0,0 +1,198 @@
+//! Pluggable memory allocation for `HashMap`.
+//!
+//! `Allocator` is a small stand-in for the unstable `core::alloc::Allocator`
+//! trait, so the map can take a custom allocator on stable Rust. `Global`
+//! forwards to the global allocator and is the default. `Bump` is an arena
+//! for request-scoped maps: allocating is a pointer bump, and everything is
+//! freed at once when the arena is reset or dropped.
+//!
+//! The linked lists in `linked-lists/lists` carry a trimmed copy of these
+//! types; its module docs explain where and why the two differ.
+//!
+//! ```
+//! use hashmap::{allocator::Bump, HashMap};
+//!
+//! let mut arena = Bump::new();
+//! for request in 0..3 {
+//!     let mut seen = HashMap::new_in(&arena);
+//!     seen.insert(request, "handled");
+//!     assert_eq!(seen.len(), 1);
+//!     drop(seen);
+//!     // hand the memory of this request back in one go
+//!     arena.reset();
+//! }
+//! ```
+
+use core::{alloc::Layout, error, fmt, ptr::NonNull};
+
+mod bump;
+mod vec;
+
+pub use bump::Bump;
+pub(crate) use vec::AllocVec;
+
+/// Returned when an allocator can't satisfy a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory allocation failed")
+    }
+}
+
+impl error::Error for AllocError {}
+
+/// Returned by the fallible `try_` methods when the map can't grow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested size doesn't fit in `isize::MAX` bytes
+    CapacityOverflow,
+    /// The allocator failed to provide memory of this layout
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(
+                f,
+                "memory allocation failed because the computed capacity exceeded the maximum"
+            ),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "memory allocation of {} bytes failed", layout.size())
+            }
+        }
+    }
+}
+
+impl error::Error for TryReserveError {}
+
+/// A source of memory.
+///
+/// # Safety
+///
+/// Memory returned by `allocate` or `grow` must stay valid, and must not be
+/// handed out again, until it is passed to `deallocate` or `grow`. Clones
+/// of an allocator, and references to it, must be able to free memory the
+/// original allocated, because the map keeps one copy per bucket.
+pub unsafe trait Allocator {
+    /// Allocate memory that fits `layout`. The contents are uninitialized.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    /// Free memory from `allocate` or `grow`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from this allocator with this `layout`, and must
+    /// not be used afterwards.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Move an allocation into a bigger one with the same alignment,
+    /// keeping its contents. The default allocates, copies and frees.
+    ///
+    /// # Safety
+    ///
+    /// As for `deallocate` with `old_layout`, and `new_layout` must be at
+    /// least as big. On error the old allocation is left untouched.
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let new = self.allocate(new_layout)?;
+        core::ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        (**self).grow(ptr, old_layout, new_layout)
+    }
+}
+
+/// The global allocator, which is what `HashMap` uses by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Global;
+
+/// A well-aligned pointer for zero-sized allocations
+fn dangling(layout: Layout) -> NonNull<u8> {
+    // SAFETY: alignments are never zero
+    unsafe { NonNull::new_unchecked(core::ptr::without_provenance_mut(layout.align())) }
+}
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        // SAFETY: the size is not zero
+        NonNull::new(unsafe { alloc::alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            alloc::alloc::dealloc(ptr.as_ptr(), layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        if old_layout.size() == 0 {
+            return self.allocate(new_layout);
+        }
+        let new = alloc::alloc::realloc(ptr.as_ptr(), old_layout, new_layout.size());
+        NonNull::new(new).ok_or(AllocError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMap;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_map_in_arena() {
+        let mut arena = Bump::new();
+        let live = Rc::new(());
+        {
+            let mut map = HashMap::new_in(&arena);
+            for i in 0..1000 {
+                map.insert(i, Rc::clone(&live));
+            }
+            assert_eq!(map.len(), 1000);
+            assert!(map.remove(&7).is_some());
+            map.retain(|k, _| k % 2 == 0);
+            assert_eq!(map.len(), 500);
+            assert_eq!(Rc::strong_count(&live), 501);
+            assert!(arena.allocated_bytes() > 0);
+        }
+        // the map dropped its values even though the arena keeps the memory
+        assert_eq!(Rc::strong_count(&live), 1);
+        arena.reset();
+        let mut map = HashMap::new_in(&arena);
+        map.insert("again", 1);
+        assert_eq!(map.get("again"), Some(&1));
+    }
+}
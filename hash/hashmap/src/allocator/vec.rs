@@ -0,0 +1,407 @@
+use core::{
+    alloc::Layout,
+    fmt,
+    iter::FromIterator,
+    marker::PhantomData,
+    mem, ops,
+    ptr::{self, NonNull},
+    slice,
+};
+
+use super::{Allocator, Global, TryReserveError};
+
+/// The part of `Vec` that `HashMap` needs, allocating through `A`.
+///
+/// Dereferences to a slice for everything that doesn't change the length.
+pub(crate) struct AllocVec<T, A: Allocator = Global> {
+    ptr: NonNull<T>,
+    cap: usize,
+    len: usize,
+    alloc: A,
+    _owns: PhantomData<T>,
+}
+
+// SAFETY: the vector owns its elements, like Vec
+unsafe impl<T: Send, A: Allocator + Send> Send for AllocVec<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for AllocVec<T, A> {}
+
+impl<T, A: Allocator> AllocVec<T, A> {
+    const IS_ZST: bool = mem::size_of::<T>() == 0;
+
+    pub(crate) fn new_in(alloc: A) -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            // zero-sized elements never need memory
+            cap: if Self::IS_ZST { usize::MAX } else { 0 },
+            len: 0,
+            alloc,
+            _owns: PhantomData,
+        }
+    }
+
+    pub(crate) fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let mut vec = Self::new_in(alloc);
+        vec.reserve_exact(capacity);
+        vec
+    }
+
+    pub(crate) fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    fn layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<T>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
+
+    fn grow_to(&mut self, cap: usize) -> Result<(), TryReserveError> {
+        let new_layout = Self::layout(cap)?;
+        let ptr = if self.cap == 0 {
+            self.alloc.allocate(new_layout)
+        } else {
+            // SAFETY: allocated by this allocator with the current layout,
+            // and the new layout is bigger
+            unsafe {
+                let old_layout = Self::layout(self.cap)?;
+                self.alloc.grow(self.ptr.cast(), old_layout, new_layout)
+            }
+        };
+        let ptr = ptr.map_err(|_| TryReserveError::AllocError { layout: new_layout })?;
+        self.ptr = ptr.cast();
+        self.cap = cap;
+        Ok(())
+    }
+
+    /// Make room for `additional` more elements, growing geometrically
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let needed = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if needed <= self.cap {
+            return Ok(());
+        }
+        self.grow_to(needed.max(self.cap * 2).max(4))
+    }
+
+    /// Make room for exactly `additional` more elements
+    pub(crate) fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let needed = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if needed <= self.cap {
+            return Ok(());
+        }
+        self.grow_to(needed)
+    }
+
+    fn handle(result: Result<(), TryReserveError>) {
+        match result {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+
+    pub(crate) fn reserve_exact(&mut self, additional: usize) {
+        Self::handle(self.try_reserve_exact(additional));
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        if self.len == self.cap {
+            Self::handle(self.try_reserve(1));
+        }
+        // SAFETY: there is room for one more
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    pub(crate) fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(index < len, "swap_remove index {index} out of bounds");
+        // SAFETY: both indexes are in bounds, and the last slot is
+        // forgotten before anything can observe it
+        unsafe {
+            let base = self.ptr.as_ptr();
+            let value = ptr::read(base.add(index));
+            ptr::copy(base.add(len - 1), base.add(index), 1);
+            self.len = len - 1;
+            value
+        }
+    }
+
+    /// Keep the elements `keep` returns true for, in order. If `keep` or a
+    /// destructor panics, the elements not visited yet are kept.
+    pub(crate) fn retain_mut<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        /// Closes the gap left by removed elements, even when unwinding
+        struct Guard<'a, T, A: Allocator> {
+            vec: &'a mut AllocVec<T, A>,
+            len: usize,
+            processed: usize,
+            deleted: usize,
+        }
+
+        impl<T, A: Allocator> Drop for Guard<'_, T, A> {
+            fn drop(&mut self) {
+                // SAFETY: the unprocessed tail is still initialized
+                unsafe {
+                    let base = self.vec.ptr.as_ptr();
+                    ptr::copy(
+                        base.add(self.processed),
+                        base.add(self.processed - self.deleted),
+                        self.len - self.processed,
+                    );
+                }
+                self.vec.len = self.len - self.deleted;
+            }
+        }
+
+        let len = self.len;
+        // until the guard runs, nothing past 0 counts as initialized
+        self.len = 0;
+        let mut g = Guard {
+            vec: self,
+            len,
+            processed: 0,
+            deleted: 0,
+        };
+        while g.processed < g.len {
+            // SAFETY: processed is in bounds and not yet moved or dropped
+            unsafe {
+                let base = g.vec.ptr.as_ptr();
+                let cur = base.add(g.processed);
+                if !keep(&mut *cur) {
+                    // count it first so a panicking drop isn't dropped again
+                    g.processed += 1;
+                    g.deleted += 1;
+                    ptr::drop_in_place(cur);
+                    continue;
+                }
+                if g.deleted > 0 {
+                    ptr::copy_nonoverlapping(cur, base.add(g.processed - g.deleted), 1);
+                }
+                g.processed += 1;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let elems: *mut [T] = &mut **self;
+        self.len = 0;
+        // SAFETY: the elements are no longer reachable through self
+        unsafe { ptr::drop_in_place(elems) };
+    }
+}
+
+impl<T, A: Allocator> Drop for AllocVec<T, A> {
+    fn drop(&mut self) {
+        /// Frees the buffer even if an element's destructor panics
+        struct Free<'a, T, A: Allocator>(&'a mut AllocVec<T, A>);
+
+        impl<T, A: Allocator> Drop for Free<'_, T, A> {
+            fn drop(&mut self) {
+                let vec = &mut *self.0;
+                if !AllocVec::<T, A>::IS_ZST && vec.cap > 0 {
+                    // SAFETY: allocated with this layout, which was valid then
+                    unsafe {
+                        let layout = Layout::array::<T>(vec.cap).unwrap_unchecked();
+                        vec.alloc.deallocate(vec.ptr.cast(), layout);
+                    }
+                }
+            }
+        }
+
+        Free(self).0.clear();
+    }
+}
+
+impl<T, A: Allocator> ops::Deref for AllocVec<T, A> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the first len elements are initialized
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T, A: Allocator> ops::DerefMut for AllocVec<T, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: as in deref
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T, A: Allocator + Default> Default for AllocVec<T, A> {
+    fn default() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a AllocVec<T, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, A: Allocator> IntoIterator for &'a mut AllocVec<T, A> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Clone for AllocVec<T, A> {
+    fn clone(&self) -> Self {
+        let mut vec = Self::with_capacity_in(self.len, self.alloc.clone());
+        for value in self.iter() {
+            vec.push(value.clone());
+        }
+        vec
+    }
+}
+
+impl<T: PartialEq, A: Allocator> PartialEq for AllocVec<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: fmt::Debug, A: Allocator> fmt::Debug for AllocVec<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T> FromIterator<T> for AllocVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut vec = Self::with_capacity_in(iter.size_hint().0, Global);
+        for value in iter {
+            vec.push(value);
+        }
+        vec
+    }
+}
+
+/// Owned iterator, which frees the buffer when dropped
+pub(crate) struct IntoIter<T, A: Allocator> {
+    // the emptied vector still owns the buffer
+    buf: AllocVec<T, A>,
+    next: usize,
+    end: usize,
+}
+
+impl<T, A: Allocator> IntoIterator for AllocVec<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(mut self) -> IntoIter<T, A> {
+        let end = mem::replace(&mut self.len, 0);
+        IntoIter {
+            buf: self,
+            next: 0,
+            end,
+        }
+    }
+}
+
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.next == self.end {
+            return None;
+        }
+        // SAFETY: elements between next and end are still initialized
+        let value = unsafe { ptr::read(self.buf.ptr.as_ptr().add(self.next)) };
+        self.next += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.next;
+        (n, Some(n))
+    }
+}
+
+impl<T, A: Allocator> Drop for IntoIter<T, A> {
+    fn drop(&mut self) {
+        let rest: *mut [T] = ptr::slice_from_raw_parts_mut(
+            // SAFETY: next is within the buffer
+            unsafe { self.buf.ptr.as_ptr().add(self.next) },
+            self.end - self.next,
+        );
+        self.next = self.end;
+        // SAFETY: the rest was never read out; buf then frees the memory
+        unsafe { ptr::drop_in_place(rest) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{rc::Rc, vec::Vec};
+
+    #[test]
+    fn test_push_and_remove() {
+        let mut vec = AllocVec::new_in(Global);
+        for i in 0..100 {
+            vec.push(i);
+        }
+        assert_eq!(vec.len(), 100);
+        assert_eq!(vec.swap_remove(0), 0);
+        assert_eq!(vec[0], 99);
+        vec.retain_mut(|x| *x % 2 == 0);
+        assert_eq!(vec.len(), 49);
+        assert!(vec.iter().all(|x| x % 2 == 0));
+        let back: Vec<i32> = vec.clone().into_iter().collect();
+        assert_eq!(back[..], vec[..]);
+    }
+
+    #[test]
+    fn test_zero_sized() {
+        let mut vec = AllocVec::new_in(Global);
+        for _ in 0..10 {
+            vec.push(());
+        }
+        vec.swap_remove(3);
+        assert_eq!(vec.into_iter().count(), 9);
+    }
+
+    #[test]
+    fn test_drops() {
+        let counter = Rc::new(());
+        let mut vec: AllocVec<_> = (0..10).map(|_| Rc::clone(&counter)).collect();
+        vec.retain_mut(|_| false);
+        assert_eq!(Rc::strong_count(&counter), 1);
+        for _ in 0..10 {
+            vec.push(Rc::clone(&counter));
+        }
+        let mut iter = vec.into_iter();
+        iter.next();
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_try_reserve_overflow() {
+        let mut vec = AllocVec::<u64>::new_in(Global);
+        assert_eq!(
+            vec.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        vec.push(1);
+        assert_eq!(
+            vec.try_reserve_exact(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Comparing and merging maps.
+//!
+//! `diff` lists the changes that turn one map into another, `apply_diff`
+//! replays such a list, and `merge3` combines two maps that were both
+//! derived from a common base, the way a version control system merges
+//! two branches.
+
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+
+use crate::{allocator::Allocator, HashMap};
+
+/// One difference between two maps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// the key is only in the new map
+    Added(K, V),
+    /// the key is only in the old map
+    Removed(K, V),
+    /// the key is in both maps with different values
+    Changed { key: K, old: V, new: V },
+}
+
+impl<K: Clone, V: Clone> Change<&K, &V> {
+    /// Copy the borrowed key and values so the change can be applied
+    pub fn cloned(self) -> Change<K, V> {
+        match self {
+            Change::Added(k, v) => Change::Added(k.clone(), v.clone()),
+            Change::Removed(k, v) => Change::Removed(k.clone(), v.clone()),
+            Change::Changed { key, old, new } => Change::Changed {
+                key: key.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            },
+        }
+    }
+}
+
+/// A key that both sides of a `merge3` changed in different ways. `None`
+/// means the key is absent from that map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict<'a, K, V> {
+    pub key: &'a K,
+    pub base: Option<&'a V>,
+    pub ours: Option<&'a V>,
+    pub theirs: Option<&'a V>,
+}
+
+/// What a `merge3` resolver decided for a conflicting key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution<V> {
+    /// store this value under the key
+    Value(V),
+    /// leave the key out of the merged map
+    Remove,
+    /// keep our side and report the conflict to the caller
+    Unresolved,
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Hash + PartialEq,
+    V: PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    /// Iterate over the changes that turn `self` into `other`. Additions
+    /// and changes come first, in the bucket order of `other`, followed by
+    /// removals in the bucket order of `self`.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = Change<&'a K, &'a V>> {
+        let added_or_changed = other
+            .iter()
+            .filter_map(move |(key, new)| match self.get(key) {
+                None => Some(Change::Added(key, new)),
+                Some(old) if old != new => Some(Change::Changed { key, old, new }),
+                Some(_) => None,
+            });
+        let removed = self
+            .iter()
+            .filter(move |(key, _)| !other.contains_key(*key))
+            .map(|(key, value)| Change::Removed(key, value));
+        added_or_changed.chain(removed)
+    }
+
+    /// Apply changes produced by `diff`. Applying `a.diff(&b)` to `a`
+    /// leaves it equal to `b`. The old values in the changes are not
+    /// checked against the map.
+    pub fn apply_diff<I>(&mut self, changes: I)
+    where
+        I: IntoIterator<Item = Change<K, V>>,
+    {
+        for change in changes {
+            match change {
+                Change::Added(key, value)
+                | Change::Changed {
+                    key, new: value, ..
+                } => {
+                    self.insert(key, value);
+                }
+                Change::Removed(key, _) => {
+                    self.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Hash + PartialEq + Clone,
+    V: PartialEq + Clone,
+    S: BuildHasher + Clone,
+    A: Allocator + Clone,
+{
+    /// Three-way merge of `ours` and `theirs`, which were both derived from
+    /// `base`.
+    ///
+    /// A key changed on only one side takes that side's value, and a key
+    /// changed the same way on both sides is taken as is. Otherwise
+    /// `resolve` is called with the conflict. Conflicts it leaves
+    /// `Unresolved` keep our value in the merged map and are returned
+    /// alongside it.
+    ///
+    /// The merged map uses the hasher and allocator of `ours`.
+    pub fn merge3<'a, F>(
+        base: &'a Self,
+        ours: &'a Self,
+        theirs: &'a Self,
+        mut resolve: F,
+    ) -> (Self, Vec<Conflict<'a, K, V>>)
+    where
+        F: FnMut(&Conflict<'a, K, V>) -> Resolution<V>,
+    {
+        let mut merged = HashMap::with_hasher_in(ours.hasher().clone(), ours.allocator().clone());
+        let mut conflicts = Vec::new();
+        // keys missing from both sides were either never there or removed
+        // by both, so they never make it into the merged map
+        let keys = ours.iter().map(|(k, _)| k).chain(
+            theirs
+                .iter()
+                .map(|(k, _)| k)
+                .filter(|k| !ours.contains_key(*k)),
+        );
+        for key in keys {
+            let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+            let value = if o == t || t == b {
+                o
+            } else if o == b {
+                t
+            } else {
+                let conflict = Conflict {
+                    key,
+                    base: b,
+                    ours: o,
+                    theirs: t,
+                };
+                match resolve(&conflict) {
+                    Resolution::Value(value) => {
+                        merged.insert(key.clone(), value);
+                        continue;
+                    }
+                    Resolution::Remove => None,
+                    Resolution::Unresolved => {
+                        conflicts.push(conflict);
+                        o
+                    }
+                }
+            };
+            if let Some(value) = value {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        (merged, conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{allocator::Bump, hashers::BuildFnvHasher};
+
+    fn map(pairs: &[(&'static str, i32)]) -> HashMap<&'static str, i32> {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = map(&[("a", 1), ("b", 2), ("c", 3)]);
+        let new = map(&[("a", 1), ("b", 20), ("d", 4)]);
+        let changes: Vec<_> = old.diff(&new).collect();
+        assert_eq!(changes.len(), 3);
+        assert!(changes.contains(&Change::Added(&"d", &4)));
+        assert!(changes.contains(&Change::Removed(&"c", &3)));
+        assert!(changes.contains(&Change::Changed {
+            key: &"b",
+            old: &2,
+            new: &20
+        }));
+        assert_eq!(old.diff(&old).count(), 0);
+    }
+
+    #[test]
+    fn test_apply_diff() {
+        let mut old = map(&[("a", 1), ("b", 2), ("c", 3)]);
+        let new = map(&[("b", 20), ("d", 4)]);
+        let changes: Vec<_> = old.diff(&new).map(Change::cloned).collect();
+        old.apply_diff(changes);
+        assert_eq!(old.diff(&new).count(), 0);
+        assert_eq!(old.len(), 2);
+    }
+
+    #[test]
+    fn test_merge3_clean() {
+        let base = map(&[("keep", 0), ("ours", 0), ("theirs", 0), ("both", 0)]);
+        let ours = map(&[
+            ("keep", 0),
+            ("ours", 1),
+            ("theirs", 0),
+            ("both", 5),
+            ("new", 7),
+        ]);
+        let theirs = map(&[("keep", 0), ("ours", 0), ("both", 5)]);
+        let (merged, conflicts) =
+            HashMap::merge3(&base, &ours, &theirs, |_| panic!("no conflicts expected"));
+        assert!(conflicts.is_empty());
+        let mut entries: Vec<_> = merged.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+        assert_eq!(entries, [("both", 5), ("keep", 0), ("new", 7), ("ours", 1)]);
+    }
+
+    #[test]
+    fn test_custom_hasher_and_allocator() {
+        let arena = Bump::new();
+        let map = |pairs: &[(&'static str, i32)]| {
+            let mut map = HashMap::with_hasher_in(BuildFnvHasher::default(), &arena);
+            for &(k, v) in pairs {
+                map.insert(k, v);
+            }
+            map
+        };
+        let base = map(&[("a", 1), ("b", 2)]);
+        let ours = map(&[("a", 10), ("b", 2)]);
+        let theirs = map(&[("a", 1), ("c", 3)]);
+        assert_eq!(base.diff(&ours).count(), 1);
+        let (merged, conflicts) =
+            HashMap::merge3(&base, &ours, &theirs, |_| panic!("no conflicts expected"));
+        assert!(conflicts.is_empty());
+        assert!(core::ptr::eq(*merged.allocator(), &arena));
+        let mut entries: Vec<_> = merged.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+        assert_eq!(entries, [("a", 10), ("c", 3)]);
+    }
+
+    #[test]
+    fn test_merge3_conflicts() {
+        let base = map(&[("x", 0), ("y", 0), ("z", 0)]);
+        let ours = map(&[("x", 1), ("y", 1), ("z", 1)]);
+        let theirs = map(&[("x", 2), ("y", 2)]);
+        let (merged, conflicts) = HashMap::merge3(&base, &ours, &theirs, |c| match *c.key {
+            "x" => Resolution::Value(c.ours.unwrap() + c.theirs.unwrap()),
+            "z" => Resolution::Remove,
+            _ => Resolution::Unresolved,
+        });
+        assert_eq!(merged.get("x"), Some(&3));
+        assert_eq!(merged.get("y"), Some(&1));
+        assert_eq!(merged.get("z"), None);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, &"y");
+        assert_eq!(conflicts[0].base, Some(&0));
+        assert_eq!(conflicts[0].theirs, Some(&2));
+    }
+}
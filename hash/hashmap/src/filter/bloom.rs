@@ -0,0 +1,235 @@
+use std::{f64::consts::LN_2, hash::Hash, marker::PhantomData};
+
+use super::{FilterError, Reader};
+use crate::hashers::{make_fnv_hash, make_hash};
+
+const MAGIC: &[u8; 4] = b"BLM1";
+
+/// Bloom filter over values of type `T`.
+///
+/// Each value sets `hashes` bits chosen by double hashing: the i-th bit is
+/// `h1 + i * h2` where `h1` is SipHash and `h2` is FNV-1a.
+pub struct BloomFilter<T: ?Sized> {
+    words: Vec<u64>,
+    nbits: u64,
+    hashes: u32,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized> Clone for BloomFilter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            words: self.words.clone(),
+            nbits: self.nbits,
+            hashes: self.hashes,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Hash + ?Sized> BloomFilter<T> {
+    /// Size the filter for `expected` items at a false-positive rate of
+    /// `fp_rate`, using the usual optimum
+    /// `m = -n ln p / (ln 2)^2` bits and `k = m / n ln 2` hashes.
+    pub fn new(expected: usize, fp_rate: f64) -> Self {
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "false-positive rate must be in (0, 1)"
+        );
+        let n = expected.max(1) as f64;
+        let nbits = (-n * fp_rate.ln() / (LN_2 * LN_2)).ceil() as u64;
+        let hashes = ((nbits as f64 / n) * LN_2).round().max(1.0) as u32;
+        Self::with_params(nbits, hashes)
+    }
+
+    /// Build a filter with an explicit number of bits and hash functions
+    pub fn with_params(nbits: u64, hashes: u32) -> Self {
+        let nbits = nbits.max(1);
+        Self {
+            words: vec![0; nbits.div_ceil(64) as usize],
+            nbits,
+            hashes: hashes.max(1),
+            _marker: PhantomData,
+        }
+    }
+
+    fn bit_indexes(&self, item: &T) -> impl Iterator<Item = u64> {
+        let h1 = make_hash(item);
+        // force h2 odd so that the sequence does not collapse when it
+        // shares a factor with nbits
+        let h2 = make_fnv_hash(item) | 1;
+        let nbits = self.nbits;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % nbits)
+    }
+
+    pub fn insert(&mut self, item: &T) {
+        for bit in self.bit_indexes(item) {
+            self.words[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if `item` was never inserted. True means it probably was.
+    pub fn contains(&self, item: &T) -> bool {
+        self.bit_indexes(item)
+            .all(|bit| self.words[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl<T: ?Sized> BloomFilter<T> {
+    /// return the number of bits in the filter
+    pub fn bits(&self) -> u64 {
+        self.nbits
+    }
+
+    /// return the number of bits set per item
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Expected false-positive rate given how many bits are set now
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let set: u64 = self.words.iter().map(|w| w.count_ones() as u64).sum();
+        (set as f64 / self.nbits as f64).powi(self.hashes as i32)
+    }
+
+    fn check_compatible(&self, other: &Self) -> Result<(), FilterError> {
+        match self.nbits == other.nbits && self.hashes == other.hashes {
+            true => Ok(()),
+            false => Err(FilterError::Incompatible),
+        }
+    }
+
+    /// Make this filter match everything that either filter matches
+    pub fn union(&mut self, other: &Self) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+        Ok(())
+    }
+
+    /// Make this filter match only what both filters match. The result
+    /// can have a higher false-positive rate than a filter built from the
+    /// intersection of the two sets directly.
+    pub fn intersection(&mut self, other: &Self) -> Result<(), FilterError> {
+        self.check_compatible(other)?;
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a &= b;
+        }
+        Ok(())
+    }
+
+    /// Encode as `"BLM1"`, the bit count and hash count, then the bit
+    /// words, all little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.words.len() * 8);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.nbits.to_le_bytes());
+        bytes.extend_from_slice(&self.hashes.to_le_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FilterError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let nbits = reader.u64()?;
+        let hashes = reader.u32()?;
+        if nbits == 0 || hashes == 0 {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let nwords =
+            usize::try_from(nbits.div_ceil(64)).map_err(|_| FilterError::InvalidEncoding)?;
+        if nwords.checked_mul(8) != Some(reader.bytes.len()) {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let words = (0..nwords)
+            .map(|_| reader.u64())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Ok(Self {
+            words,
+            nbits,
+            hashes,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            filter.insert(&i);
+        }
+        assert!((0..1000u32).all(|i| filter.contains(&i)));
+    }
+
+    #[test]
+    fn test_empirical_fp_rate() {
+        for &target in &[0.1, 0.01, 0.001] {
+            let mut filter = BloomFilter::new(10_000, target);
+            for i in 0..10_000u64 {
+                filter.insert(&i);
+            }
+            let trials = 100_000u64;
+            let hits = (10_000..10_000 + trials)
+                .filter(|i| filter.contains(i))
+                .count();
+            let rate = hits as f64 / trials as f64;
+            assert!(rate < target * 1.5, "target {target}, measured {rate}");
+        }
+    }
+
+    #[test]
+    fn test_union_intersection() {
+        let mut a = BloomFilter::new(100, 0.01);
+        let mut b = BloomFilter::new(100, 0.01);
+        a.insert("foo");
+        a.insert("bar");
+        b.insert("bar");
+        b.insert("baz");
+        let mut both = a.clone();
+        both.intersection(&b).unwrap();
+        assert!(both.contains("bar"));
+        assert!(!both.contains("foo") || !both.contains("baz"));
+        a.union(&b).unwrap();
+        assert!(["foo", "bar", "baz"].iter().all(|s| a.contains(s)));
+
+        let other = BloomFilter::new(1000, 0.01);
+        assert_eq!(a.union(&other), Err(FilterError::Incompatible));
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut filter = BloomFilter::new(500, 0.05);
+        for i in 0..500u32 {
+            filter.insert(&i);
+        }
+        let bytes = filter.to_bytes();
+        let decoded = BloomFilter::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.bits(), filter.bits());
+        assert_eq!(decoded.hashes(), filter.hashes());
+        assert!((0..500u32).all(|i| decoded.contains(&i)));
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert!(BloomFilter::<u32>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(BloomFilter::<u32>::from_bytes(b"nope").is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use alloc::{vec, vec::Vec};
+use core::{hash::Hash, marker::PhantomData, mem};
+
+use super::{FilterError, Reader};
+use crate::hashers::{make_fnv_hash, make_hash};
+
+const MAGIC: &[u8; 4] = b"CKO1";
+const BUCKET_SIZE: usize = 4;
+const MAX_KICKS: usize = 500;
+// fingerprint 0 marks an empty slot
+const EMPTY: u16 = 0;
+
+/// Cuckoo filter over values of type `T`.
+///
+/// Stores a 16-bit fingerprint of every value in one of two candidate
+/// buckets. Unlike a Bloom filter it supports `remove`, as long as only
+/// values that were inserted are removed.
+pub struct CuckooFilter<T: ?Sized> {
+    buckets: Vec<[u16; BUCKET_SIZE]>,
+    // a fingerprint evicted by the last failed insert, kept so that it
+    // does not turn into a false negative
+    victim: Option<(usize, u16)>,
+    items: usize,
+    rng: u64,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> CuckooFilter<T> {
+    /// Size the filter to hold `capacity` items at 95% occupancy
+    pub fn new(capacity: usize) -> Self {
+        let nbuckets = (capacity.max(1) * 100)
+            .div_ceil(BUCKET_SIZE * 95)
+            .next_power_of_two();
+        Self {
+            buckets: vec![[EMPTY; BUCKET_SIZE]; nbuckets],
+            victim: None,
+            items: 0,
+            rng: 0x2545_f491_4f6c_dd1d,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Fingerprint and primary bucket of `item`
+    fn locate(&self, item: &T) -> (u16, usize) {
+        let fp = (make_fnv_hash(item) % u16::MAX as u64) as u16 + 1;
+        (fp, make_hash(item) as usize & self.mask())
+    }
+
+    /// Insert `item`. Fails with `FilterError::Full` once the filter can
+    /// no longer make room by relocating fingerprints.
+    pub fn insert(&mut self, item: &T) -> Result<(), FilterError> {
+        if self.victim.is_some() {
+            return Err(FilterError::Full);
+        }
+        let (mut fp, i1) = self.locate(item);
+        let i2 = self.alt_index(i1, fp);
+        self.items += 1;
+        if self.put(i1, fp) || self.put(i2, fp) {
+            return Ok(());
+        }
+        let mut idx = if self.next_random() & 1 == 0 { i1 } else { i2 };
+        for _ in 0..MAX_KICKS {
+            let slot = self.next_random() as usize % BUCKET_SIZE;
+            mem::swap(&mut fp, &mut self.buckets[idx][slot]);
+            idx = self.alt_index(idx, fp);
+            if self.put(idx, fp) {
+                return Ok(());
+            }
+        }
+        // The item itself is in the table by now; it is the last evicted
+        // fingerprint that has nowhere to go.
+        self.victim = Some((idx, fp));
+        Ok(())
+    }
+
+    pub fn contains(&self, item: &T) -> bool {
+        let (fp, i1) = self.locate(item);
+        let i2 = self.alt_index(i1, fp);
+        let in_victim = matches!(self.victim, Some((i, v)) if v == fp && (i == i1 || i == i2));
+        in_victim || self.buckets[i1].contains(&fp) || self.buckets[i2].contains(&fp)
+    }
+
+    /// Remove one copy of `item`'s fingerprint. Removing a value that was
+    /// never inserted can remove another value that shares its fingerprint.
+    pub fn remove(&mut self, item: &T) -> bool {
+        let (fp, i1) = self.locate(item);
+        let i2 = self.alt_index(i1, fp);
+        if let Some((i, v)) = self.victim {
+            if v == fp && (i == i1 || i == i2) {
+                self.victim = None;
+                self.items -= 1;
+                return true;
+            }
+        }
+        for idx in [i1, i2] {
+            if let Some(slot) = self.buckets[idx].iter_mut().find(|s| **s == fp) {
+                *slot = EMPTY;
+                self.items -= 1;
+                // the freed slot may have room for the stashed victim
+                if let Some((i, v)) = self.victim.take() {
+                    let alt = self.alt_index(i, v);
+                    if !self.put(i, v) && !self.put(alt, v) {
+                        self.victim = Some((i, v));
+                    }
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl<T: ?Sized> CuckooFilter<T> {
+    fn mask(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    /// The other bucket for a fingerprint at `idx`. XOR makes the mapping
+    /// its own inverse, so either bucket leads back to the other.
+    fn alt_index(&self, idx: usize, fp: u16) -> usize {
+        (idx ^ make_fnv_hash(&fp) as usize) & self.mask()
+    }
+
+    fn put(&mut self, idx: usize, fp: u16) -> bool {
+        match self.buckets[idx].iter_mut().find(|s| **s == EMPTY) {
+            Some(slot) => {
+                *slot = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // xorshift64, only used to pick which fingerprint to kick out
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    /// return the number of items in the filter
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    /// return true if the filter is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// return the number of fingerprints the filter has room for
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Encode as `"CKO1"`, the bucket count, item count and victim, then
+    /// the fingerprints, all little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.capacity() * 2);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(self.buckets.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.items as u64).to_le_bytes());
+        match self.victim {
+            Some((idx, fp)) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(idx as u64).to_le_bytes());
+                bytes.extend_from_slice(&fp.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        for fp in self.buckets.iter().flatten() {
+            bytes.extend_from_slice(&fp.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FilterError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let nbuckets = reader.usize()?;
+        if !nbuckets.is_power_of_two() {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let items = reader.usize()?;
+        let victim = match reader.u8()? {
+            0 => None,
+            1 => Some((reader.usize()?, reader.u16()?)),
+            _ => return Err(FilterError::InvalidEncoding),
+        };
+        // the bucket count is untrusted, so check it against the payload
+        // before allocating anything
+        let len = nbuckets
+            .checked_mul(BUCKET_SIZE * 2)
+            .ok_or(FilterError::InvalidEncoding)?;
+        if reader.bytes.len() != len {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let mut buckets = vec![[EMPTY; BUCKET_SIZE]; nbuckets];
+        for slot in buckets.iter_mut().flatten() {
+            *slot = reader.u16()?;
+        }
+        reader.finish()?;
+        if matches!(victim, Some((idx, _)) if idx >= nbuckets) {
+            return Err(FilterError::InvalidEncoding);
+        }
+        Ok(Self {
+            buckets,
+            victim,
+            items,
+            rng: 0x2545_f491_4f6c_dd1d,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut filter = CuckooFilter::new(100);
+        filter.insert("foo").unwrap();
+        filter.insert("bar").unwrap();
+        assert!(filter.contains("foo"));
+        assert!(filter.contains("bar"));
+        assert_eq!(filter.len(), 2);
+        assert!(filter.remove("foo"));
+        assert!(!filter.contains("foo"));
+        assert!(filter.contains("bar"));
+        assert!(!filter.remove("foo"));
+        assert_eq!(filter.len(), 1);
+    }
+
+    #[test]
+    fn test_fill_to_capacity() {
+        let mut filter = CuckooFilter::new(10_000);
+        let mut inserted = 0u32;
+        while filter.insert(&inserted).is_ok() {
+            inserted += 1;
+        }
+        // cuckoo filters with 4-slot buckets reach ~95% occupancy
+        assert!(inserted as usize >= filter.capacity() * 9 / 10);
+        assert!((0..inserted).all(|i| filter.contains(&i)));
+        for i in 0..inserted {
+            assert!(filter.remove(&i));
+        }
+        assert!(filter.is_empty());
+        assert!(filter.insert(&0).is_ok());
+    }
+
+    #[test]
+    fn test_empirical_fp_rate() {
+        let mut filter = CuckooFilter::new(10_000);
+        for i in 0..10_000u64 {
+            filter.insert(&i).unwrap();
+        }
+        let trials = 100_000u64;
+        let hits = (10_000..10_000 + trials)
+            .filter(|i| filter.contains(i))
+            .count();
+        let rate = hits as f64 / trials as f64;
+        // 2 buckets * 4 slots / 2^16 fingerprints bounds the rate at ~0.012%
+        assert!(rate < 0.0005, "measured {rate}");
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut filter = CuckooFilter::new(1000);
+        for i in 0..1000u32 {
+            filter.insert(&i).unwrap();
+        }
+        let bytes = filter.to_bytes();
+        let mut decoded = CuckooFilter::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 1000);
+        assert!((0..1000u32).all(|i| decoded.contains(&i)));
+        assert!(decoded.remove(&7));
+        assert!(!decoded.contains(&7));
+
+        assert!(CuckooFilter::<u32>::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_huge_bucket_count_is_rejected() {
+        // nbuckets * BUCKET_SIZE * 2 is 2^65 for the first one, and wraps
+        // to 0 bytes of payload if the multiplication isn't checked
+        for nbuckets in [1u64 << 62, 1 << 63] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&nbuckets.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+            bytes.push(0);
+            assert_eq!(
+                CuckooFilter::<u32>::from_bytes(&bytes).err(),
+                Some(FilterError::InvalidEncoding)
+            );
+        }
+    }
+}
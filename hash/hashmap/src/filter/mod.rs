@@ -0,0 +1,84 @@
+//! Probabilistic membership filters.
+//!
+//! Both filters answer "definitely absent" or "probably present". A
+//! negative answer can be trusted, which makes them a cheap guard in front
+//! of an expensive lookup.
+
+use core::{error, fmt};
+
+#[cfg(feature = "std")]
+mod bloom;
+mod cuckoo;
+
+#[cfg(feature = "std")]
+pub use bloom::BloomFilter;
+pub use cuckoo::CuckooFilter;
+
+/// Errors reported by the filters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    /// The two filters were built with different sizes or hash counts
+    Incompatible,
+    /// The cuckoo filter could not find room for another fingerprint
+    Full,
+    /// The bytes passed to `from_bytes` are not a filter of this kind
+    InvalidEncoding,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Incompatible => write!(f, "filters have different parameters"),
+            FilterError::Full => write!(f, "filter is full"),
+            FilterError::InvalidEncoding => write!(f, "invalid filter encoding"),
+        }
+    }
+}
+
+impl error::Error for FilterError {}
+
+/// Cursor over a byte slice used by the `from_bytes` decoders
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], FilterError> {
+        if self.bytes.len() < n {
+            return Err(FilterError::InvalidEncoding);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, FilterError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FilterError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    // only the Bloom filter has u32 fields
+    #[cfg(feature = "std")]
+    fn u32(&mut self) -> Result<u32, FilterError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, FilterError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A u64 length or index, which must fit in a usize on this target
+    fn usize(&mut self) -> Result<usize, FilterError> {
+        usize::try_from(self.u64()?).map_err(|_| FilterError::InvalidEncoding)
+    }
+
+    fn finish(self) -> Result<(), FilterError> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(FilterError::InvalidEncoding),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Hash functions shared by the maps, filters and sketches in this crate.
+//!
+//! Structures that need two independent hashes of the same value (double
+//! hashing in the Bloom filter, fingerprints in the cuckoo filter) take one
+//! from `make_hash`, which is SipHash-1-3, and the other from `FnvHasher`.
+//!
+//! Both are implemented here rather than taken from std, so hashes are the
+//! same with and without the `std` feature.
+
+use core::hash::{BuildHasherDefault, Hash, Hasher};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a. Much cheaper than SipHash for short keys, but offers no
+/// protection against keys chosen to collide.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub type BuildFnvHasher = BuildHasherDefault<FnvHasher>;
+
+/// SipHash with `C` rounds per message word and `D` finalization rounds.
+///
+/// `new()` uses all-zero keys, which give no protection against keys
+/// chosen to collide; use `new_with_keys` with secret random keys when
+/// that matters.
+#[derive(Clone, Debug)]
+pub struct SipHasher<const C: usize, const D: usize> {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    // bytes not yet compressed, little-endian in the low bits
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+pub type SipHasher13 = SipHasher<1, 3>;
+pub type SipHasher24 = SipHasher<2, 4>;
+
+/// Builds the hasher `HashMap` uses unless it is given another one
+pub type DefaultHashBuilder = BuildHasherDefault<SipHasher13>;
+
+/// Read up to 8 bytes as a little-endian integer
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64)
+}
+
+impl<const C: usize, const D: usize> SipHasher<C, D> {
+    pub fn new() -> Self {
+        Self::new_with_keys(0, 0)
+    }
+
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        for _ in 0..C {
+            self.round();
+        }
+        self.v0 ^= word;
+    }
+}
+
+impl<const C: usize, const D: usize> Default for SipHasher<C, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const C: usize, const D: usize> Hasher for SipHasher<C, D> {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len();
+        if self.ntail > 0 {
+            let fill = (8 - self.ntail).min(bytes.len());
+            self.tail |= read_le(&bytes[..fill]) << (8 * self.ntail);
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+            if self.ntail < 8 {
+                return;
+            }
+            self.compress(self.tail);
+            self.ntail = 0;
+        }
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.compress(read_le(word));
+        }
+        self.tail = read_le(words.remainder());
+        self.ntail = words.remainder().len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        // the last word carries the message length in its top byte
+        state.compress((self.length as u64) << 56 | self.tail);
+        state.v2 ^= 0xff;
+        for _ in 0..D {
+            state.round();
+        }
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// Hash `value` with a fresh hasher of type `H`
+pub fn hash_with<H, T>(value: &T) -> u64
+where
+    H: Hasher + Default,
+    T: Hash + ?Sized,
+{
+    let mut hasher = H::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The hash `HashMap` uses to pick a bucket
+pub fn make_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    hash_with::<SipHasher13, T>(value)
+}
+
+/// Hash of `value` that is independent of `make_hash`
+pub fn make_fnv_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    hash_with::<FnvHasher, T>(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_fnv_reference_values() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = FnvHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_siphash_reference_values() {
+        // from the SipHash paper: key 00..0f, messages 00, 00 01, ...
+        let hash = |len: u8| {
+            let mut hasher =
+                SipHasher24::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+            hasher.write(&(0..len).collect::<Vec<_>>());
+            hasher.finish()
+        };
+        assert_eq!(hash(0), 0x726f_db47_dd0e_0e31);
+        assert_eq!(hash(15), 0xa129_ca61_49be_45e5);
+    }
+
+    #[test]
+    fn test_siphash13_reference_values() {
+        // the reference implementation with 1 and 3 rounds, same key and
+        // messages as above
+        let hash = |len: u8| {
+            let mut hasher =
+                SipHasher13::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+            hasher.write(&(0..len).collect::<Vec<_>>());
+            hasher.finish()
+        };
+        assert_eq!(hash(0), 0xabac_0158_050f_c4dc);
+        assert_eq!(hash(1), 0xc9f4_9bf3_7d57_ca93);
+        assert_eq!(hash(2), 0x82cb_9b02_4dc7_d44d);
+    }
+
+    #[test]
+    fn test_siphash_split_writes() {
+        let bytes: Vec<u8> = (0..40).collect();
+        let mut whole = SipHasher13::new();
+        whole.write(&bytes);
+        for split in [1, 3, 8, 13] {
+            let mut parts = SipHasher13::new();
+            bytes.chunks(split).for_each(|chunk| parts.write(chunk));
+            assert_eq!(parts.finish(), whole.finish());
+        }
+    }
+
+    #[test]
+    fn test_hashers_disagree() {
+        assert_ne!(make_hash("foo"), make_fnv_hash("foo"));
+        assert_eq!(make_fnv_hash("foo"), make_fnv_hash("foo"));
+    }
+}
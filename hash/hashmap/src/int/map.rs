@@ -0,0 +1,345 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{iter::FromIterator, ops};
+
+// keys per page
+const PAGE_BITS: u32 = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+struct Page<V> {
+    slots: Box<[Option<V>]>,
+    // number of occupied slots, so empty pages can be freed
+    len: usize,
+}
+
+impl<V> Page<V> {
+    fn new() -> Self {
+        Self {
+            slots: (0..PAGE_SIZE).map(|_| None).collect(),
+            len: 0,
+        }
+    }
+}
+
+fn split(key: u32) -> (usize, usize) {
+    ((key >> PAGE_BITS) as usize, key as usize & (PAGE_SIZE - 1))
+}
+
+/// Map from `u32` keys to values, stored in a direct-address table.
+///
+/// The key space is cut into pages of 1024 keys and a page is only
+/// allocated once one of its keys is inserted, so sparse ranges of keys
+/// cost one pointer per page instead of a slot per key. Lookups are two
+/// array indexes and no hashing. Iteration is in key order.
+///
+/// The page directory grows to the largest key seen, so keys should stay
+/// well below `u32::MAX`.
+pub struct IntMap<V> {
+    pages: Vec<Option<Page<V>>>,
+    items: usize,
+}
+
+impl<V> Default for IntMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> IntMap<V> {
+    pub fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            items: 0,
+        }
+    }
+
+    /// return the number of elements in the map
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    /// return true if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&self, key: u32) -> Option<&Option<V>> {
+        let (page, offset) = split(key);
+        Some(&self.pages.get(page)?.as_ref()?.slots[offset])
+    }
+
+    /// Return the page holding `key`, allocating it if needed
+    fn page_mut(&mut self, key: u32) -> &mut Page<V> {
+        let (page, _) = split(key);
+        if page >= self.pages.len() {
+            self.pages.resize_with(page + 1, || None);
+        }
+        self.pages[page].get_or_insert_with(Page::new)
+    }
+
+    pub fn insert(&mut self, key: u32, value: V) -> Option<V> {
+        let page = self.page_mut(key);
+        let old = page.slots[split(key).1].replace(value);
+        if old.is_none() {
+            page.len += 1;
+            self.items += 1;
+        }
+        old
+    }
+
+    pub fn get(&self, key: &u32) -> Option<&V> {
+        self.slot(*key)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &u32) -> Option<&mut V> {
+        let (page, offset) = split(*key);
+        self.pages.get_mut(page)?.as_mut()?.slots[offset].as_mut()
+    }
+
+    pub fn contains_key(&self, key: &u32) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &u32) -> Option<V> {
+        let (page_idx, offset) = split(*key);
+        let page = self.pages.get_mut(page_idx)?.as_mut()?;
+        let value = page.slots[offset].take()?;
+        page.len -= 1;
+        self.items -= 1;
+        if page.len == 0 {
+            self.pages[page_idx] = None;
+        }
+        Some(value)
+    }
+
+    pub fn entry(&mut self, key: u32) -> Entry<'_, V> {
+        if self.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry {
+                value: self.get_mut(&key).expect("key is present"),
+            })
+        } else {
+            Entry::Vacant(VacantEntry { key, map: self })
+        }
+    }
+
+    /// Keep only the entries for which 'keep' returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(u32, &mut V) -> bool,
+    {
+        for (page_idx, slot) in self.pages.iter_mut().enumerate() {
+            let Some(page) = slot else { continue };
+            for (offset, entry) in page.slots.iter_mut().enumerate() {
+                let key = ((page_idx << PAGE_BITS) | offset) as u32;
+                if entry.as_mut().is_some_and(|v| !keep(key, v)) {
+                    *entry = None;
+                    page.len -= 1;
+                    self.items -= 1;
+                }
+            }
+            if page.len == 0 {
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.items = 0;
+    }
+
+    /// Iterate over the entries in ascending key order
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            pages: self.pages.iter().enumerate(),
+            page: None,
+        }
+    }
+}
+
+pub enum Entry<'a, V> {
+    Occupied(OccupiedEntry<'a, V>),
+    Vacant(VacantEntry<'a, V>),
+}
+
+pub struct OccupiedEntry<'a, V> {
+    value: &'a mut V,
+}
+
+pub struct VacantEntry<'a, V> {
+    key: u32,
+    map: &'a mut IntMap<V>,
+}
+
+impl<'a, V> VacantEntry<'a, V> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        let (_, offset) = split(self.key);
+        self.map.items += 1;
+        let page = self.map.page_mut(self.key);
+        page.len += 1;
+        page.slots[offset].insert(value)
+    }
+}
+
+impl<'a, V> Entry<'a, V> {
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.value,
+            Entry::Vacant(e) => e.insert(value),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, maker: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.value,
+            Entry::Vacant(e) => e.insert(maker()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(Default::default)
+    }
+}
+
+impl<V> ops::Index<&u32> for IntMap<V> {
+    type Output = V;
+
+    fn index(&self, key: &u32) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+type Pages<'a, V> = core::iter::Enumerate<core::slice::Iter<'a, Option<Page<V>>>>;
+type Slots<'a, V> = core::iter::Enumerate<core::slice::Iter<'a, Option<V>>>;
+
+/// Iterator over the entries of an `IntMap` in key order
+pub struct Iter<'a, V> {
+    pages: Pages<'a, V>,
+    // current page index and its remaining slots
+    page: Option<(usize, Slots<'a, V>)>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (u32, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((page_idx, slots)) = &mut self.page {
+                for (offset, slot) in slots.by_ref() {
+                    if let Some(value) = slot {
+                        return Some((((*page_idx << PAGE_BITS) | offset) as u32, value));
+                    }
+                }
+            }
+            let (page_idx, page) = self.pages.by_ref().find(|(_, p)| p.is_some())?;
+            let page = page.as_ref().expect("found an allocated page");
+            self.page = Some((page_idx, page.slots.iter().enumerate()));
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a IntMap<V> {
+    type Item = (u32, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<V> FromIterator<(u32, V)> for IntMap<V> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (u32, V)>,
+    {
+        let mut map = IntMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<V> Extend<(u32, V)> for IntMap<V> {
+    fn extend<I: IntoIterator<Item = (u32, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<V: Clone> Clone for IntMap<V> {
+    fn clone(&self) -> Self {
+        let pages = self
+            .pages
+            .iter()
+            .map(|page| {
+                page.as_ref().map(|p| Page {
+                    slots: p.slots.clone(),
+                    len: p.len,
+                })
+            })
+            .collect();
+        Self {
+            pages,
+            items: self.items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocated_pages<V>(map: &IntMap<V>) -> usize {
+        map.pages.iter().filter(|p| p.is_some()).count()
+    }
+
+    #[test]
+    fn test_basic_operations() {
+        let mut map = IntMap::new();
+        assert_eq!(map.get(&3), None);
+        assert_eq!(map.insert(3, "three"), None);
+        assert_eq!(map.insert(3, "drei"), Some("three"));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&3], "drei");
+        *map.get_mut(&3).unwrap() = "trois";
+        assert_eq!(map.remove(&3), Some("trois"));
+        assert_eq!(map.remove(&3), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_sparse_keys_share_pages() {
+        let mut map: IntMap<u32> = [0, 1, 1023, 1_000_000]
+            .map(|k| (k, k))
+            .into_iter()
+            .collect();
+        // 0..1023 fit in one page, the big key gets its own
+        assert_eq!(allocated_pages(&map), 2);
+        assert_eq!(map.remove(&1_000_000), Some(1_000_000));
+        assert_eq!(allocated_pages(&map), 1);
+        map.retain(|k, _| k > 0 && k < 1000);
+        assert_eq!(map.iter().collect::<Vec<_>>(), [(1, &1)]);
+        map.retain(|_, _| false);
+        assert_eq!(allocated_pages(&map), 0);
+    }
+
+    #[test]
+    fn test_entry_and_iteration_order() {
+        let mut map = IntMap::new();
+        for key in [5000, 7, 7, 2048, 5000, 5000] {
+            *map.entry(key).or_default() += 1;
+        }
+        let entries: Vec<_> = map.iter().map(|(k, &v)| (k, v)).collect();
+        assert_eq!(entries, [(7, 2), (2048, 1), (5000, 3)]);
+        assert_eq!(map.len(), 3);
+        map.clear();
+        assert!(map.iter().next().is_none());
+    }
+}
@@ -0,0 +1,11 @@
+//! Containers for small integer keys.
+//!
+//! When keys are small integers they can index an array directly, which
+//! skips hashing and keeps neighbouring keys next to each other in memory.
+//! `IntMap` does this for maps and `SparseSet` for sets.
+
+mod map;
+mod set;
+
+pub use map::{Entry, IntMap, Iter, OccupiedEntry, VacantEntry};
+pub use set::SparseSet;
@@ -0,0 +1,150 @@
+use alloc::{vec, vec::Vec};
+use core::{iter::FromIterator, slice};
+
+/// Set of `u32` values with O(1) insert, remove, lookup and clear.
+///
+/// Members are packed in `dense`, and `sparse[v]` holds the position of
+/// `v` in `dense`. A lookup trusts `sparse[v]` only if `dense` agrees, so
+/// stale entries left in `sparse` never need clearing: `clear` just
+/// truncates `dense`. Iteration walks the packed members, in insertion
+/// order until something is removed.
+///
+/// `sparse` grows to the largest value inserted, so values should be
+/// small, such as ids handed out from a counter.
+#[derive(Clone, Default)]
+pub struct SparseSet {
+    dense: Vec<u32>,
+    sparse: Vec<u32>,
+}
+
+impl SparseSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set that can hold values below `universe` without growing
+    pub fn with_universe(universe: usize) -> Self {
+        Self {
+            dense: Vec::new(),
+            sparse: vec![0; universe],
+        }
+    }
+
+    /// return the number of elements in the set
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    /// return true if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn position(&self, value: u32) -> Option<usize> {
+        let pos = *self.sparse.get(value as usize)? as usize;
+        (self.dense.get(pos) == Some(&value)).then_some(pos)
+    }
+
+    pub fn contains(&self, value: &u32) -> bool {
+        self.position(*value).is_some()
+    }
+
+    /// Add `value`, returning false if it was already present
+    pub fn insert(&mut self, value: u32) -> bool {
+        if self.contains(&value) {
+            return false;
+        }
+        let idx = value as usize;
+        if idx >= self.sparse.len() {
+            self.sparse.resize(idx + 1, 0);
+        }
+        self.sparse[idx] = self.dense.len() as u32;
+        self.dense.push(value);
+        true
+    }
+
+    /// Remove `value`, returning false if it was not present
+    pub fn remove(&mut self, value: &u32) -> bool {
+        let Some(pos) = self.position(*value) else {
+            return false;
+        };
+        // move the last member into the hole
+        self.dense.swap_remove(pos);
+        if let Some(&moved) = self.dense.get(pos) {
+            self.sparse[moved as usize] = pos as u32;
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, u32> {
+        self.dense.iter()
+    }
+
+    /// The members as a packed slice
+    pub fn as_slice(&self) -> &[u32] {
+        &self.dense
+    }
+}
+
+impl<'a> IntoIterator for &'a SparseSet {
+    type Item = &'a u32;
+    type IntoIter = slice::Iter<'a, u32>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<u32> for SparseSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut set = SparseSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<u32> for SparseSet {
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut set = SparseSet::with_universe(16);
+        assert!(set.insert(3));
+        assert!(set.insert(9));
+        assert!(!set.insert(3));
+        assert!(set.insert(100));
+        assert_eq!(set.as_slice(), [3, 9, 100]);
+        assert!(set.remove(&3));
+        assert!(!set.remove(&3));
+        assert!(!set.contains(&3));
+        // the last member filled the hole
+        assert_eq!(set.as_slice(), [100, 9]);
+        assert!(set.contains(&100) && set.contains(&9));
+    }
+
+    #[test]
+    fn test_clear_ignores_stale_entries() {
+        let mut set: SparseSet = (0..50).collect();
+        set.clear();
+        assert!(set.is_empty());
+        assert!((0..50).all(|v| !set.contains(&v)));
+        // sparse[7] still points at slot 7 from before the clear
+        set.extend([7, 1, 2]);
+        assert!(set.contains(&7));
+        assert!(!set.contains(&3));
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), [7, 1, 2]);
+    }
+}
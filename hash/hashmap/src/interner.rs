@@ -0,0 +1,319 @@
+//! String interning.
+//!
+//! `Interner` hands out a `Symbol` for every distinct string it sees and
+//! stores each string once. The strings are copied into large arena
+//! chunks that are never reallocated, so `resolve` can return a `&str`
+//! into the arena without allocating, and the lookup table can key on
+//! those same slices instead of owning a second copy.
+
+use alloc::{string::String, vec::Vec};
+use core::{error, fmt};
+#[cfg(feature = "std")]
+use std::sync::{PoisonError, RwLock};
+
+use crate::HashMap;
+
+const MIN_CHUNK: usize = 4096;
+
+/// Compact id of an interned string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Position of the string in interning order, starting at 0
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Returned by `from_bytes` when the input is not a symbol table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid symbol table encoding")
+    }
+}
+
+impl error::Error for DecodeError {}
+
+#[derive(Default)]
+pub struct Interner {
+    // Strings are appended to `chunk` until it runs out of capacity, then
+    // it is retired to `full` and a bigger one is started. A `String` is
+    // never pushed past its capacity, so its bytes never move.
+    chunk: String,
+    full: Vec<String>,
+    // The slices below point into the chunks. They are only handed out
+    // with the lifetime of `&self`, and the chunks live as long as `self`.
+    map: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the symbol for `s`, interning it first if it is new
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(&sym) = self.map.get(s) {
+            return sym;
+        }
+        let sym = Symbol(u32::try_from(self.strings.len()).expect("too many symbols"));
+        let stored = self.alloc(s);
+        self.map.insert(stored, sym);
+        self.strings.push(stored);
+        sym
+    }
+
+    /// Return the symbol for `s` if it has been interned
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.map.get(s).copied()
+    }
+
+    /// Return the string for `sym`.
+    ///
+    /// Panics if `sym` was not handed out by this interner.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        self.strings[sym.index()]
+    }
+
+    /// return the number of distinct strings
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// return true if nothing has been interned
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the symbols and their strings in interning order
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| (Symbol(i as u32), s))
+    }
+
+    fn alloc(&mut self, s: &str) -> &'static str {
+        if self.chunk.capacity() - self.chunk.len() < s.len() {
+            let size = (self.chunk.capacity() * 2).max(s.len()).max(MIN_CHUNK);
+            let old = core::mem::replace(&mut self.chunk, String::with_capacity(size));
+            if !old.is_empty() {
+                self.full.push(old);
+            }
+        }
+        let start = self.chunk.len();
+        self.chunk.push_str(s);
+        let stored = &self.chunk[start..];
+        // SAFETY: the bytes live in a chunk that is never reallocated or
+        // dropped before `self`, and the 'static is never exposed.
+        unsafe { &*(stored as *const str) }
+    }
+
+    /// Encode the symbol table as a little-endian `u32` count followed by
+    /// each string as a `u32` byte length and its UTF-8 bytes, in symbol
+    /// order. Decoding gives every string its original symbol.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size: usize = self.strings.iter().map(|s| 4 + s.len()).sum();
+        let mut bytes = Vec::with_capacity(4 + size);
+        bytes.extend_from_slice(&(self.strings.len() as u32).to_le_bytes());
+        for s in &self.strings {
+            bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let count = read_u32(&mut bytes)?;
+        let mut interner = Interner::new();
+        for i in 0..count {
+            let len = read_u32(&mut bytes)? as usize;
+            let s = core::str::from_utf8(take(&mut bytes, len)?).map_err(|_| DecodeError)?;
+            // a duplicate would shift every later symbol
+            if interner.intern(s).0 != i {
+                return Err(DecodeError);
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(DecodeError);
+        }
+        Ok(interner)
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < n {
+        return Err(DecodeError);
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, DecodeError> {
+    Ok(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+}
+
+/// Interner that can be shared between threads.
+///
+/// Lookups of strings that are already interned only take a read lock.
+/// `resolve` returns a `&str` that outlives the lock, which is fine
+/// because interned strings never move.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct SyncInterner {
+    inner: RwLock<Interner>,
+}
+
+#[cfg(feature = "std")]
+impl SyncInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, s: &str) -> Symbol {
+        if let Some(sym) = self.get(s) {
+            return sym;
+        }
+        // another thread may have interned it in between; intern()
+        // checks again under the write lock
+        self.inner
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .intern(s)
+    }
+
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(s)
+    }
+
+    /// Return the string for `sym`.
+    ///
+    /// Panics if `sym` was not handed out by this interner.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        let guard = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        let s: *const str = guard.resolve(sym);
+        // SAFETY: the string lives in an arena chunk owned by the interner,
+        // which is never moved or freed while `self` is borrowed.
+        unsafe { &*s }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            inner: RwLock::new(Interner::from_bytes(bytes)?),
+        })
+    }
+
+    pub fn into_inner(self) -> Interner {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_intern_resolve() {
+        let mut interner = Interner::new();
+        let foo = interner.intern("foo");
+        let bar = interner.intern("bar");
+        assert_ne!(foo, bar);
+        assert_eq!(interner.intern("foo"), foo);
+        assert_eq!(interner.resolve(foo), "foo");
+        assert_eq!(interner.resolve(bar), "bar");
+        assert_eq!(interner.get("bar"), Some(bar));
+        assert_eq!(interner.get("baz"), None);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn test_strings_do_not_move() {
+        let mut interner = Interner::new();
+        let first = interner.intern("first");
+        let before = interner.resolve(first).as_ptr();
+        // force several new chunks
+        for i in 0..10_000 {
+            interner.intern(&format!("identifier_{i}"));
+        }
+        interner.intern(&"x".repeat(3 * MIN_CHUNK));
+        assert_eq!(interner.resolve(first).as_ptr(), before);
+        assert_eq!(interner.resolve(Symbol(5000)), "identifier_4999");
+        assert!(interner.iter().all(|(sym, s)| interner.get(s) == Some(sym)));
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut interner = Interner::new();
+        for s in ["", "foo", "bär", "foo bar"] {
+            interner.intern(s);
+        }
+        let bytes = interner.to_bytes();
+        let decoded = Interner::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 4);
+        for (sym, s) in interner.iter() {
+            assert_eq!(decoded.resolve(sym), s);
+        }
+        assert_eq!(
+            Interner::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(DecodeError)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Interner::from_bytes(&trailing).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sync_interner() {
+        use std::{sync::Arc, thread};
+
+        let interner = Arc::new(SyncInterner::new());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let interner = Arc::clone(&interner);
+                thread::spawn(move || {
+                    (0..1000)
+                        .map(|i| interner.intern(&format!("sym{i}")))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        // every thread saw the same symbol for the same string
+        assert!(results.windows(2).all(|w| w[0] == w[1]));
+        assert_eq!(interner.len(), 1000);
+        assert_eq!(interner.resolve(results[0][42]), "sym42");
+        let copy = SyncInterner::from_bytes(&interner.to_bytes()).unwrap();
+        assert_eq!(copy.get("sym42"), Some(results[0][42]));
+    }
+}
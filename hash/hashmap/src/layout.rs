@@ -0,0 +1,196 @@
+//! Views of the bucket layout, for spotting skew and for teaching.
+//!
+//! `dump_layout` is a plain text table with one row per bucket. `to_dot`
+//! renders the same thing as a Graphviz digraph: the buckets form a column
+//! of nodes, and each one points to the chain of entries stored in it. Feed
+//! the output to `dot -Tsvg` to draw it.
+
+use alloc::{format, string::String};
+use core::fmt::{self, Debug, Write};
+
+use crate::{allocator::Allocator, HashMap};
+
+/// Quote `s` for a DOT string literal
+fn dot_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    /// Number of buckets in the table, 0 until the first insert
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn longest_chain(&self) -> usize {
+        self.buckets.iter().map(|b| b.len()).max().unwrap_or(0)
+    }
+
+    /// A text table of every bucket with its chain length and keys, in
+    /// chain order, followed by a one-line summary.
+    ///
+    /// ```text
+    /// bucket | len | keys
+    /// -------+-----+-----
+    ///      0 |   1 | "foo"
+    ///      1 |   0 |
+    /// 1 entries in 2 buckets, longest chain 1
+    /// ```
+    pub fn dump_layout(&self) -> String
+    where
+        K: Debug,
+    {
+        let mut out = String::new();
+        self.write_layout(&mut out)
+            .expect("writing to a String can't fail");
+        out
+    }
+
+    fn write_layout(&self, out: &mut String) -> fmt::Result
+    where
+        K: Debug,
+    {
+        let index_width = format!("{}", self.buckets.len().saturating_sub(1)).len();
+        let index_width = index_width.max("bucket".len());
+        let len_width = format!("{}", self.longest_chain()).len().max("len".len());
+        writeln!(
+            out,
+            "{:>index_width$} | {:>len_width$} | keys",
+            "bucket", "len"
+        )?;
+        writeln!(out, "{:-<index_width$}-+-{:-<len_width$}-+-----", "", "")?;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            write!(out, "{i:>index_width$} | {:>len_width$} |", bucket.len())?;
+            for (n, (key, _)) in bucket.iter().enumerate() {
+                let sep = if n == 0 { " " } else { ", " };
+                write!(out, "{sep}{key:?}")?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "{} entries in {} buckets, longest chain {}",
+            self.items,
+            self.buckets.len(),
+            self.longest_chain()
+        )
+    }
+
+    /// The layout as a Graphviz digraph. Bucket `i` is node `b{i}` and the
+    /// `n`th entry in its chain is node `e{i}_{n}`, labelled `key: value`.
+    pub fn to_dot(&self) -> String
+    where
+        K: Debug,
+        V: Debug,
+    {
+        let mut out = String::new();
+        self.write_dot(&mut out)
+            .expect("writing to a String can't fail");
+        out
+    }
+
+    fn write_dot(&self, out: &mut String) -> fmt::Result
+    where
+        K: Debug,
+        V: Debug,
+    {
+        writeln!(out, "digraph hashmap {{")?;
+        writeln!(out, "    rankdir=LR;")?;
+        writeln!(
+            out,
+            "    label=\"{} entries in {} buckets\";",
+            self.items,
+            self.buckets.len()
+        )?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+        // keep the buckets in one column, in index order
+        writeln!(out, "    {{")?;
+        writeln!(out, "        rank=same;")?;
+        for i in 0..self.buckets.len() {
+            writeln!(out, "        b{i} [label=\"{i}\", shape=square];")?;
+        }
+        for i in 1..self.buckets.len() {
+            writeln!(out, "        b{} -> b{i} [style=invis];", i - 1)?;
+        }
+        writeln!(out, "    }}")?;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            for (n, (key, value)) in bucket.iter().enumerate() {
+                let label = dot_escape(&format!("{key:?}: {value:?}"));
+                writeln!(out, "    e{i}_{n} [label=\"{label}\"];")?;
+                match n {
+                    0 => writeln!(out, "    b{i} -> e{i}_0;")?,
+                    n => writeln!(out, "    e{i}_{} -> e{i}_{n};", n - 1)?,
+                }
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_empty_map() {
+        let map: HashMap<u32, u32> = HashMap::new();
+        assert_eq!(map.bucket_count(), 0);
+        assert_eq!(
+            map.dump_layout(),
+            "bucket | len | keys\n\
+             -------+-----+-----\n\
+             0 entries in 0 buckets, longest chain 0\n"
+        );
+        let dot = map.to_dot();
+        assert!(dot.starts_with("digraph hashmap {\n"));
+        assert!(!dot.contains("b0"));
+    }
+
+    #[test]
+    fn test_layout_follows_buckets() {
+        let map: HashMap<_, _> = (0..20).map(|i| (i, i * i)).collect();
+        let dump = map.dump_layout();
+        let rows: Vec<&str> = dump.lines().collect();
+        // header, rule, one row per bucket and the summary
+        assert_eq!(rows.len(), map.bucket_count() + 3);
+        for (i, bucket) in map.buckets.iter().enumerate() {
+            let keys: Vec<String> = bucket.iter().map(|(k, _)| format!("{k:?}")).collect();
+            let expected = format!("{i:>6} | {:>3} | {}", bucket.len(), keys.join(", "));
+            assert_eq!(rows[i + 2].trim_end(), expected.trim_end());
+        }
+        assert_eq!(
+            rows.last().unwrap(),
+            &format!(
+                "20 entries in {} buckets, longest chain {}",
+                map.bucket_count(),
+                map.longest_chain()
+            )
+        );
+
+        let dot = map.to_dot();
+        assert_eq!(dot.matches("shape=square").count(), map.bucket_count());
+        // one edge into every entry, from its bucket or its predecessor
+        let edges = dot.lines().filter(|l| l.contains("-> e")).count();
+        assert_eq!(edges, 20);
+        assert!(dot.contains("[label=\"7: 49\"]"));
+    }
+
+    #[test]
+    fn test_dot_escapes_labels() {
+        let mut map = HashMap::new();
+        map.insert("say \"hi\"", "back\\slash");
+        let dot = map.to_dot();
+        assert!(dot.contains(r#"e0_0 [label="\"say \\\"hi\\\"\": \"back\\\\slash\""];"#));
+    }
+}
@@ -0,0 +1,484 @@
+//! A map whose readers never block, for read-mostly data.
+//!
+//! `LeftRightMap` keeps two copies of a `HashMap`. Readers look at the
+//! active copy, while the single writer changes the other one and records
+//! each change in an operation log. `publish` makes the writer's copy the
+//! active one, waits until no reader is still inside the old copy, and then
+//! replays the log on it so both copies agree again.
+//!
+//! Each `ReadHandle` owns an epoch counter that is odd while it is reading.
+//! A read is two atomic increments around the lookup, so readers are
+//! wait-free. The writer only waits for readers that were already reading
+//! when it switched copies. Changes are invisible to readers until they
+//! are published:
+//!
+//! ```
+//! use hashmap::left_right;
+//!
+//! let (mut writer, reader) = left_right::new();
+//! writer.insert("route", 1);
+//! assert_eq!(reader.get("route"), None);
+//! writer.publish();
+//! assert_eq!(reader.get("route"), Some(1));
+//! ```
+
+use std::{
+    cell::{Cell, UnsafeCell},
+    hash::Hash,
+    ops,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+
+use crate::{Equivalent, HashMap};
+
+enum Op<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Clear,
+}
+
+impl<K: Hash + PartialEq + Clone, V: Clone> Op<K, V> {
+    fn apply(&self, map: &mut HashMap<K, V>) {
+        match self {
+            Op::Insert(key, value) => {
+                map.insert(key.clone(), value.clone());
+            }
+            Op::Remove(key) => {
+                map.remove(key);
+            }
+            Op::Clear => map.retain(|_, _| false),
+        }
+    }
+}
+
+/// The state shared by the handles of one map
+pub struct LeftRightMap<K, V> {
+    maps: [UnsafeCell<HashMap<K, V>>; 2],
+    // index of the copy readers use
+    active: AtomicUsize,
+    // one counter per live ReadHandle, odd while it is reading
+    epochs: Mutex<Vec<Arc<AtomicUsize>>>,
+}
+
+// SAFETY: readers only share the active copy, and the single writer only
+// touches the other one once every reader has left it
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for LeftRightMap<K, V> {}
+
+impl<K, V> LeftRightMap<K, V> {
+    fn register(self: &Arc<Self>) -> ReadHandle<K, V> {
+        let epoch = Arc::new(AtomicUsize::new(0));
+        self.epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::clone(&epoch));
+        ReadHandle {
+            shared: Arc::clone(self),
+            epoch,
+            depth: Cell::new(0),
+        }
+    }
+}
+
+/// Create an empty map, returning its only writer and a first reader
+pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>) {
+    let shared = Arc::new(LeftRightMap {
+        maps: [
+            UnsafeCell::new(HashMap::new()),
+            UnsafeCell::new(HashMap::new()),
+        ],
+        active: AtomicUsize::new(0),
+        epochs: Mutex::new(Vec::new()),
+    });
+    let reader = shared.register();
+    let writer = WriteHandle {
+        shared,
+        log: Vec::new(),
+        behind: false,
+    };
+    (writer, reader)
+}
+
+/// Reads the published state of a `LeftRightMap`.
+///
+/// Clone the handle to give another thread its own. A handle can be sent
+/// to another thread but not shared between threads.
+pub struct ReadHandle<K, V> {
+    shared: Arc<LeftRightMap<K, V>>,
+    epoch: Arc<AtomicUsize>,
+    // nested guards from this handle share one epoch increment
+    depth: Cell<usize>,
+}
+
+impl<K, V> ReadHandle<K, V> {
+    /// Borrow the published map. The writer cannot publish again until the
+    /// guard is dropped, so don't hold on to it.
+    pub fn read(&self) -> ReadGuard<'_, K, V> {
+        if self.depth.get() == 0 {
+            // odd: reading. SeqCst orders this before the load of active,
+            // so a writer that saw the even value has already switched
+            self.epoch.fetch_add(1, Ordering::SeqCst);
+        }
+        self.depth.set(self.depth.get() + 1);
+        let active = self.shared.active.load(Ordering::SeqCst);
+        // SAFETY: the writer leaves the active copy alone, and waits for
+        // our epoch to move on before it touches this copy again
+        let map = unsafe { &*self.shared.maps[active].get() };
+        ReadGuard { handle: self, map }
+    }
+}
+
+impl<K: Hash + PartialEq, V> ReadHandle<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+        V: Clone,
+    {
+        self.read().get(key).cloned()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.read().contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Clone for ReadHandle<K, V> {
+    fn clone(&self) -> Self {
+        self.shared.register()
+    }
+}
+
+impl<K, V> Drop for ReadHandle<K, V> {
+    fn drop(&mut self) {
+        self.shared
+            .epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|epoch| !Arc::ptr_eq(epoch, &self.epoch));
+    }
+}
+
+/// The published map, borrowed from a `ReadHandle`
+pub struct ReadGuard<'a, K, V> {
+    handle: &'a ReadHandle<K, V>,
+    map: &'a HashMap<K, V>,
+}
+
+impl<K, V> ops::Deref for ReadGuard<'_, K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &HashMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> Drop for ReadGuard<'_, K, V> {
+    fn drop(&mut self) {
+        let depth = self.handle.depth.get() - 1;
+        self.handle.depth.set(depth);
+        if depth == 0 {
+            // even again: done with this copy
+            self.handle.epoch.fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+/// The single writer of a `LeftRightMap`.
+///
+/// Changes go to the copy readers can't see until `publish` is called.
+/// Every change is applied to both copies, so keys and values are cloned
+/// for each.
+pub struct WriteHandle<K, V> {
+    shared: Arc<LeftRightMap<K, V>>,
+    log: Vec<Op<K, V>>,
+    // the log was published but not yet replayed on the write copy
+    behind: bool,
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Hash + PartialEq + Clone,
+    V: Clone,
+{
+    fn write_copy(&mut self) -> &mut HashMap<K, V> {
+        self.catch_up();
+        let inactive = 1 - self.shared.active.load(Ordering::Relaxed);
+        // SAFETY: readers only use the active copy, and only this handle
+        // changes which copy that is
+        unsafe { &mut *self.shared.maps[inactive].get() }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.write_copy().insert(key.clone(), value.clone());
+        self.log.push(Op::Insert(key, value));
+    }
+
+    pub fn remove(&mut self, key: K) {
+        self.write_copy().remove(&key);
+        self.log.push(Op::Remove(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.write_copy().retain(|_, _| false);
+        self.log.push(Op::Clear);
+    }
+
+    /// The map as it will look once published
+    pub fn pending(&mut self) -> &HashMap<K, V> {
+        self.write_copy()
+    }
+
+    /// Number of changes made since the last `publish`
+    pub fn unpublished(&self) -> usize {
+        if self.behind {
+            0
+        } else {
+            self.log.len()
+        }
+    }
+
+    /// Make every change so far visible to readers.
+    ///
+    /// Blocks until the readers that were reading the old copy are done
+    /// with it, which is never longer than the lookups they were doing.
+    ///
+    /// If a key's `Hash` or `PartialEq` impl panics while the changes are
+    /// replayed on the other copy, readers still see every change, and the
+    /// replay is retried by the next call on this handle.
+    pub fn publish(&mut self) {
+        self.catch_up();
+        if self.log.is_empty() {
+            return;
+        }
+        let epochs = self
+            .shared
+            .epochs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let old = self.shared.active.load(Ordering::Relaxed);
+        self.shared.active.store(1 - old, Ordering::SeqCst);
+        // readers that start from here on see the new copy, so only wait
+        // for the ones caught in the middle of a read
+        let started: Vec<usize> = epochs.iter().map(|e| e.load(Ordering::SeqCst)).collect();
+        for (epoch, start) in epochs.iter().zip(started) {
+            if start % 2 == 1 {
+                while epoch.load(Ordering::Acquire) == start {
+                    thread::yield_now();
+                }
+            }
+        }
+        drop(epochs);
+        // no reader is left in the old copy
+        self.behind = true;
+        self.catch_up();
+    }
+
+    /// Replay the published log on the write copy. The log is only cleared
+    /// once every op went through, so after a panic the next call starts
+    /// over, which is harmless: replaying an op twice leaves the key as the
+    /// last op on it says.
+    fn catch_up(&mut self) {
+        if !self.behind {
+            return;
+        }
+        let inactive = 1 - self.shared.active.load(Ordering::Relaxed);
+        // SAFETY: publish waited for every reader to leave this copy
+        let map = unsafe { &mut *self.shared.maps[inactive].get() };
+        for op in &self.log {
+            op.apply(map);
+        }
+        self.log.clear();
+        self.behind = false;
+    }
+
+    /// Another reader of this map
+    pub fn reader(&self) -> ReadHandle<K, V> {
+        self.shared.register()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        hash::Hasher,
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::AtomicBool,
+    };
+
+    /// A key whose `Hash` impl panics once armed
+    #[derive(Clone)]
+    struct Touchy {
+        k: u64,
+        armed: Arc<AtomicBool>,
+    }
+
+    impl Hash for Touchy {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            if self.armed.swap(false, Ordering::Relaxed) {
+                panic!("hash bomb");
+            }
+            self.k.hash(state)
+        }
+    }
+
+    impl PartialEq for Touchy {
+        fn eq(&self, other: &Self) -> bool {
+            self.k == other.k
+        }
+    }
+
+    #[test]
+    fn test_publish_and_replay() {
+        let (mut writer, reader) = new();
+        writer.insert(1, "one");
+        writer.insert(2, "two");
+        assert_eq!(writer.unpublished(), 2);
+        assert!(reader.is_empty());
+        writer.publish();
+        assert_eq!(reader.get(&2), Some("two"));
+
+        writer.remove(2);
+        writer.insert(3, "three");
+        assert_eq!(writer.pending().len(), 2);
+        assert_eq!(reader.len(), 2);
+        writer.publish();
+        // the second copy caught up with the first publish by replaying
+        assert_eq!(reader.get(&2), None);
+        assert_eq!(reader.get(&1), Some("one"));
+        writer.clear();
+        writer.publish();
+        writer.insert(4, "four");
+        writer.publish();
+        assert_eq!(reader.len(), 1);
+        assert_eq!(writer.pending().len(), 1);
+    }
+
+    #[test]
+    fn test_nested_guards() {
+        let (mut writer, reader) = new();
+        writer.insert("k", 1);
+        writer.publish();
+        let outer = reader.read();
+        let inner = reader.read();
+        drop(inner);
+        // still inside the outer read
+        assert_eq!(reader.epoch.load(Ordering::Relaxed) % 2, 1);
+        assert_eq!(outer.get("k"), Some(&1));
+        drop(outer);
+        assert_eq!(reader.epoch.load(Ordering::Relaxed) % 2, 0);
+    }
+
+    #[test]
+    fn test_stress_readers_see_whole_publishes() {
+        const KEYS: u64 = 32;
+        const ROUNDS: u64 = 500;
+        let (mut writer, reader) = new();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let reader = reader.clone();
+                s.spawn(move || {
+                    let mut last = 0;
+                    // keep reading until the last publish shows up
+                    while last < ROUNDS {
+                        let map = reader.read();
+                        if let Some(&round) = map.get(&0) {
+                            // every publish sets all keys together
+                            assert!((0..KEYS).all(|k| map.get(&k) == Some(&round)));
+                            assert!(round >= last);
+                            last = round;
+                        }
+                        drop(map);
+                        thread::yield_now();
+                    }
+                });
+            }
+            for round in 1..=ROUNDS {
+                for key in 0..KEYS {
+                    writer.insert(key, round);
+                }
+                writer.publish();
+            }
+        });
+        assert_eq!(reader.get(&(KEYS - 1)), Some(ROUNDS));
+    }
+
+    #[test]
+    fn test_stress_readers_come_and_go() {
+        let (mut writer, reader) = new();
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let reader = reader.clone();
+                    s.spawn(move || {
+                        let mut seen = 0;
+                        for _ in 0..500 {
+                            // new handles register while the writer publishes
+                            let reader = reader.clone();
+                            seen = seen.max(reader.len());
+                        }
+                        seen
+                    })
+                })
+                .collect();
+            for i in 0..500 {
+                writer.insert(i, i);
+                writer.publish();
+            }
+            for handle in handles {
+                assert!(handle.join().unwrap() <= 500);
+            }
+        });
+        assert_eq!(reader.len(), 500);
+        // only the original reader is still registered
+        assert_eq!(reader.shared.epochs.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_hash_panic_during_replay() {
+        let (mut writer, reader) = new();
+        let keys: Vec<_> = (0..4)
+            .map(|k| Touchy {
+                k,
+                armed: Arc::new(AtomicBool::new(false)),
+            })
+            .collect();
+        for key in &keys[..3] {
+            writer.insert(key.clone(), key.k);
+        }
+        writer.remove(keys[0].clone());
+        keys[2].armed.store(true, Ordering::Relaxed);
+        let publish = panic::catch_unwind(AssertUnwindSafe(|| writer.publish()));
+        assert!(publish.is_err());
+        // the published copy is whole, and the replay is not lost
+        assert_eq!(reader.len(), 2);
+        assert_eq!(writer.unpublished(), 0);
+        let pending = writer.pending();
+        assert_eq!(pending.len(), 2);
+        assert!(!pending.contains_key(&keys[0]) && pending.contains_key(&keys[2]));
+
+        writer.insert(keys[3].clone(), 3);
+        writer.publish();
+        writer.insert(keys[0].clone(), 0);
+        writer.publish();
+        // both copies have been published since, and agree
+        for key in &keys {
+            assert_eq!(reader.get(key), Some(key.k));
+            assert_eq!(writer.pending().get(key), Some(&key.k));
+        }
+    }
+}
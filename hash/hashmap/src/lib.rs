@@ -1,75 +1,284 @@
-use std::{
-    borrow,
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
+//! A separate-chaining hash map, and a collection of structures built on
+//! it or alongside it.
+//!
+//! The crate only needs `alloc` when the default `std` feature is turned
+//! off. Without std, `HashMap` hashes with the in-crate SipHash-1-3 unless
+//! it is given another hasher with `with_hasher`. The parts that need
+//! threads, clocks, locks or floating point math are left out: `par`,
+//! `left_right`, `sketch`, `ttl`, the Bloom filter, rendezvous hashing and
+//! the `Sync` wrappers in `interner` and `once`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+use core::{
+    borrow::Borrow,
+    error, fmt,
+    hash::{BuildHasher, Hash},
     iter::FromIterator,
 };
-use std::{mem, ops};
+use core::{mem, ops};
+
+use allocator::{AllocVec, Allocator, Global};
+use hashers::DefaultHashBuilder;
+
+pub use allocator::TryReserveError;
+
+pub mod allocator;
+pub mod diff;
+pub mod filter;
+pub mod hashers;
+pub mod int;
+pub mod interner;
+mod layout;
+#[cfg(feature = "std")]
+pub mod left_right;
+pub mod once;
+#[cfg(feature = "std")]
+mod par;
+mod scan;
+pub mod sharding;
+#[cfg(feature = "std")]
+pub mod sketch;
+pub mod small;
+pub mod strategy;
+pub mod transaction;
+#[cfg(feature = "std")]
+pub mod ttl;
+pub mod weak;
 
 const INITIAL_NBUCKETS: usize = 1;
 
+/// Key equivalence for lookups.
+///
+/// The lookup methods take any `Q` that is `Equivalent` to the key type.
+/// Every `Q` that `K` can be borrowed as is covered by the blanket impl, so
+/// `&str` works for `String` keys as before. Other query types can opt in
+/// by implementing the trait, as long as they hash exactly like the key
+/// they are equivalent to:
+///
+/// ```
+/// use hashmap::{Equivalent, HashMap};
+///
+/// // look up (String, String) keys without allocating two Strings
+/// #[derive(Hash)]
+/// struct Pair<'a>(&'a str, &'a str);
+///
+/// impl Equivalent<(String, String)> for Pair<'_> {
+///     fn equivalent(&self, key: &(String, String)) -> bool {
+///         self.0 == key.0 && self.1 == key.1
+///     }
+/// }
+///
+/// let mut map = HashMap::new();
+/// map.insert(("foo".to_string(), "bar".to_string()), 42);
+/// assert_eq!(map.get(&Pair("foo", "bar")), Some(&42));
+/// ```
+pub trait Equivalent<K: ?Sized> {
+    fn equivalent(&self, key: &K) -> bool;
+}
+
+impl<Q, K> Equivalent<K> for Q
+where
+    Q: PartialEq + ?Sized,
+    K: Borrow<Q> + ?Sized,
+{
+    fn equivalent(&self, key: &K) -> bool {
+        self == key.borrow()
+    }
+}
+
+/// A hash map that chains colliding entries in per-bucket vectors.
+///
+/// A panic in a key's `Hash` or `PartialEq` impl, in a closure passed to a
+/// method, or in a key or value's `Drop` impl reaches the caller with the
+/// map still consistent: `len` matches the entries, no entry is dropped
+/// twice, and only entries the method was removing are lost. The keys and
+/// values passed to the panicking call are dropped.
+///
+/// All memory, the bucket array and every chain, comes from the allocator
+/// `A`. See the `allocator` module for a bump allocator that frees a whole
+/// map at once.
 #[derive(Debug)]
-pub struct HashMap<K, V> {
-    // each bucket is a Vec of (key, value) pairs
-    buckets: Vec<Vec<(K, V)>>,
+pub struct HashMap<K, V, S = DefaultHashBuilder, A: Allocator = Global> {
+    // each bucket is a vector of (key, value) pairs
+    buckets: AllocVec<AllocVec<(K, V), A>, A>,
     items: usize,
+    hash_builder: S,
 }
 
-impl<K, V> Default for HashMap<K, V> {
+impl<K, V, S: Default, A: Allocator + Default> Default for HashMap<K, V, S, A> {
     fn default() -> Self {
-        Self::new()
+        Self::with_hasher_in(S::default(), A::default())
     }
 }
 
 impl<K, V> HashMap<K, V> {
     pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+impl<K, V, A: Allocator> HashMap<K, V, DefaultHashBuilder, A> {
+    /// Create an empty map that allocates from `alloc`
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_hasher_in(DefaultHashBuilder::default(), alloc)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Create an empty map that hashes keys with hashers from `hash_builder`
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_hasher_in(hash_builder, Global)
+    }
+}
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    /// Create an empty map with both a hasher and an allocator
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> Self {
         Self {
-            buckets: Vec::new(),
+            buckets: AllocVec::new_in(alloc),
             items: 0,
+            hash_builder,
+        }
+    }
+
+    /// return the allocator the map allocates from
+    pub fn allocator(&self) -> &A {
+        self.buckets.allocator()
+    }
+
+    /// return the builder the map hashes keys with
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// return the number of elements in the map
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    /// return true if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the entries of the map in bucket order
+    pub fn iter(&self) -> HashMapIter<'_, K, V, A> {
+        HashMapIter::new(self)
+    }
+
+    /// Keep only the entries for which 'keep' returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let items = &mut self.items;
+        for bucket in self.buckets.iter_mut() {
+            // count as we go, so a panic in 'keep' leaves the count right
+            bucket.retain_mut(|(k, v)| {
+                let kept = keep(k, v);
+                *items -= !kept as usize;
+                kept
+            });
         }
     }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S, A> HashMap<K, V, S, A>
 where
     K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
 {
     pub fn bucket<Q>(&self, key: &Q) -> usize
     where
-        K: borrow::Borrow<Q>,
-        Q: Hash + PartialEq + ?Sized,
+        Q: Hash + ?Sized,
     {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        (hasher.finish() % self.buckets.len() as u64) as usize
+        self.bucket_in(key, self.buckets.len())
     }
 
-    pub fn resize(&mut self) {
-        let target_size = match self.buckets.len() {
+    fn bucket_in<Q>(&self, key: &Q, nbuckets: usize) -> usize
+    where
+        Q: Hash + ?Sized,
+    {
+        (self.hash_builder.hash_one(key) % nbuckets as u64) as usize
+    }
+
+    // If buckets is empty or if map is 3/4th full
+    fn needs_resize(&self) -> bool {
+        self.buckets.is_empty() || self.items > self.buckets.len() * 3 / 4
+    }
+
+    fn grown_size(&self) -> usize {
+        match self.buckets.len() {
             0 => INITIAL_NBUCKETS,
             n => 2 * n,
-        };
-        let mut new_buckets = Vec::with_capacity(target_size);
-        new_buckets.extend((0..target_size).map(|_| Vec::new()));
-        // Drain all elements from buckets and insert them into new_buckets
-        for (key, value) in self.buckets.drain(..).flatten() {
-            let mut hasher = DefaultHasher::new();
-            key.hash(&mut hasher);
-            let bucket = (hasher.finish() % new_buckets.len() as u64) as usize;
-            new_buckets[bucket].push((key, value));
         }
-        mem::swap(&mut self.buckets, &mut new_buckets);
+    }
+
+    pub fn resize(&mut self) {
+        let target_size = self.grown_size();
+        // Hash every key before moving any entry. Hashing is the only step
+        // that runs user code, so if it panics the map is left as it was.
+        let alloc = self.allocator().clone();
+        let mut targets = AllocVec::with_capacity_in(self.items, alloc.clone());
+        for (key, _) in self.buckets.iter().flatten() {
+            targets.push(self.bucket_in(key, target_size));
+        }
+        let mut new_buckets = AllocVec::with_capacity_in(target_size, alloc.clone());
+        for _ in 0..target_size {
+            new_buckets.push(AllocVec::new_in(alloc.clone()));
+        }
+        let old = mem::replace(&mut self.buckets, new_buckets);
+        for (entry, bucket) in old.into_iter().flatten().zip(targets.iter()) {
+            self.buckets[*bucket].push(entry);
+        }
+    }
+
+    /// Same as `resize`, but reports a failed allocation instead of
+    /// aborting. Every new bucket is allocated at its final size before
+    /// any entry moves, so on error the map is left as it was.
+    pub fn try_resize(&mut self) -> Result<(), TryReserveError> {
+        let target_size = self.grown_size();
+        let alloc = self.allocator().clone();
+        let mut targets = AllocVec::new_in(alloc.clone());
+        targets.try_reserve_exact(self.items)?;
+        let mut lens = AllocVec::new_in(alloc.clone());
+        lens.try_reserve_exact(target_size)?;
+        for _ in 0..target_size {
+            lens.push(0);
+        }
+        for (key, _) in self.buckets.iter().flatten() {
+            let bucket = self.bucket_in(key, target_size);
+            targets.push(bucket);
+            lens[bucket] += 1;
+        }
+        let mut new_buckets = AllocVec::new_in(alloc.clone());
+        new_buckets.try_reserve_exact(target_size)?;
+        for &len in lens.iter() {
+            let mut bucket = AllocVec::new_in(alloc.clone());
+            bucket.try_reserve_exact(len)?;
+            new_buckets.push(bucket);
+        }
+        // nothing below allocates; entries move in the same order as in resize
+        let old = mem::replace(&mut self.buckets, new_buckets);
+        for (entry, bucket) in old.into_iter().flatten().zip(targets.iter()) {
+            self.buckets[*bucket].push(entry);
+        }
+        Ok(())
     }
 
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        // If buckets is empty or if map is 3/4th full
-        if self.buckets.is_empty() || self.items > self.buckets.len() * 3 / 4 {
+        if self.needs_resize() {
             self.resize();
         }
         let bucket = self.bucket(&key);
         let bucket = &mut self.buckets[bucket];
 
-        self.items += 1;
         for &mut (ref ekey, ref mut evalue) in bucket.iter_mut() {
             if ekey == &key {
                 // return existing value
@@ -77,59 +286,100 @@ where
             }
         }
         bucket.push((key, value));
+        self.items += 1;
         None
     }
 
+    /// Insert `key` only if it is not in the map yet, returning a reference
+    /// to the new value. An existing value is never overwritten; the error
+    /// hands back the occupied entry along with the rejected value.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V>> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    /// Same as `insert`, but returns an error instead of aborting when the
+    /// map can't allocate room for the entry. On error the entries are
+    /// unchanged, though the table may have grown, and `key` and `value`
+    /// are dropped.
+    pub fn try_insert_reserve(&mut self, key: K, value: V) -> Result<Option<V>, TryReserveError> {
+        match self.try_entry(key)? {
+            Entry::Occupied(entry) => Ok(Some(mem::replace(entry.into_mut(), value))),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                Ok(None)
+            }
+        }
+    }
+
     /// Get ideally takes a reference to a key and returns a reference to the value
-    /// But in this case, it should take a 'Q' where 'K' can be borrowed as 'Q'
-    /// Q is Hash + PartialEq because K is Hash + PartialEq. Q is also ?Sized
-    /// so that the hashmap can support types such as &str as keys which is unsized
-    /// Now 'ekey' is of type 'K' and 'key' is of type 'Q'. So borrow 'ekey' as'Q'
-    /// in order to compare them.
+    /// But in this case, it should take a 'Q' that is 'Equivalent' to 'K'
+    /// This covers every 'Q' where 'K' can be borrowed as 'Q' and any other
+    /// query type that implements 'Equivalent<K>'. Q is also ?Sized so that
+    /// the hashmap can support types such as &str as keys which is unsized
+    /// Now 'ekey' is of type 'K' and 'key' is of type 'Q'. So ask 'key' if it
+    /// is equivalent to 'ekey' in order to compare them.
     pub fn get<Q>(&self, key: &Q) -> Option<&V>
     where
-        K: borrow::Borrow<Q>,
-        Q: Hash + PartialEq + ?Sized,
+        Q: Hash + Equivalent<K> + ?Sized,
     {
+        if self.buckets.is_empty() {
+            return None;
+        }
         let bucket = self.bucket(key);
         self.buckets[bucket]
             .iter()
-            .find(|(ref ekey, _)| ekey.borrow() == key)
+            .find(|(ref ekey, _)| key.equivalent(ekey))
             .map(|(_, ref v)| v)
     }
 
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(key);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(ref ekey, _)| key.equivalent(ekey))
+            .map(|(_, ref mut v)| v)
+    }
+
     pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
-        K: borrow::Borrow<Q>,
-        Q: Hash + PartialEq + ?Sized,
+        Q: Hash + Equivalent<K> + ?Sized,
     {
         self.get(key).is_some()
     }
 
     pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
     where
-        K: borrow::Borrow<Q>,
-        Q: Hash + PartialEq + ?Sized,
+        Q: Hash + Equivalent<K> + ?Sized,
     {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    /// Remove the entry for 'key' and return the stored key with its value
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
         let bucket = self.bucket(key);
         let bucket = &mut self.buckets[bucket];
         let i = bucket
             .iter()
-            .position(|(ref ekey, _)| ekey.borrow() == key)?;
+            .position(|(ref ekey, _)| key.equivalent(ekey))?;
         // use swap_remove to avoid shifting elements
-        let val = bucket.swap_remove(i).1;
+        let entry = bucket.swap_remove(i);
         self.items -= 1;
-        Some(val)
-    }
-
-    /// return the number of elements in the map
-    pub fn len(&self) -> usize {
-        self.items
-    }
-
-    /// return true if the map is empty
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        Some(entry)
     }
 }
 
@@ -137,9 +387,9 @@ where
 /// inserted in the map. Entry is an enum with two variants:
 /// 1. Vacant - when the key is not present in the map
 /// 2. Occupied - when the key is present in the map
-pub enum Entry<'a, K, V> {
+pub enum Entry<'a, K, V, A: Allocator = Global> {
     Occupied(OccupiedEntry<'a, K, V>),
-    Vacant(VacantEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V, A>),
 }
 
 /// OccupiedEntry is returned when the key is present in the map
@@ -149,16 +399,89 @@ pub struct OccupiedEntry<'a, K, V> {
 }
 
 /// VacantEntry is returned when the key is not present in the map
-/// It contains a mutable reference to the map and the bucket where
-/// the element is inserted. It will also keep the key to be inserted
+/// It contains a mutable reference to the bucket where the element is
+/// inserted and to the map's item count. It will also keep the key to be inserted
 /// because the key needed when the entry is inserted later.
-pub struct VacantEntry<'a, K: 'a, V: 'a> {
+pub struct VacantEntry<'a, K: 'a, V: 'a, A: Allocator = Global> {
     key: K,
-    map: &'a mut HashMap<K, V>,
-    bucket: usize,
+    bucket: &'a mut AllocVec<(K, V), A>,
+    items: &'a mut usize,
 }
 
-impl<'a, K, V> VacantEntry<'a, K, V> {
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    /// The key stored in the map
+    pub fn key(&self) -> &K {
+        &self.entry.0
+    }
+
+    /// The stored key, for swapping in one that hashes and compares the
+    /// same
+    pub(crate) fn key_mut(&mut self) -> &mut K {
+        &mut self.entry.0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.entry.1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.entry.1
+    }
+
+    /// Consumes self and returns a reference to the value that lives as
+    /// long as the borrow of the map
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.entry.1
+    }
+
+    /// Replace the value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(&mut self.entry.1, value)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OccupiedEntry<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+/// Returned by `try_insert` when the key is already in the map
+pub struct OccupiedError<'a, K, V> {
+    /// The entry that is in the way
+    pub entry: OccupiedEntry<'a, K, V>,
+    /// The value that was not inserted
+    pub value: V,
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.entry.key())
+            .field("old_value", self.entry.get())
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Display for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value,
+            self.entry.key(),
+            self.entry.get(),
+        )
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> error::Error for OccupiedError<'_, K, V> {}
+
+impl<'a, K, V, A: Allocator> VacantEntry<'a, K, V, A> {
     /// Consumes self and inserts the key-value pair into the map
     /// Returns a mutable reference to the value so that it can be
     /// modified later as part of the entry API where this is used.
@@ -166,14 +489,14 @@ impl<'a, K, V> VacantEntry<'a, K, V> {
     where
         K: Hash + PartialEq,
     {
-        self.map.buckets[self.bucket].push((self.key, value));
-        self.map.items += 1;
+        self.bucket.push((self.key, value));
+        *self.items += 1;
         // unwrap is safe because we just inserted the value
-        &mut self.map.buckets[self.bucket].last_mut().unwrap().1
+        &mut self.bucket.last_mut().unwrap().1
     }
 }
 
-impl<'a, K, V> Entry<'a, K, V>
+impl<'a, K, V, A: Allocator> Entry<'a, K, V, A>
 where
     K: Hash + PartialEq,
 {
@@ -214,39 +537,67 @@ where
     }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S, A> HashMap<K, V, S, A>
 where
     K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
 {
-    pub fn entry(&mut self, key: K) -> Entry<K, V> {
-        if self.buckets.is_empty() || self.items > self.buckets.len() * 3 / 4 {
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A> {
+        if self.needs_resize() {
             self.resize();
         }
-        let bucket = self.bucket(&key);
-        // Find an element in the bucket that matches the key and return
-        // mutable reference to it if found. If not found, return a Vacant
-        // entry with the key and mutable reference to the bucket.
-        match self.buckets[bucket]
-            .iter_mut()
-            .find(|(ref ekey, _)| ekey == &key)
-        {
-            Some(entry) => Entry::Occupied(OccupiedEntry {
-                // use unsafe to avoid borrowing issues
-                entry: unsafe { mem::transmute(entry) },
+        let (bucket, position) = self.position(&key);
+        self.entry_at(key, bucket, position)
+    }
+
+    /// Same as `entry`, but returns an error instead of aborting when the
+    /// map can't grow. Room for the key is reserved up front, so inserting
+    /// into a vacant entry doesn't allocate.
+    pub fn try_entry(&mut self, key: K) -> Result<Entry<'_, K, V, A>, TryReserveError> {
+        if self.needs_resize() {
+            self.try_resize()?;
+        }
+        let (bucket, position) = self.position(&key);
+        if position.is_none() {
+            self.buckets[bucket].try_reserve(1)?;
+        }
+        Ok(self.entry_at(key, bucket, position))
+    }
+
+    /// The bucket `key` belongs in, and where it is in that bucket if the
+    /// map has it
+    fn position(&self, key: &K) -> (usize, Option<usize>) {
+        let bucket = self.bucket(key);
+        let position = self.buckets[bucket]
+            .iter()
+            .position(|(ref ekey, _)| ekey == key);
+        (bucket, position)
+    }
+
+    /// The entry for `key`, given what `position` found for it. Occupied
+    /// entries borrow the matching element, vacant ones the bucket to push
+    /// the key onto.
+    fn entry_at(&mut self, key: K, bucket: usize, position: Option<usize>) -> Entry<'_, K, V, A> {
+        match position {
+            Some(i) => Entry::Occupied(OccupiedEntry {
+                entry: &mut self.buckets[bucket][i],
             }),
             None => Entry::Vacant(VacantEntry {
                 key,
-                map: self,
-                bucket,
+                bucket: &mut self.buckets[bucket],
+                items: &mut self.items,
             }),
         }
     }
 }
 
-impl<K, Q: ?Sized, V> ops::Index<&Q> for HashMap<K, V>
+impl<K, Q: ?Sized, V, S, A> ops::Index<&Q> for HashMap<K, V, S, A>
 where
-    K: Eq + Hash + borrow::Borrow<Q>,
-    Q: Eq + Hash,
+    K: Eq + Hash,
+    Q: Hash + Equivalent<K>,
+    S: BuildHasher,
+    A: Allocator + Clone,
 {
     type Output = V;
 
@@ -256,29 +607,29 @@ where
 }
 
 /// Iterator for HashMap
-pub struct HashMapIter<'a, K, V> {
-    map: &'a HashMap<K, V>,
+pub struct HashMapIter<'a, K, V, A: Allocator = Global> {
+    buckets: &'a [AllocVec<(K, V), A>],
     bucket: usize,
     at: usize,
 }
 
-impl<'a, K, V> HashMapIter<'a, K, V> {
-    fn new(map: &'a HashMap<K, V>) -> Self {
+impl<'a, K, V, A: Allocator> HashMapIter<'a, K, V, A> {
+    fn new<S>(map: &'a HashMap<K, V, S, A>) -> Self {
         Self {
-            map,
+            buckets: &map.buckets,
             bucket: 0,
             at: 0,
         }
     }
 }
 
-impl<'a, K, V> Iterator for HashMapIter<'a, K, V> {
+impl<'a, K, V, A: Allocator> Iterator for HashMapIter<'a, K, V, A> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
         // Loop is required since the bucket list can be sparse
         loop {
-            match self.map.buckets.get(self.bucket) {
+            match self.buckets.get(self.bucket) {
                 Some(bucket) => match bucket.get(self.at) {
                     Some((ref k, ref v)) => {
                         self.at += 1;
@@ -300,9 +651,9 @@ impl<'a, K, V> Iterator for HashMapIter<'a, K, V> {
 
 /// Implement IntoIterator for HashMap to allow iterating over
 /// the map using a reference to the map.
-impl<'a, K, V> IntoIterator for &'a HashMap<K, V> {
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a HashMap<K, V, S, A> {
     type Item = (&'a K, &'a V);
-    type IntoIter = HashMapIter<'a, K, V>;
+    type IntoIter = HashMapIter<'a, K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
         HashMapIter::new(self)
@@ -310,16 +661,16 @@ impl<'a, K, V> IntoIterator for &'a HashMap<K, V> {
 }
 
 /// Owned iterator for HashMap
-pub struct HashMapIntoIter<K, V> {
-    map: HashMap<K, V>,
+pub struct HashMapIntoIter<K, V, A: Allocator = Global> {
+    buckets: AllocVec<AllocVec<(K, V), A>, A>,
     bucket: usize,
 }
 
-impl<K, V> Iterator for HashMapIntoIter<K, V> {
+impl<K, V, A: Allocator> Iterator for HashMapIntoIter<K, V, A> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(bucket) = self.map.buckets.get_mut(self.bucket) {
+        while let Some(bucket) = self.buckets.get_mut(self.bucket) {
             if !bucket.is_empty() {
                 return Some(bucket.swap_remove(0));
             }
@@ -330,13 +681,13 @@ impl<K, V> Iterator for HashMapIntoIter<K, V> {
     }
 }
 
-impl<K, V> IntoIterator for HashMap<K, V> {
+impl<K, V, S, A: Allocator> IntoIterator for HashMap<K, V, S, A> {
     type Item = (K, V);
-    type IntoIter = HashMapIntoIter<K, V>;
+    type IntoIter = HashMapIntoIter<K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
         HashMapIntoIter {
-            map: self,
+            buckets: self.buckets,
             bucket: 0,
         }
     }
@@ -345,15 +696,17 @@ impl<K, V> IntoIterator for HashMap<K, V> {
 /// Implement FromIterator for HashMap
 /// This allows us to collect an iterator of key-value pairs into a HashMap
 /// The from_iter takes a generic iterator that produces (K, V) pairs.
-impl<K, V> FromIterator<(K, V)> for HashMap<K, V>
+impl<K, V, S, A> FromIterator<(K, V)> for HashMap<K, V, S, A>
 where
     K: Hash + PartialEq,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
 {
     fn from_iter<T>(iter: T) -> Self
     where
         T: IntoIterator<Item = (K, V)>,
     {
-        let mut map = HashMap::new();
+        let mut map = HashMap::default();
         for (k, v) in iter {
             map.insert(k, v);
         }
@@ -364,6 +717,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::format;
 
     #[test]
     fn test_insert() {
@@ -378,7 +732,77 @@ mod tests {
         map.insert("foo", 42);
         assert_eq!(map.get(&"foo"), Some(&42));
         assert_eq!(map.get(&"bar"), None);
+        *map.get_mut("foo").unwrap() += 1;
+        assert_eq!(map.get(&"foo"), Some(&43));
+    }
+    #[test]
+    fn test_try_insert() {
+        let mut map = HashMap::new();
+        *map.try_insert("foo", 42).unwrap() += 1;
+        let err = map.try_insert("foo", 7).unwrap_err();
+        assert_eq!(
+            (err.entry.key(), err.entry.get(), err.value),
+            (&"foo", &43, 7)
+        );
+        assert_eq!(
+            format!("{err}"),
+            "failed to insert 7, key \"foo\" already exists with value 43"
+        );
+        assert_eq!(map.get("foo"), Some(&43));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_try_insert_reserve_matches_insert() {
+        let mut expected = HashMap::new();
+        let mut map = HashMap::new();
+        for i in 0..200 {
+            assert_eq!(
+                map.try_insert_reserve(i % 150, i),
+                Ok(expected.insert(i % 150, i))
+            );
+        }
+        assert_eq!(map.len(), 150);
+        assert_eq!(map.buckets, expected.buckets);
+        let mut entry = match map.try_entry(3).unwrap() {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(_) => unreachable!(),
+        };
+        assert_eq!(entry.insert(0), 153);
+        assert_eq!(map.try_entry(1000).unwrap().or_insert(1), &1);
+    }
+
+    #[test]
+    fn test_empty_lookups() {
+        let mut map: HashMap<&str, i32> = HashMap::new();
+        assert_eq!(map.get("foo"), None);
+        assert_eq!(map.get_mut("foo"), None);
+        assert!(!map.contains_key("foo"));
+        assert_eq!(map.remove("foo"), None);
     }
+
+    #[test]
+    fn test_equivalent_lookups() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct UserId(u64);
+
+        // a newtype key can be looked up by its inner value
+        impl Equivalent<UserId> for u64 {
+            fn equivalent(&self, key: &UserId) -> bool {
+                *self == key.0
+            }
+        }
+
+        let mut map = HashMap::new();
+        map.insert(UserId(7), "alice");
+        map.insert(UserId(9), "bob");
+        assert_eq!(map.get(&7u64), Some(&"alice"));
+        assert!(map.contains_key(&9u64));
+        assert_eq!(map[&9u64], "bob");
+        assert_eq!(map.remove(&7u64), Some("alice"));
+        assert_eq!(map.get(&UserId(7)), None);
+    }
+
     #[test]
     fn test_contains_key() {
         let mut map = HashMap::new();
@@ -397,6 +821,18 @@ mod tests {
         assert_eq!(map.remove(&"foo"), None);
         assert_eq!(map.get(&"foo"), None);
     }
+    #[test]
+    fn test_retain() {
+        let mut map: HashMap<i32, i32> = (0..100).map(|i| (i, i)).collect();
+        map.retain(|k, v| {
+            *v += 1;
+            k % 3 == 0
+        });
+        assert_eq!(map.len(), 34);
+        assert_eq!(map.get(&3), Some(&4));
+        assert_eq!(map.get(&4), None);
+    }
+
     #[test]
     fn test_len() {
         let mut map = HashMap::new();
@@ -406,6 +842,8 @@ mod tests {
         assert_eq!(map.len(), 1);
         map.insert("bar", 23);
         assert_eq!(map.len(), 2);
+        map.insert("bar", 24);
+        assert_eq!(map.len(), 2);
         map.remove(&"foo");
         assert_eq!(map.len(), 1);
         assert!(!map.is_empty());
@@ -0,0 +1,355 @@
+//! Append-only maps that insert through a shared reference.
+//!
+//! Values live in their own heap allocations, and entries can only be
+//! removed through `&mut self`, so a value never moves or gets dropped
+//! while the map is borrowed. That makes it sound to hand out `&V` with
+//! the lifetime of `&self` even though other inserts may resize the table
+//! underneath. This is what a memoization cache needs:
+//!
+//! ```
+//! use hashmap::once::OnceMap;
+//!
+//! let squares = OnceMap::new();
+//! let four: &u64 = squares.get_or_insert_with(2, || 2 * 2);
+//! squares.get_or_insert_with(3, || 3 * 3);
+//! assert_eq!(*four, 4);
+//! ```
+
+use alloc::boxed::Box;
+use core::{cell::RefCell, hash::Hash, mem::ManuallyDrop, ptr::NonNull};
+#[cfg(feature = "std")]
+use std::sync::{PoisonError, RwLock};
+
+use crate::{Equivalent, HashMap};
+
+/// An owned heap allocation for one value.
+///
+/// This is a `Box` that has been turned into a raw pointer. Moving a `Box`
+/// asserts unique ownership of its contents, which would invalidate the
+/// `&V`s already handed out whenever a resize moves the entries. Moving a
+/// raw pointer asserts nothing.
+struct Slot<V>(NonNull<V>);
+
+// SAFETY: a Slot owns its value just like Box<V> does
+unsafe impl<V: Send> Send for Slot<V> {}
+unsafe impl<V: Sync> Sync for Slot<V> {}
+
+impl<V> Slot<V> {
+    fn new(value: V) -> Self {
+        Slot(NonNull::from(Box::leak(Box::new(value))))
+    }
+
+    /// Borrow the value for as long as the map lives.
+    ///
+    /// # Safety
+    ///
+    /// The slot must be owned by a map that only removes or drops entries
+    /// through `&mut self` or by being dropped itself.
+    unsafe fn detach<'a>(&self) -> &'a V {
+        self.0.as_ref()
+    }
+
+    fn into_inner(self) -> V {
+        let slot = ManuallyDrop::new(self);
+        // SAFETY: the pointer came from Box::leak and is not freed by Drop
+        *unsafe { Box::from_raw(slot.0.as_ptr()) }
+    }
+}
+
+impl<V> Drop for Slot<V> {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from Box::leak and is only freed here
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+/// Single-threaded append-only map
+pub struct OnceMap<K, V> {
+    map: RefCell<HashMap<K, Slot<V>>>,
+}
+
+impl<K, V> Default for OnceMap<K, V> {
+    fn default() -> Self {
+        Self {
+            map: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + PartialEq, V> OnceMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let map = self.map.borrow();
+        // SAFETY: the slot stays in the map until `&mut self` is available
+        map.get(key).map(|value| unsafe { value.detach() })
+    }
+
+    /// Return the value for `key`, calling `f` to create it if it is
+    /// missing.
+    ///
+    /// `f` may itself use the map, for example to memoize a recursive
+    /// function. If it inserts `key` along the way, that value is kept and
+    /// the one `f` returned is dropped.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        // no borrow is held while f runs, so it can re-enter the map
+        let value = Slot::new(f());
+        let mut map = self.map.borrow_mut();
+        let value = map.entry(key).or_insert(value);
+        // SAFETY: as in get
+        unsafe { value.detach() }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.map.borrow().contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove an entry. This needs `&mut self`, so no reference into the
+    /// map can be alive.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.map.get_mut().remove(key).map(Slot::into_inner)
+    }
+
+    pub fn into_map(self) -> HashMap<K, V> {
+        self.map
+            .into_inner()
+            .into_iter()
+            .map(|(k, v)| (k, v.into_inner()))
+            .collect()
+    }
+}
+
+/// Append-only map that can be shared between threads.
+///
+/// Lookups of existing keys only take a read lock. The lock is not held
+/// while `get_or_insert_with` runs `f`, so two threads that miss on the
+/// same key at the same time may both compute a value; the first one
+/// inserted wins and both callers get it.
+#[cfg(feature = "std")]
+pub struct SyncOnceMap<K, V> {
+    map: RwLock<HashMap<K, Slot<V>>>,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Default for SyncOnceMap<K, V> {
+    fn default() -> Self {
+        Self {
+            map: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Hash + PartialEq, V> SyncOnceMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let map = self.map.read().unwrap_or_else(PoisonError::into_inner);
+        // SAFETY: the slot stays in the map until `&mut self` is available
+        map.get(key).map(|value| unsafe { value.detach() })
+    }
+
+    /// Return the value for `key`, calling `f` to create it if it is
+    /// missing. See the type docs for what happens when threads race.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = Slot::new(f());
+        let mut map = self.map.write().unwrap_or_else(PoisonError::into_inner);
+        let value = map.entry(key).or_insert(value);
+        // SAFETY: as in get
+        unsafe { value.detach() }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.map
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key)
+            .map(Slot::into_inner)
+    }
+
+    pub fn into_map(self) -> HashMap<K, V> {
+        self.map
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .into_iter()
+            .map(|(k, v)| (k, v.into_inner()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::cell::Cell;
+
+    #[test]
+    fn test_references_survive_resizes() {
+        let map = OnceMap::new();
+        let first = map.get_or_insert_with(0, || "zero".to_string());
+        let addr = first as *const String;
+        // many inserts through &self grow the table several times
+        let refs: Vec<&String> = (1..1000)
+            .map(|i| map.get_or_insert_with(i, || i.to_string()))
+            .collect();
+        assert_eq!(first, "zero");
+        assert_eq!(map.get(&0).unwrap() as *const String, addr);
+        assert_eq!(refs[498], "499");
+        assert_eq!(map.len(), 1000);
+    }
+
+    // Mostly there for `cargo miri test`: a reference taken before the table
+    // grows must still be usable after the entries have been moved.
+    #[test]
+    fn test_reference_held_across_resize() {
+        let map = OnceMap::new();
+        let first: &String = map.get_or_insert_with(0, || "zero".to_string());
+        let nbuckets = map.map.borrow().buckets.len();
+        let mut i = 1;
+        while map.map.borrow().buckets.len() == nbuckets {
+            map.get_or_insert_with(i, || i.to_string());
+            i += 1;
+        }
+        assert_eq!(first, "zero");
+        let mut map = map;
+        assert_eq!(map.remove(&0).as_deref(), Some("zero"));
+    }
+
+    #[test]
+    fn test_insert_once() {
+        let map = OnceMap::new();
+        let calls = Cell::new(0);
+        for _ in 0..3 {
+            let value = map.get_or_insert_with("key", || {
+                calls.set(calls.get() + 1);
+                42
+            });
+            assert_eq!(*value, 42);
+        }
+        assert_eq!(calls.get(), 1);
+    }
+
+    fn fib(memo: &OnceMap<u64, u64>, n: u64) -> u64 {
+        if n < 2 {
+            return n;
+        }
+        *memo.get_or_insert_with(n, || fib(memo, n - 1) + fib(memo, n - 2))
+    }
+
+    #[test]
+    fn test_reentrant_memoization() {
+        let memo = OnceMap::new();
+        assert_eq!(fib(&memo, 90), 2_880_067_194_370_816_120);
+        assert_eq!(memo.len(), 89);
+        let mut memo = memo;
+        assert_eq!(memo.remove(&90), Some(2_880_067_194_370_816_120));
+        assert_eq!(memo.into_map().len(), 88);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sync_once_map() {
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Barrier,
+            },
+            thread,
+        };
+
+        let map = SyncOnceMap::new();
+        let calls = AtomicUsize::new(0);
+        let barrier = Barrier::new(8);
+        let value = |i: usize| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            format!("value{i}")
+        };
+        let results: Vec<Vec<&String>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|t| {
+                    let (map, barrier) = (&map, &barrier);
+                    s.spawn(move || {
+                        // every thread inserts its own keys, growing the
+                        // table while the others read and insert...
+                        for i in (t..200).step_by(8) {
+                            map.get_or_insert_with(i, || value(i));
+                        }
+                        barrier.wait();
+                        // ...then every key is already there
+                        (0..200)
+                            .map(|i| map.get_or_insert_with(i, || value(i)))
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(map.len(), 200);
+        assert_eq!(calls.load(Ordering::Relaxed), 200);
+        for refs in &results {
+            for (i, value) in refs.iter().enumerate() {
+                assert!(std::ptr::eq(*value, map.get(&i).unwrap()));
+            }
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Bulk operations that spread the work over scoped threads.
+//!
+//! Every method takes the number of threads to use and leaves the map
+//! exactly as the sequential version would: the same entries, the same
+//! number of buckets and the same order inside each bucket, so even the
+//! iteration order matches.
+//!
+//! `par_from_iter` and `par_extend` partition the entries by the low bits
+//! of their hash. The bucket count is a power of two and an entry lands in
+//! bucket `hash % buckets`, so once the final size is known each partition
+//! owns every `parts`th bucket and fills them without looking at the
+//! others.
+
+use std::{
+    any::Any,
+    hash::{BuildHasher, Hash},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, ScopedJoinHandle},
+};
+
+use crate::{
+    allocator::{AllocVec, Allocator},
+    HashMap, INITIAL_NBUCKETS,
+};
+
+/// An entry on its way into the table, with its position in the input
+struct Pending<K, V> {
+    index: usize,
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// Join a worker, passing its panic on to the caller
+fn join<T>(handle: ScopedJoinHandle<'_, T>) -> T {
+    handle.join().unwrap_or_else(|e| panic::resume_unwind(e))
+}
+
+/// Number of buckets `insert` ends up with when it starts from `buckets`
+/// and the last call finds `items` entries already in the map
+fn table_size(buckets: usize, items: usize) -> usize {
+    let mut n = match buckets {
+        0 => INITIAL_NBUCKETS,
+        n => n,
+    };
+    while items > n * 3 / 4 {
+        n *= 2;
+    }
+    n
+}
+
+/// Every entry handed to `dedup` or `build`, given back untouched after a
+/// key's `PartialEq` impl panicked
+struct Unwound<K, V> {
+    entries: Vec<Pending<K, V>>,
+    payload: Box<dyn Any + Send>,
+}
+
+/// The entries of a partition, with repeated keys found but not yet
+/// dropped
+struct Deduped<K, V> {
+    /// The first occurrence of each key
+    kept: Vec<Pending<K, V>>,
+    /// Later occurrences, each with the position of the first one in `kept`
+    repeats: Vec<(usize, Pending<K, V>)>,
+}
+
+impl<K, V> Deduped<K, V> {
+    /// Give the first occurrence of each key the value of the last one, as
+    /// repeated inserts would. Returns the survivors in input order.
+    fn take_last_values(self) -> Vec<Pending<K, V>> {
+        let mut kept = self.kept;
+        // repeats of the same key come in input order, so the last one wins
+        for (i, repeat) in self.repeats {
+            kept[i].value = repeat.value;
+        }
+        kept.sort_unstable_by_key(|e| e.index);
+        kept
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = Pending<K, V>> {
+        let repeats = self.repeats.into_iter().map(|(_, e)| e);
+        self.kept.into_iter().chain(repeats)
+    }
+}
+
+/// Find the first occurrence of each key. Nothing is dropped or changed
+/// yet, so that if a `PartialEq` impl panics every entry can be given back.
+fn dedup<K: PartialEq, V>(runs: Vec<Vec<Pending<K, V>>>) -> Result<Deduped<K, V>, Unwound<K, V>> {
+    let mut entries: Vec<_> = runs.into_iter().flatten().collect();
+    entries.sort_unstable_by_key(|e| (e.hash, e.index));
+    let mut kept: Vec<Pending<K, V>> = Vec::with_capacity(entries.len());
+    let mut repeats = Vec::new();
+    // start of the run of equal hashes at the end of kept
+    let mut group = 0;
+    let mut entries = entries.into_iter();
+    while let Some(entry) = entries.next() {
+        if kept.last().is_some_and(|last| last.hash != entry.hash) {
+            group = kept.len();
+        }
+        let found = panic::catch_unwind(AssertUnwindSafe(|| {
+            kept[group..].iter().position(|e| e.key == entry.key)
+        }));
+        match found {
+            Ok(Some(i)) => repeats.push((group + i, entry)),
+            Ok(None) => kept.push(entry),
+            Err(payload) => {
+                let entries = Deduped { kept, repeats }
+                    .into_entries()
+                    .chain(Some(entry))
+                    .chain(entries)
+                    .collect();
+                return Err(Unwound { entries, payload });
+            }
+        }
+    }
+    Ok(Deduped { kept, repeats })
+}
+
+/// Split `items` into owned chunks of `chunk_len`, which is not zero. The
+/// splits start from the back so that each one only moves the chunk.
+fn split<T>(items: Vec<T>, chunk_len: usize) -> Vec<Vec<T>> {
+    let mut chunks = Vec::with_capacity(items.len().div_ceil(chunk_len));
+    let mut rest = items;
+    while rest.len() > chunk_len {
+        let at = (rest.len() - 1) / chunk_len * chunk_len;
+        chunks.push(rest.split_off(at));
+    }
+    chunks.push(rest);
+    chunks.reverse();
+    chunks
+}
+
+/// Hash the keys of `entries`, which is not empty, on up to `threads`
+/// threads. The entries keep their order.
+fn hash_all<K, V, S>(entries: Vec<(K, V)>, threads: usize, hasher: &S) -> Vec<(u64, K, V)>
+where
+    K: Hash + Send,
+    V: Send,
+    S: BuildHasher + Sync,
+{
+    let chunk_len = entries.len().div_ceil(threads);
+    thread::scope(|s| {
+        let handles: Vec<_> = split(entries, chunk_len)
+            .into_iter()
+            .map(|chunk| {
+                s.spawn(move || {
+                    let hashed = chunk.into_iter().map(|(k, v)| (hasher.hash_one(&k), k, v));
+                    hashed.collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(join).collect()
+    })
+}
+
+/// The bucket array of a `HashMap`
+type Buckets<K, V, A> = AllocVec<AllocVec<(K, V), A>, A>;
+
+/// The buckets of a `HashMap` with the number of entries in them
+type Table<K, V, A> = (Buckets<K, V, A>, usize);
+
+fn empty_buckets<K, V, A: Allocator + Clone>(n: usize, alloc: &A) -> Buckets<K, V, A> {
+    let mut buckets = AllocVec::with_capacity_in(n, alloc.clone());
+    for _ in 0..n {
+        buckets.push(AllocVec::new_in(alloc.clone()));
+    }
+    buckets
+}
+
+/// Build the buckets for the hashed `entries`, which is not empty, as if
+/// they were inserted one by one into a map with `buckets` buckets.
+/// Returns them with the number of entries left after dropping repeated
+/// keys, or every entry if a key's `PartialEq` impl panicked.
+fn build<K, V, A>(
+    buckets: usize,
+    entries: Vec<(u64, K, V)>,
+    threads: usize,
+    alloc: A,
+) -> Result<Table<K, V, A>, Unwound<K, V>>
+where
+    K: PartialEq + Send,
+    V: Send,
+    A: Allocator + Clone + Send,
+{
+    let total = entries.len();
+    let parts = 1 << threads.ilog2();
+    let chunk_len = total.div_ceil(threads);
+
+    // sort every entry into its partition
+    let scattered: Vec<Vec<Vec<Pending<K, V>>>> = thread::scope(|s| {
+        let handles: Vec<_> = split(entries, chunk_len)
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                s.spawn(move || {
+                    let mut out: Vec<Vec<_>> = (0..parts).map(|_| Vec::new()).collect();
+                    for (offset, (hash, key, value)) in chunk.into_iter().enumerate() {
+                        out[(hash % parts as u64) as usize].push(Pending {
+                            index: i * chunk_len + offset,
+                            hash,
+                            key,
+                            value,
+                        });
+                    }
+                    out
+                })
+            })
+            .collect();
+        handles.into_iter().map(join).collect()
+    });
+    let mut runs: Vec<Vec<Vec<Pending<K, V>>>> = (0..parts).map(|_| Vec::new()).collect();
+    for chunk in scattered {
+        for (part, run) in chunk.into_iter().enumerate() {
+            runs[part].push(run);
+        }
+    }
+
+    let deduped: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = runs
+            .into_iter()
+            .map(|runs| s.spawn(|| dedup(runs)))
+            .collect();
+        handles.into_iter().map(join).collect()
+    });
+    // values are only replaced once every partition got through without a
+    // panic, until then all entries can still be given back as they came
+    if deduped.iter().any(Result::is_err) {
+        let mut entries = Vec::with_capacity(total);
+        let mut payload = None;
+        for result in deduped {
+            match result {
+                Ok(deduped) => entries.extend(deduped.into_entries()),
+                Err(unwound) => {
+                    entries.extend(unwound.entries);
+                    payload.get_or_insert(unwound.payload);
+                }
+            }
+        }
+        let payload = payload.expect("a partition panicked");
+        return Err(Unwound { entries, payload });
+    }
+    let kept: Vec<Vec<Pending<K, V>>> = thread::scope(|s| {
+        let handles: Vec<_> = deduped
+            .into_iter()
+            .map(|result| match result {
+                Ok(deduped) => s.spawn(|| deduped.take_last_values()),
+                Err(_) => unreachable!("checked above"),
+            })
+            .collect();
+        handles.into_iter().map(join).collect()
+    });
+    let items: usize = kept.iter().map(Vec::len).sum();
+    // the last insert grows the table with the items already in the map,
+    // which excludes its own key unless that key was seen before
+    let last_is_new = kept.iter().flatten().any(|e| e.index == total - 1);
+    let n = table_size(buckets, items - last_is_new as usize);
+
+    if n < parts {
+        // too few buckets to go around, and too few items to care
+        let mut entries: Vec<_> = kept.into_iter().flatten().collect();
+        entries.sort_unstable_by_key(|e| e.index);
+        let mut buckets = empty_buckets(n, &alloc);
+        for e in entries {
+            buckets[(e.hash % n as u64) as usize].push((e.key, e.value));
+        }
+        return Ok((buckets, items));
+    }
+
+    // partition p fills buckets p, p + parts, p + 2 * parts, ...
+    let owned: Vec<Vec<AllocVec<(K, V), A>>> = thread::scope(|s| {
+        let handles: Vec<_> = kept
+            .into_iter()
+            .map(|entries| {
+                let alloc = alloc.clone();
+                s.spawn(move || {
+                    let mut buckets: Vec<_> = (0..n / parts)
+                        .map(|_| AllocVec::new_in(alloc.clone()))
+                        .collect();
+                    for e in entries {
+                        let bucket = (e.hash % n as u64) as usize / parts;
+                        buckets[bucket].push((e.key, e.value));
+                    }
+                    buckets
+                })
+            })
+            .collect();
+        handles.into_iter().map(join).collect()
+    });
+    let mut owned: Vec<_> = owned.into_iter().map(Vec::into_iter).collect();
+    let mut buckets = AllocVec::with_capacity_in(n, alloc);
+    for b in 0..n {
+        buckets.push(owned[b % parts].next().expect("partition owns this bucket"));
+    }
+    Ok((buckets, items))
+}
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    /// Call `f` on every entry, using up to `threads` threads. Entries are
+    /// visited in no particular order.
+    pub fn par_for_each<F>(&self, threads: usize, f: F)
+    where
+        K: Sync,
+        V: Sync,
+        A: Sync,
+        F: Fn(&K, &V) + Sync,
+    {
+        if self.buckets.is_empty() {
+            return;
+        }
+        let chunk_len = self.buckets.len().div_ceil(threads.max(1));
+        let f = &f;
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .buckets
+                .chunks(chunk_len)
+                .map(|chunk| s.spawn(move || chunk.iter().flatten().for_each(|(k, v)| f(k, v))))
+                .collect();
+            handles.into_iter().for_each(join);
+        });
+    }
+
+    /// Same as `retain`, using up to `threads` threads. `keep` may be
+    /// called on the entries in any order.
+    pub fn par_retain<F>(&mut self, threads: usize, keep: F)
+    where
+        K: Send,
+        V: Send,
+        A: Send,
+        F: Fn(&K, &mut V) -> bool + Sync,
+    {
+        if self.buckets.is_empty() {
+            return;
+        }
+        let chunk_len = self.buckets.len().div_ceil(threads.max(1));
+        let keep = &keep;
+        // shared so the removals of a worker that panics are still counted
+        let removed = &AtomicUsize::new(0);
+        let buckets = &mut self.buckets;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            thread::scope(|s| {
+                let handles: Vec<_> = buckets
+                    .chunks_mut(chunk_len)
+                    .map(|chunk| {
+                        s.spawn(move || {
+                            for bucket in chunk {
+                                bucket.retain_mut(|(k, v)| {
+                                    let kept = keep(k, v);
+                                    if !kept {
+                                        removed.fetch_add(1, Ordering::Relaxed);
+                                    }
+                                    kept
+                                });
+                            }
+                        })
+                    })
+                    .collect();
+                handles.into_iter().for_each(join);
+            })
+        }));
+        self.items -= removed.load(Ordering::Relaxed);
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Hash + PartialEq + Send,
+    V: Send,
+    S: BuildHasher + Sync,
+    A: Allocator + Clone + Send,
+{
+    /// Collect `iter` into a map, hashing and placing the entries on up to
+    /// `threads` threads. Later values for a key replace earlier ones, as
+    /// with `collect`.
+    pub fn par_from_iter<I>(threads: usize, iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        S: Default,
+        A: Default,
+    {
+        let mut map = HashMap::default();
+        map.par_extend(threads, iter);
+        map
+    }
+
+    /// Insert every entry of `iter`, using up to `threads` threads.
+    ///
+    /// The table is rebuilt from scratch, so this only pays off when `iter`
+    /// is large compared to the map. If a key's `Hash` or `PartialEq` impl
+    /// panics, the map is put back as it was and the new entries are
+    /// dropped.
+    pub fn par_extend<I>(&mut self, threads: usize, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let threads = threads.max(1);
+        let new: Vec<_> = iter.into_iter().collect();
+        if new.is_empty() {
+            return;
+        }
+        // Hash everything up front. The old keys stay borrowed so that a
+        // panic leaves them in place, and sharing them with other threads
+        // would need `K: Sync`, so they are hashed on this one.
+        let old_hashes: Vec<u64> = self
+            .iter()
+            .map(|(key, _)| self.hash_builder.hash_one(key))
+            .collect();
+        let new = hash_all(new, threads, &self.hash_builder);
+
+        // drain bucket by bucket, the same order `iter` went in
+        let alloc = self.allocator().clone();
+        let buckets = mem::replace(&mut self.buckets, AllocVec::new_in(alloc.clone()));
+        self.items = 0;
+        let start = buckets.len();
+        let old_len = old_hashes.len();
+        let mut entries = Vec::with_capacity(old_len + new.len());
+        let old = old_hashes.into_iter().zip(buckets.into_iter().flatten());
+        entries.extend(old.map(|(hash, (key, value))| (hash, key, value)));
+        entries.extend(new);
+        let unwound = match build(start, entries, threads, alloc.clone()) {
+            Ok(table) => {
+                (self.buckets, self.items) = table;
+                return;
+            }
+            Err(unwound) => unwound,
+        };
+        // the old entries come first in the input, so putting them back in
+        // input order rebuilds every bucket exactly as it was
+        let (mut old, new): (Vec<_>, Vec<_>) =
+            unwound.entries.into_iter().partition(|e| e.index < old_len);
+        old.sort_unstable_by_key(|e| e.index);
+        let mut buckets = empty_buckets(start, &alloc);
+        for e in old {
+            buckets[(e.hash % start as u64) as usize].push((e.key, e.value));
+        }
+        (self.buckets, self.items) = (buckets, old_len);
+        drop(new);
+        panic::resume_unwind(unwound.payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::BuildFnvHasher;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn layout<K: Clone, V: Clone, S>(map: &HashMap<K, V, S>) -> (usize, Vec<Vec<(K, V)>>) {
+        let buckets = map.buckets.iter().map(|b| b.to_vec()).collect();
+        (map.buckets.len(), buckets)
+    }
+
+    /// Pairs with plenty of repeated keys
+    fn pairs(n: u64) -> Vec<(u64, u64)> {
+        (0..n).map(|i| (i * 7919 % (n / 2 + 1), i)).collect()
+    }
+
+    #[test]
+    fn test_par_from_iter_matches_collect() {
+        for n in [0, 1, 2, 3, 7, 100, 5000] {
+            let expected: HashMap<_, _> = pairs(n).into_iter().collect();
+            for threads in [1, 2, 3, 8] {
+                let map: HashMap<_, _> = HashMap::par_from_iter(threads, pairs(n));
+                assert_eq!(map.len(), expected.len());
+                assert_eq!(layout(&map), layout(&expected), "n={n} threads={threads}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_par_extend_matches_insert() {
+        let mut expected: HashMap<_, _> = pairs(3000).into_iter().collect();
+        // removals leave more buckets than the items need
+        expected.retain(|k, _| k % 5 == 0);
+        let mut map: HashMap<_, _> = HashMap::par_from_iter(4, pairs(3000));
+        map.par_retain(4, |k, _| k % 5 == 0);
+        assert_eq!(layout(&map), layout(&expected));
+
+        map.par_extend(4, Vec::new());
+        assert_eq!(layout(&map), layout(&expected));
+        let more: Vec<_> = (1000..4000).map(|i| (i, i)).collect();
+        for &(k, v) in &more {
+            expected.insert(k, v);
+        }
+        map.par_extend(3, more);
+        assert_eq!(map.len(), expected.len());
+        assert_eq!(layout(&map), layout(&expected));
+    }
+
+    #[test]
+    fn test_par_uses_the_maps_hasher() {
+        let mut expected = HashMap::with_hasher(BuildFnvHasher::default());
+        let mut map = HashMap::with_hasher(BuildFnvHasher::default());
+        for (k, v) in pairs(1000) {
+            expected.insert(k, v);
+        }
+        map.par_extend(4, pairs(1000));
+        assert_eq!(layout(&map), layout(&expected));
+        let map: HashMap<_, _, BuildFnvHasher> = HashMap::par_from_iter(3, pairs(1000));
+        assert_eq!(layout(&map), layout(&expected));
+    }
+
+    #[test]
+    fn test_par_for_each() {
+        let map: HashMap<_, _> = HashMap::par_from_iter(4, (0..10_000u64).map(|i| (i, i)));
+        let sum = AtomicU64::new(0);
+        map.par_for_each(4, |_, v| {
+            sum.fetch_add(*v, Ordering::Relaxed);
+        });
+        assert_eq!(sum.into_inner(), (0..10_000).sum());
+        HashMap::<u64, u64>::new().par_for_each(4, |_, _| unreachable!());
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn test_worker_panic_reaches_caller() {
+        let map: HashMap<_, _> = HashMap::par_from_iter(2, (0..100).map(|i| (i, i)));
+        map.par_for_each(2, |&k, _| assert!(k != 42, "boom"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Stateless cursor iteration, in the style of Redis `SCAN`.
+//!
+//! The cursor holds the next bucket to visit with its bits reversed, and
+//! is advanced by incrementing the reversed value. The bucket count is
+//! always a power of two and `resize` splits bucket `i` into `i` and
+//! `i + n`, so visiting buckets in reverse-binary order never skips over
+//! the buckets that a split moved entries into. Every key present for the
+//! whole scan is returned at least once, even across resizes. Keys may be
+//! returned more than once if the table grows mid-scan.
+
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::{allocator::Allocator, HashMap};
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    /// Advance `cursor` to the next bucket in reverse-binary order, or to
+    /// 0 once every bucket has been visited.
+    fn next_cursor(&self, cursor: u64) -> u64 {
+        let mask = self.buckets.len() as u64 - 1;
+        // Set the bits above the mask so that the increment carries out
+        // of the masked range and wraps to 0 after the last bucket.
+        let cursor = (cursor | !mask).reverse_bits();
+        cursor.wrapping_add(1).reverse_bits()
+    }
+
+    /// Bucket indexes visited by a `scan(cursor, count)` call, together
+    /// with the cursor to resume from.
+    fn scan_buckets(&self, mut cursor: u64, count: usize) -> (u64, Vec<usize>) {
+        let mut visited = Vec::new();
+        if self.buckets.is_empty() {
+            return (0, visited);
+        }
+        let mask = self.buckets.len() as u64 - 1;
+        let mut found = 0;
+        loop {
+            let bucket = (cursor & mask) as usize;
+            found += self.buckets[bucket].len();
+            visited.push(bucket);
+            cursor = self.next_cursor(cursor);
+            if cursor == 0 || found >= count.max(1) {
+                return (cursor, visited);
+            }
+        }
+    }
+
+    /// Return roughly `count` entries starting at `cursor` and the cursor
+    /// for the next call. Start with 0 and stop when 0 is returned.
+    ///
+    /// Whole buckets are returned, so a call may return more than `count`
+    /// entries.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&K, &V)>) {
+        let (next, visited) = self.scan_buckets(cursor, count);
+        let entries = visited
+            .into_iter()
+            .flat_map(|b| self.buckets[b].iter().map(|(k, v)| (k, v)))
+            .collect();
+        (next, entries)
+    }
+
+    /// Same as `scan` but hands out mutable references to the values
+    pub fn scan_mut(&mut self, cursor: u64, count: usize) -> (u64, Vec<(&K, &mut V)>) {
+        let (next, mut visited) = self.scan_buckets(cursor, count);
+        // Split the visited buckets off the front of the vector in index
+        // order, so that they can be borrowed mutably at the same time
+        // without walking the buckets in between.
+        visited.sort_unstable();
+        let mut entries = Vec::new();
+        let mut rest = &mut self.buckets[..];
+        let mut offset = 0;
+        for i in visited {
+            let (bucket, tail) = mem::take(&mut rest)[i - offset..]
+                .split_first_mut()
+                .expect("visited buckets are in range");
+            entries.extend(bucket.iter_mut().map(|(k, v)| (&*k, v)));
+            rest = tail;
+            offset = i + 1;
+        }
+        (next, entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HashMap;
+    use alloc::vec::Vec;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_scan_visits_every_key_once() {
+        let map: HashMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = map.scan(cursor, 10);
+            seen.extend(batch.into_iter().map(|(k, _)| *k));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        seen.sort_unstable();
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scan_across_resize() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut extra = 1000;
+        loop {
+            let (next, batch) = map.scan(cursor, 5);
+            seen.extend(batch.into_iter().map(|(k, _)| *k));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+            // grow the table between calls, forcing several resizes
+            for _ in 0..20 {
+                map.insert(extra, extra);
+                extra += 1;
+            }
+        }
+        assert!((0..100).all(|k| seen.contains(&k)));
+    }
+
+    #[test]
+    fn test_scan_mut() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = map.scan_mut(cursor, 7);
+            for (_, v) in batch {
+                *v += 1;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert!((0..100).all(|k| map[&k] == k + 1));
+    }
+
+    #[test]
+    fn test_scan_empty() {
+        let map: HashMap<u32, u32> = HashMap::new();
+        let (next, batch) = map.scan(0, 10);
+        assert_eq!(next, 0);
+        assert!(batch.is_empty());
+    }
+}
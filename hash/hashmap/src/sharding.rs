@@ -0,0 +1,406 @@
+//! Key-to-node assignment that moves few keys when nodes come and go.
+//!
+//! Three schemes are provided:
+//! 1. `HashRing` - a consistent hashing ring with weighted virtual nodes
+//! 2. `jump_hash` - jump consistent hash, for numbered shards `0..n`
+//! 3. `Rendezvous` - highest random weight (HRW) hashing
+//!
+//! All of them hash through `hashers::make_hash`, so every process running
+//! the same build agrees on where a key lives.
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::hashers::make_hash;
+
+/// Number of points a node of weight 1 gets on the ring
+pub const DEFAULT_VNODES: usize = 160;
+
+/// Consistent hashing ring.
+///
+/// Each node is placed on the ring at `vnodes * weight` points. A key
+/// belongs to the first point at or after its own hash, wrapping around.
+pub struct HashRing<N> {
+    // (weight, node), indexed by the node ids stored in `points`
+    nodes: Vec<Option<(u32, N)>>,
+    // sorted by hash
+    points: Vec<(u64, usize)>,
+    vnodes: usize,
+}
+
+impl<N> Default for HashRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> HashRing<N> {
+    pub fn new() -> Self {
+        Self::with_vnodes(DEFAULT_VNODES)
+    }
+
+    /// More virtual nodes even out the load at the cost of a bigger ring
+    pub fn with_vnodes(vnodes: usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            points: Vec::new(),
+            vnodes: vnodes.max(1),
+        }
+    }
+
+    /// return the number of nodes on the ring
+    pub fn len(&self) -> usize {
+        self.nodes.iter().flatten().count()
+    }
+
+    /// return true if the ring has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &N> {
+        self.nodes.iter().flatten().map(|(_, n)| n)
+    }
+}
+
+impl<N> HashRing<N>
+where
+    N: Hash + PartialEq,
+{
+    /// Add a node with weight 1
+    pub fn add_node(&mut self, node: N) -> bool {
+        self.add_weighted_node(node, 1)
+    }
+
+    /// Add a node that receives `weight` times the share of a weight 1
+    /// node. Returns false if the node is already on the ring.
+    pub fn add_weighted_node(&mut self, node: N, weight: u32) -> bool {
+        if weight == 0 || self.position(&node).is_some() {
+            return false;
+        }
+        let id = match self.nodes.iter().position(Option::is_none) {
+            Some(id) => id,
+            None => {
+                self.nodes.push(None);
+                self.nodes.len() - 1
+            }
+        };
+        let npoints = self.vnodes * weight as usize;
+        self.points
+            .extend((0..npoints).map(|replica| (make_hash(&(&node, replica)), id)));
+        self.points.sort_unstable();
+        self.nodes[id] = Some((weight, node));
+        true
+    }
+
+    /// Remove a node. Only the keys that mapped to it move.
+    pub fn remove_node(&mut self, node: &N) -> Option<N> {
+        let id = self.position(node)?;
+        self.points.retain(|&(_, owner)| owner != id);
+        self.nodes[id].take().map(|(_, n)| n)
+    }
+
+    pub fn weight(&self, node: &N) -> Option<u32> {
+        self.position(node)
+            .and_then(|id| self.nodes[id].as_ref())
+            .map(|(w, _)| *w)
+    }
+
+    fn position(&self, node: &N) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|n| matches!(n, Some((_, existing)) if existing == node))
+    }
+
+    /// Index into `points` of the first point at or after the key's hash
+    fn start<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        let hash = make_hash(key);
+        match self.points.partition_point(|&(h, _)| h < hash) {
+            i if i == self.points.len() => 0,
+            i => i,
+        }
+    }
+
+    /// The node that owns `key`
+    pub fn get_node<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let id = self.points[self.start(key)].1;
+        self.nodes[id].as_ref().map(|(_, n)| n)
+    }
+
+    /// Up to `n` distinct nodes for `key`, walking clockwise from its
+    /// owner. The first one is always `get_node(key)`, the rest are the
+    /// natural places for its replicas.
+    pub fn get_n_nodes<K: Hash + ?Sized>(&self, key: &K, n: usize) -> Vec<&N> {
+        let mut ids: Vec<usize> = Vec::with_capacity(n);
+        if self.points.is_empty() {
+            return Vec::new();
+        }
+        let start = self.start(key);
+        let total = self.len();
+        for i in 0..self.points.len() {
+            if ids.len() == n.min(total) {
+                break;
+            }
+            let id = self.points[(start + i) % self.points.len()].1;
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids.into_iter()
+            .filter_map(|id| self.nodes[id].as_ref().map(|(_, n)| n))
+            .collect()
+    }
+}
+
+/// Jump consistent hash (Lamping and Veach, 2014).
+///
+/// Maps `key` to a bucket in `0..buckets` using no memory. Growing from
+/// `n` to `n + 1` buckets moves only the keys that land in the new bucket,
+/// but buckets can only be added or removed at the end.
+pub fn jump_consistent_hash(mut key: u64, buckets: u32) -> u32 {
+    assert!(buckets > 0, "jump hash needs at least one bucket");
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as u32
+}
+
+/// `jump_consistent_hash` of any hashable key
+pub fn jump_hash<K: Hash + ?Sized>(key: &K, buckets: u32) -> u32 {
+    jump_consistent_hash(make_hash(key), buckets)
+}
+
+/// Rendezvous (highest random weight) hashing.
+///
+/// Every node scores every key and the highest score wins. Lookups cost
+/// O(nodes), but there is no ring to maintain and removing a node only
+/// moves the keys it owned.
+#[cfg(feature = "std")]
+pub struct Rendezvous<N> {
+    nodes: Vec<(u32, N)>,
+}
+
+#[cfg(feature = "std")]
+impl<N> Default for Rendezvous<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<N> Rendezvous<N> {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    /// return the number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// return true if there are no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &N> {
+        self.nodes.iter().map(|(_, n)| n)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<N> Rendezvous<N>
+where
+    N: Hash + PartialEq,
+{
+    pub fn add_node(&mut self, node: N) -> bool {
+        self.add_weighted_node(node, 1)
+    }
+
+    pub fn add_weighted_node(&mut self, node: N, weight: u32) -> bool {
+        if weight == 0 || self.nodes.iter().any(|(_, n)| n == &node) {
+            return false;
+        }
+        self.nodes.push((weight, node));
+        true
+    }
+
+    pub fn remove_node(&mut self, node: &N) -> Option<N> {
+        let i = self.nodes.iter().position(|(_, n)| n == node)?;
+        Some(self.nodes.remove(i).1)
+    }
+
+    /// Weighted score from Schindelhauer and Schomaker: `-w / ln(u)` with
+    /// `u` the combined hash mapped into (0, 1).
+    fn score(node: &N, weight: u32, key_hash: u64) -> f64 {
+        let hash = make_hash(&(node, key_hash));
+        let unit = ((hash >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        -(weight as f64) / unit.ln()
+    }
+
+    pub fn get_node<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        self.get_n_nodes(key, 1).pop()
+    }
+
+    /// The `n` highest scoring nodes for `key`, best first
+    pub fn get_n_nodes<K: Hash + ?Sized>(&self, key: &K, n: usize) -> Vec<&N> {
+        let key_hash = make_hash(key);
+        let mut scored: Vec<(f64, &N)> = self
+            .nodes
+            .iter()
+            .map(|(w, node)| (Self::score(node, *w, key_hash), node))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(n).map(|(_, node)| node).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, string::String, vec};
+
+    const KEYS: u32 = 100_000;
+
+    fn counts<'a>(nodes: usize, owner: impl Fn(u32) -> &'a str) -> Vec<usize> {
+        let mut counts = vec![0; nodes];
+        for key in 0..KEYS {
+            let node = owner(key);
+            counts[node[4..].parse::<usize>().unwrap()] += 1;
+        }
+        counts
+    }
+
+    fn names(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("node{i}")).collect()
+    }
+
+    #[test]
+    fn test_ring_balance() {
+        let names = names(10);
+        let mut ring = HashRing::new();
+        for name in &names {
+            ring.add_node(name.as_str());
+        }
+        let counts = counts(10, |k| *ring.get_node(&k).unwrap());
+        let mean = KEYS as usize / 10;
+        for c in counts {
+            // 160 vnodes per node keeps every node within 25% of the mean
+            assert!(c > mean * 3 / 4 && c < mean * 5 / 4, "{c}");
+        }
+    }
+
+    #[test]
+    fn test_ring_weights() {
+        let names = names(2);
+        let mut ring = HashRing::new();
+        ring.add_weighted_node(names[0].as_str(), 1);
+        ring.add_weighted_node(names[1].as_str(), 3);
+        assert_eq!(ring.weight(&names[1].as_str()), Some(3));
+        let counts = counts(2, |k| *ring.get_node(&k).unwrap());
+        let share = counts[1] as f64 / KEYS as f64;
+        assert!((0.7..0.8).contains(&share), "{share}");
+    }
+
+    #[test]
+    fn test_ring_minimal_remapping() {
+        let names = names(11);
+        let mut ring = HashRing::new();
+        for name in &names[..10] {
+            ring.add_node(name.as_str());
+        }
+        let before: Vec<&str> = (0..KEYS).map(|k| *ring.get_node(&k).unwrap()).collect();
+        ring.add_node(names[10].as_str());
+        let moved = (0..KEYS)
+            .filter(|k| *ring.get_node(k).unwrap() != before[*k as usize])
+            .count();
+        // only keys that now belong to the new node may move
+        for k in 0..KEYS {
+            let now = *ring.get_node(&k).unwrap();
+            assert!(now == before[k as usize] || now == names[10]);
+        }
+        assert!(moved < KEYS as usize * 2 / 11);
+
+        assert_eq!(ring.remove_node(&names[10].as_str()), Some("node10"));
+        assert!((0..KEYS).all(|k| *ring.get_node(&k).unwrap() == before[k as usize]));
+        assert_eq!(ring.len(), 10);
+    }
+
+    #[test]
+    fn test_ring_replicas() {
+        let mut ring = HashRing::new();
+        assert_eq!(ring.get_node("key"), None);
+        for node in ["a", "b", "c"] {
+            assert!(ring.add_node(node));
+        }
+        assert!(!ring.add_node("a"));
+        let replicas = ring.get_n_nodes("key", 2);
+        assert_eq!(replicas.len(), 2);
+        assert_ne!(replicas[0], replicas[1]);
+        assert_eq!(Some(replicas[0]), ring.get_node("key"));
+        assert_eq!(ring.get_n_nodes("key", 5).len(), 3);
+    }
+
+    #[test]
+    fn test_jump_hash() {
+        assert_eq!(jump_consistent_hash(0, 1), 0);
+        let mut counts = [0usize; 10];
+        for k in 0..KEYS {
+            let bucket = jump_hash(&k, 10);
+            counts[bucket as usize] += 1;
+            // growing to 11 buckets only moves keys into bucket 10
+            let grown = jump_hash(&k, 11);
+            assert!(grown == bucket || grown == 10);
+        }
+        let mean = KEYS as usize / 10;
+        assert!(counts
+            .iter()
+            .all(|&c| c > mean * 9 / 10 && c < mean * 11 / 10));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rendezvous() {
+        let names = names(10);
+        let mut hrw = Rendezvous::new();
+        for name in &names {
+            hrw.add_node(name.as_str());
+        }
+        let counts = counts(10, |k| *hrw.get_node(&k).unwrap());
+        let mean = KEYS as usize / 10;
+        assert!(counts
+            .iter()
+            .all(|&c| c > mean * 9 / 10 && c < mean * 11 / 10));
+
+        let before: Vec<&str> = (0..KEYS).map(|k| *hrw.get_node(&k).unwrap()).collect();
+        hrw.remove_node(&"node3");
+        for k in 0..KEYS {
+            let now = *hrw.get_node(&k).unwrap();
+            if before[k as usize] != "node3" {
+                assert_eq!(now, before[k as usize]);
+            }
+        }
+        let top = hrw.get_n_nodes(&1u32, 3);
+        assert_eq!(top.len(), 3);
+        assert_eq!(Some(top[0]), hrw.get_node(&1u32));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rendezvous_weights() {
+        let names = names(2);
+        let mut hrw = Rendezvous::new();
+        hrw.add_weighted_node(names[0].as_str(), 1);
+        hrw.add_weighted_node(names[1].as_str(), 3);
+        let counts = counts(2, |k| *hrw.get_node(&k).unwrap());
+        let share = counts[1] as f64 / KEYS as f64;
+        assert!((0.72..0.78).contains(&share), "{share}");
+    }
+}
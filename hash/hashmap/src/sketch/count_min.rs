@@ -0,0 +1,272 @@
+use std::{cmp::Reverse, f64::consts::E, hash::Hash, marker::PhantomData};
+
+use super::SketchError;
+use crate::{
+    hashers::{make_fnv_hash, make_hash},
+    HashMap,
+};
+
+/// Frequency estimator over values of type `T`.
+///
+/// `depth` rows of `width` counters; each value increments one counter per
+/// row, picked by double hashing like `BloomFilter`. The estimate is the
+/// smallest of those counters, so it never undercounts. Updates are
+/// conservative: a counter is only raised as far as the new estimate,
+/// which keeps collisions from inflating the other counters as much.
+pub struct CountMinSketch<T: ?Sized> {
+    counters: Vec<u64>,
+    width: usize,
+    depth: usize,
+    total: u64,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized> Clone for CountMinSketch<T> {
+    fn clone(&self) -> Self {
+        Self {
+            counters: self.counters.clone(),
+            width: self.width,
+            depth: self.depth,
+            total: self.total,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> CountMinSketch<T> {
+    /// Size the sketch so that an estimate exceeds the true count by more
+    /// than `epsilon * total()` with probability at most `delta`, using
+    /// `width = e / epsilon` and `depth = ln(1 / delta)`.
+    pub fn new(epsilon: f64, delta: f64) -> Self {
+        assert!(epsilon > 0.0, "epsilon must be positive");
+        assert!(delta > 0.0 && delta < 1.0, "delta must be in (0, 1)");
+        let width = (E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+        Self::with_dims(width, depth)
+    }
+
+    /// Build a sketch with an explicit number of counters per row and rows
+    pub fn with_dims(width: usize, depth: usize) -> Self {
+        let (width, depth) = (width.max(1), depth.max(1));
+        Self {
+            counters: vec![0; width * depth],
+            width,
+            depth,
+            total: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Sum of all counts added
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|c| *c = 0);
+        self.total = 0;
+    }
+
+    /// Add the counts of `other` to this sketch
+    pub fn merge(&mut self, other: &Self) -> Result<(), SketchError> {
+        if self.width != other.width || self.depth != other.depth {
+            return Err(SketchError::Incompatible);
+        }
+        for (a, b) in self.counters.iter_mut().zip(&other.counters) {
+            *a = a.saturating_add(*b);
+        }
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+}
+
+impl<T: Hash + ?Sized> CountMinSketch<T> {
+    /// Index into `counters` of the cell for `item` in each row
+    fn cells(&self, item: &T) -> impl Iterator<Item = usize> {
+        let h1 = make_hash(item);
+        let h2 = make_fnv_hash(item) | 1;
+        let width = self.width;
+        (0..self.depth).map(move |row| {
+            let h = h1.wrapping_add((row as u64).wrapping_mul(h2));
+            row * width + (h % width as u64) as usize
+        })
+    }
+
+    /// Record `count` more occurrences of `item` and return its new
+    /// estimated count
+    pub fn add(&mut self, item: &T, count: u64) -> u64 {
+        let new = self.estimate(item).saturating_add(count);
+        for cell in self.cells(item).collect::<Vec<_>>() {
+            let counter = &mut self.counters[cell];
+            *counter = (*counter).max(new);
+        }
+        self.total = self.total.saturating_add(count);
+        new
+    }
+
+    pub fn increment(&mut self, item: &T) -> u64 {
+        self.add(item, 1)
+    }
+
+    /// Estimated number of occurrences of `item`. Never less than the true
+    /// count.
+    pub fn estimate(&self, item: &T) -> u64 {
+        self.cells(item)
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// Tracks the `k` most frequent items of a stream.
+///
+/// Counts come from a `CountMinSketch`, and the current top `k` items are
+/// kept in a `HashMap` next to it. An item enters the top `k` once its
+/// estimate beats the smallest one there.
+pub struct HeavyHitters<T> {
+    sketch: CountMinSketch<T>,
+    k: usize,
+    top: HashMap<T, u64>,
+}
+
+impl<T: Hash + PartialEq + Clone> HeavyHitters<T> {
+    pub fn new(k: usize, sketch: CountMinSketch<T>) -> Self {
+        Self {
+            sketch,
+            k,
+            top: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, item: &T, count: u64) {
+        let estimate = self.sketch.add(item, count);
+        if self.top.contains_key(item) || self.top.len() < self.k {
+            self.top.insert(item.clone(), estimate);
+            return;
+        }
+        let min = self
+            .top
+            .iter()
+            .min_by_key(|(_, &count)| count)
+            .map(|(key, &count)| (key.clone(), count));
+        if let Some((min_key, min_count)) = min {
+            if estimate > min_count {
+                self.top.remove(&min_key);
+                self.top.insert(item.clone(), estimate);
+            }
+        }
+    }
+
+    pub fn increment(&mut self, item: &T) {
+        self.add(item, 1)
+    }
+
+    /// The tracked items with their estimated counts, most frequent first
+    pub fn top(&self) -> Vec<(&T, u64)> {
+        let mut top: Vec<_> = self.top.iter().map(|(k, &v)| (k, v)).collect();
+        top.sort_by_key(|&(_, count)| Reverse(count));
+        top
+    }
+
+    pub fn sketch(&self) -> &CountMinSketch<T> {
+        &self.sketch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Zipf-like stream: item i occurs about 2000 / (i + 1) times
+    fn stream() -> Vec<u32> {
+        (0..2000u32)
+            .flat_map(|i| std::iter::repeat_n(i, (2000 / (i + 1)) as usize))
+            .collect()
+    }
+
+    fn exact_counts(items: &[u32]) -> Vec<u64> {
+        let mut counts = vec![0; 2000];
+        for &i in items {
+            counts[i as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_error_bound() {
+        let (epsilon, delta) = (0.001, 0.01);
+        let items = stream();
+        let exact = exact_counts(&items);
+        let mut sketch = CountMinSketch::new(epsilon, delta);
+        for item in &items {
+            sketch.increment(item);
+        }
+        assert_eq!(sketch.total(), items.len() as u64);
+        let bound = (epsilon * sketch.total() as f64) as u64;
+        let mut over_bound = 0;
+        for (item, &count) in exact.iter().enumerate() {
+            let estimate = sketch.estimate(&(item as u32));
+            assert!(estimate >= count);
+            if estimate - count > bound {
+                over_bound += 1;
+            }
+        }
+        assert!((over_bound as f64) <= delta * exact.len() as f64);
+    }
+
+    #[test]
+    fn test_conservative_update() {
+        let mut sketch = CountMinSketch::with_dims(4, 2);
+        sketch.add("hot", 100);
+        let hot: Vec<_> = sketch.cells("hot").collect();
+        // find a key that shares the first row's cell with "hot" only
+        let cold = (0..)
+            .map(|i| format!("cold{i}"))
+            .find(|key| {
+                let cells: Vec<_> = sketch.cells(key.as_str()).collect();
+                cells[0] == hot[0] && cells[1] != hot[1]
+            })
+            .unwrap();
+        assert_eq!(sketch.add(&cold, 1), 1);
+        // a plain update would have raised the shared cell to 101
+        assert_eq!(sketch.counters[hot[0]], 100);
+        assert_eq!(sketch.estimate("hot"), 100);
+        assert_eq!(sketch.estimate(&cold), 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = CountMinSketch::with_dims(100, 3);
+        let mut b = a.clone();
+        a.add("x", 2);
+        b.add("x", 3);
+        a.merge(&b).unwrap();
+        assert_eq!(a.estimate("x"), 5);
+        assert_eq!(a.total(), 5);
+        let c = CountMinSketch::with_dims(50, 3);
+        assert_eq!(a.merge(&c), Err(SketchError::Incompatible));
+    }
+
+    #[test]
+    fn test_heavy_hitters() {
+        let items = stream();
+        let mut hitters = HeavyHitters::new(5, CountMinSketch::new(0.001, 0.01));
+        for item in &items {
+            hitters.increment(item);
+        }
+        let top: Vec<_> = hitters.top().into_iter().map(|(&k, _)| k).collect();
+        assert_eq!(top, [0, 1, 2, 3, 4]);
+        let exact = exact_counts(&items);
+        for (&item, estimate) in hitters.top() {
+            assert!(estimate >= exact[item as usize]);
+        }
+    }
+}
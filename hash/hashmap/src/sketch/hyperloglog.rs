@@ -0,0 +1,260 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use super::SketchError;
+use crate::{hashers::make_hash, HashMap};
+
+const MIN_PRECISION: u8 = 4;
+const MAX_PRECISION: u8 = 18;
+
+enum Registers {
+    // register index -> rank, for the registers that are not zero
+    Sparse(HashMap<u32, u8>),
+    Dense(Vec<u8>),
+}
+
+/// Cardinality estimator over values of type `T`.
+///
+/// Each value is hashed once; the top `precision` bits pick one of
+/// `2^precision` registers and the register keeps the longest run of
+/// leading zeros seen in the remaining bits. The relative standard error
+/// of `estimate` is about `1.04 / sqrt(2^precision)`.
+///
+/// A new sketch stores only the registers that have been set, and switches
+/// to a flat array once that stops saving memory.
+pub struct HyperLogLog<T: ?Sized> {
+    registers: Registers,
+    precision: u8,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized> Clone for HyperLogLog<T> {
+    fn clone(&self) -> Self {
+        let registers = match &self.registers {
+            Registers::Sparse(map) => {
+                Registers::Sparse(map.iter().map(|(&i, &r)| (i, r)).collect())
+            }
+            Registers::Dense(regs) => Registers::Dense(regs.clone()),
+        };
+        Self {
+            registers,
+            precision: self.precision,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> HyperLogLog<T> {
+    /// Create a sketch with `2^precision` registers. `precision` must be
+    /// between 4 and 18.
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "precision must be in 4..=18"
+        );
+        Self {
+            registers: Registers::Sparse(HashMap::new()),
+            precision,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    fn nregisters(&self) -> usize {
+        1 << self.precision
+    }
+
+    /// return true while only the set registers are stored
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.registers, Registers::Sparse(_))
+    }
+
+    /// Expected relative standard error of `estimate`
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.nregisters() as f64).sqrt()
+    }
+
+    pub fn clear(&mut self) {
+        self.registers = Registers::Sparse(HashMap::new());
+    }
+
+    /// Raise register `idx` to `rank` if it is lower
+    fn update(&mut self, idx: u32, rank: u8) {
+        match &mut self.registers {
+            Registers::Sparse(map) => {
+                let reg = map.entry(idx).or_insert(0);
+                *reg = (*reg).max(rank);
+                // an entry costs several bytes against one per register
+                // in the dense array
+                if map.len() > self.nregisters() / 16 {
+                    self.densify();
+                }
+            }
+            Registers::Dense(regs) => {
+                let reg = &mut regs[idx as usize];
+                *reg = (*reg).max(rank);
+            }
+        }
+    }
+
+    fn densify(&mut self) {
+        if let Registers::Sparse(map) = &self.registers {
+            let mut regs = vec![0; self.nregisters()];
+            for (&idx, &rank) in map.iter() {
+                regs[idx as usize] = rank;
+            }
+            self.registers = Registers::Dense(regs);
+        }
+    }
+
+    /// Estimated number of distinct values inserted
+    pub fn estimate(&self) -> f64 {
+        let m = self.nregisters() as f64;
+        // sum of 2^-register over all registers, and how many are zero
+        let (sum, zeros) = match &self.registers {
+            Registers::Sparse(map) => {
+                let zeros = self.nregisters() - map.len();
+                let set: f64 = map.iter().map(|(_, &r)| (-(r as f64)).exp2()).sum();
+                (set + zeros as f64, zeros)
+            }
+            Registers::Dense(regs) => {
+                let zeros = regs.iter().filter(|&&r| r == 0).count();
+                let sum = regs.iter().map(|&r| (-(r as f64)).exp2()).sum();
+                (sum, zeros)
+            }
+        };
+        let alpha = match self.nregisters() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let raw = alpha * m * m / sum;
+        // The raw estimate is biased for small cardinalities, where linear
+        // counting over the empty registers is more accurate. With 64-bit
+        // hashes no large range correction is needed.
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Make this sketch count everything either sketch has seen
+    pub fn merge(&mut self, other: &Self) -> Result<(), SketchError> {
+        if self.precision != other.precision {
+            return Err(SketchError::Incompatible);
+        }
+        match &other.registers {
+            Registers::Sparse(map) => {
+                for (&idx, &rank) in map.iter() {
+                    self.update(idx, rank);
+                }
+            }
+            Registers::Dense(other_regs) => {
+                self.densify();
+                if let Registers::Dense(regs) = &mut self.registers {
+                    for (a, &b) in regs.iter_mut().zip(other_regs) {
+                        *a = (*a).max(b);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Hash + ?Sized> HyperLogLog<T> {
+    pub fn insert(&mut self, item: &T) {
+        let hash = make_hash(item);
+        let idx = (hash >> (64 - self.precision)) as u32;
+        // The sentinel bit caps the rank when all remaining bits are zero
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        self.update(idx, rest.leading_zeros() as u8 + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative_error(estimate: f64, exact: usize) -> f64 {
+        (estimate - exact as f64).abs() / exact as f64
+    }
+
+    /// Every register, whichever way the sketch stores them
+    fn registers<T: ?Sized>(hll: &HyperLogLog<T>) -> Vec<u8> {
+        let mut hll = hll.clone();
+        hll.densify();
+        match hll.registers {
+            Registers::Dense(regs) => regs,
+            Registers::Sparse(_) => unreachable!(),
+        }
+    }
+
+    /// The two estimates only differ by rounding in their sums
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-9 * b, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_error_bound() {
+        for exact in [10, 100, 1_000, 10_000, 100_000] {
+            let mut hll = HyperLogLog::new(12);
+            for i in 0..exact {
+                hll.insert(&i);
+                // duplicates must not count
+                hll.insert(&i);
+            }
+            let err = relative_error(hll.estimate(), exact);
+            assert!(
+                err < 3.0 * hll.standard_error(),
+                "{exact}: estimate {} error {err}",
+                hll.estimate()
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let mut sparse = HyperLogLog::new(14);
+        let mut dense = HyperLogLog::new(14);
+        dense.densify();
+        for i in 0..500 {
+            sparse.insert(&i);
+            dense.insert(&i);
+        }
+        assert!(sparse.is_sparse());
+        assert_eq!(registers(&sparse), registers(&dense));
+        assert_close(sparse.estimate(), dense.estimate());
+        for i in 500..5000 {
+            sparse.insert(&i);
+        }
+        assert!(!sparse.is_sparse());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = HyperLogLog::new(12);
+        let mut b = HyperLogLog::new(12);
+        for i in 0..30_000 {
+            a.insert(&i);
+        }
+        // half of b overlaps with a; b stays sparse
+        for i in 29_900..30_100 {
+            b.insert(&i);
+        }
+        assert!(b.is_sparse());
+        let mut union = a.clone();
+        union.merge(&b).unwrap();
+        assert!(relative_error(union.estimate(), 30_100) < 3.0 * a.standard_error());
+        // merging is symmetric
+        b.merge(&a).unwrap();
+        assert_eq!(registers(&b), registers(&union));
+        assert_close(b.estimate(), union.estimate());
+        let other: HyperLogLog<i32> = HyperLogLog::new(10);
+        assert_eq!(a.merge(&other), Err(SketchError::Incompatible));
+    }
+}
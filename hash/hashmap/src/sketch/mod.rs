@@ -0,0 +1,31 @@
+//! Probabilistic summaries of streams.
+//!
+//! The sketches use a fixed amount of memory no matter how many items they
+//! see, in exchange for approximate answers with known error bounds:
+//! `HyperLogLog` estimates how many distinct items there were, and
+//! `CountMinSketch` how often each one occurred.
+
+use std::{error, fmt};
+
+mod count_min;
+mod hyperloglog;
+
+pub use count_min::{CountMinSketch, HeavyHitters};
+pub use hyperloglog::HyperLogLog;
+
+/// Errors reported by the sketches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SketchError {
+    /// The two sketches were built with different parameters
+    Incompatible,
+}
+
+impl fmt::Display for SketchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SketchError::Incompatible => write!(f, "sketches have different parameters"),
+        }
+    }
+}
+
+impl error::Error for SketchError {}
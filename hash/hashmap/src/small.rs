@@ -0,0 +1,478 @@
+//! A map that keeps up to `N` entries inline.
+//!
+//! Small maps are searched linearly, which beats hashing for a handful of
+//! keys and needs no allocation. Inserting entry `N + 1` moves everything
+//! into a `HashMap`, and the map stays hashed from then on.
+
+use core::{array, hash::Hash, iter::FromIterator, mem, ops, slice};
+
+use crate::{Equivalent, HashMap, HashMapIntoIter, HashMapIter, OccupiedEntry};
+
+enum Repr<K, V, const N: usize> {
+    // entries are packed at the front of `slots`
+    Inline {
+        slots: [Option<(K, V)>; N],
+        len: usize,
+    },
+    Heap(HashMap<K, V>),
+}
+
+pub struct SmallMap<K, V, const N: usize> {
+    repr: Repr<K, V, N>,
+}
+
+impl<K, V, const N: usize> Default for SmallMap<K, V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const N: usize> SmallMap<K, V, N> {
+    pub fn new() -> Self {
+        Self {
+            repr: Repr::Inline {
+                slots: array::from_fn(|_| None),
+                len: 0,
+            },
+        }
+    }
+
+    /// return true while the entries are still stored inline
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, Repr::Inline { .. })
+    }
+
+    /// return the number of elements in the map
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            Repr::Inline { len, .. } => *len,
+            Repr::Heap(map) => map.len(),
+        }
+    }
+
+    /// return true if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keep only the entries for which `keep` returns true
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        match &mut self.repr {
+            Repr::Inline { slots, len } => {
+                let mut i = 0;
+                while i < *len {
+                    let (k, v) = slots[i].as_mut().unwrap();
+                    if keep(k, v) {
+                        i += 1;
+                        continue;
+                    }
+                    // same as remove, so the entries stay packed even if
+                    // `keep` or a drop panics
+                    *len -= 1;
+                    slots.swap(i, *len);
+                    slots[*len].take();
+                }
+            }
+            Repr::Heap(map) => map.retain(keep),
+        }
+    }
+}
+
+impl<K, V, const N: usize> SmallMap<K, V, N>
+where
+    K: Hash + PartialEq,
+{
+    /// Move the inline entries into a `HashMap`
+    fn spill(&mut self) -> &mut HashMap<K, V> {
+        if let Repr::Inline { slots, .. } = &mut self.repr {
+            let map = slots.iter_mut().filter_map(Option::take).collect();
+            self.repr = Repr::Heap(map);
+        }
+        match &mut self.repr {
+            Repr::Heap(map) => map,
+            Repr::Inline { .. } => unreachable!("just spilled"),
+        }
+    }
+
+    fn position<Q>(slots: &[Option<(K, V)>], key: &Q) -> Option<usize>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        slots
+            .iter()
+            .map_while(Option::as_ref)
+            .position(|(k, _)| key.equivalent(k))
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match &mut self.repr {
+            Repr::Inline { slots, len } => {
+                if let Some(i) = Self::position(&slots[..*len], &key) {
+                    let (_, v) = slots[i].as_mut().unwrap();
+                    return Some(mem::replace(v, value));
+                }
+                if *len < N {
+                    slots[*len] = Some((key, value));
+                    *len += 1;
+                    return None;
+                }
+                self.spill().insert(key, value)
+            }
+            Repr::Heap(map) => map.insert(key, value),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        match &self.repr {
+            Repr::Inline { slots, len } => {
+                let i = Self::position(&slots[..*len], key)?;
+                slots[i].as_ref().map(|(_, v)| v)
+            }
+            Repr::Heap(map) => map.get(key),
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        match &mut self.repr {
+            Repr::Inline { slots, len } => {
+                let i = Self::position(&slots[..*len], key)?;
+                slots[i].as_mut().map(|(_, v)| v)
+            }
+            Repr::Heap(map) => map.get_mut(key),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        match &mut self.repr {
+            Repr::Inline { slots, len } => {
+                let i = Self::position(&slots[..*len], key)?;
+                // keep the entries packed by moving the last one into the hole
+                *len -= 1;
+                slots.swap(i, *len);
+                slots[*len].take()
+            }
+            Repr::Heap(map) => map.remove_entry(key),
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, N> {
+        let found = match &self.repr {
+            Repr::Inline { slots, len } => Self::position(&slots[..*len], &key),
+            Repr::Heap(_) => None,
+        };
+        if self.is_inline() && found.is_none() {
+            return Entry::Vacant(VacantEntry {
+                inner: Vacant::Inline { key, map: self },
+            });
+        }
+        match &mut self.repr {
+            Repr::Inline { slots, .. } => Entry::Occupied(OccupiedEntry {
+                entry: slots[found.unwrap()].as_mut().unwrap(),
+            }),
+            Repr::Heap(map) => match map.entry(key) {
+                crate::Entry::Occupied(entry) => Entry::Occupied(entry),
+                crate::Entry::Vacant(entry) => Entry::Vacant(VacantEntry {
+                    inner: Vacant::Heap(entry),
+                }),
+            },
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.into_iter()
+    }
+}
+
+/// Entry API for `SmallMap`, mirroring `hashmap::Entry`. Occupied entries
+/// are the same type as the ones `HashMap` hands out.
+pub enum Entry<'a, K, V, const N: usize> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V, N>),
+}
+
+/// VacantEntry is returned when the key is not present in the map. It
+/// moves the map to the heap if the insert does not fit inline.
+pub struct VacantEntry<'a, K, V, const N: usize> {
+    inner: Vacant<'a, K, V, N>,
+}
+
+enum Vacant<'a, K, V, const N: usize> {
+    Inline {
+        key: K,
+        map: &'a mut SmallMap<K, V, N>,
+    },
+    Heap(crate::VacantEntry<'a, K, V>),
+}
+
+impl<'a, K, V, const N: usize> VacantEntry<'a, K, V, N>
+where
+    K: Hash + PartialEq,
+{
+    /// Consumes self and inserts the key-value pair into the map
+    pub fn insert(self, value: V) -> &'a mut V {
+        match self.inner {
+            Vacant::Heap(entry) => entry.insert(value),
+            Vacant::Inline { key, map } => {
+                let spill = match &map.repr {
+                    Repr::Inline { len, .. } => *len == N,
+                    Repr::Heap(_) => true,
+                };
+                if spill {
+                    return map.spill().entry(key).or_insert(value);
+                }
+                match &mut map.repr {
+                    Repr::Inline { slots, len } => {
+                        let slot = &mut slots[*len];
+                        *len += 1;
+                        &mut slot.insert((key, value)).1
+                    }
+                    Repr::Heap(_) => unreachable!("checked above"),
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> Entry<'a, K, V, N>
+where
+    K: Hash + PartialEq,
+{
+    /// Return the value for the key, inserting `value` first if needed
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Like `or_insert`, but only calls `maker` if the key is missing
+    pub fn or_insert_with<F>(self, maker: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(maker()),
+        }
+    }
+
+    /// Like `or_insert`, inserting `V::default()`
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(Default::default)
+    }
+}
+
+impl<K, Q: ?Sized, V, const N: usize> ops::Index<&Q> for SmallMap<K, V, N>
+where
+    K: Eq + Hash,
+    Q: Hash + Equivalent<K>,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+/// Iterator for SmallMap
+pub enum Iter<'a, K, V> {
+    Inline(slice::Iter<'a, Option<(K, V)>>),
+    Heap(HashMapIter<'a, K, V>),
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Inline(slots) => slots.next()?.as_ref().map(|(k, v)| (k, v)),
+            Iter::Heap(iter) => iter.next(),
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> IntoIterator for &'a SmallMap<K, V, N> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match &self.repr {
+            Repr::Inline { slots, len } => Iter::Inline(slots[..*len].iter()),
+            Repr::Heap(map) => Iter::Heap(map.into_iter()),
+        }
+    }
+}
+
+/// Owned iterator for SmallMap
+pub enum IntoIter<K, V, const N: usize> {
+    Inline(array::IntoIter<Option<(K, V)>, N>),
+    Heap(HashMapIntoIter<K, V>),
+}
+
+impl<K, V, const N: usize> Iterator for IntoIter<K, V, N> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            IntoIter::Inline(slots) => slots.next()?,
+            IntoIter::Heap(iter) => iter.next(),
+        }
+    }
+}
+
+impl<K, V, const N: usize> IntoIterator for SmallMap<K, V, N> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self.repr {
+            Repr::Inline { slots, .. } => IntoIter::Inline(slots.into_iter()),
+            Repr::Heap(map) => IntoIter::Heap(map.into_iter()),
+        }
+    }
+}
+
+impl<K, V, const N: usize> FromIterator<(K, V)> for SmallMap<K, V, N>
+where
+    K: Hash + PartialEq,
+{
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = SmallMap::new();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    #[test]
+    fn test_inline_then_spill() {
+        let mut map: SmallMap<&str, i32, 2> = SmallMap::new();
+        assert_eq!(map.insert("foo", 1), None);
+        assert_eq!(map.insert("bar", 2), None);
+        assert_eq!(map.insert("foo", 3), Some(1));
+        assert!(map.is_inline());
+        assert_eq!(map.insert("baz", 4), None);
+        assert!(!map.is_inline());
+        assert_eq!(map.len(), 3);
+        assert_eq!(map["foo"], 3);
+        assert_eq!(map["bar"], 2);
+        assert_eq!(map["baz"], 4);
+    }
+
+    #[test]
+    fn test_remove_keeps_inline_packed() {
+        let mut map: SmallMap<i32, i32, 4> = (0..4).map(|i| (i, i)).collect();
+        assert_eq!(map.remove(&1), Some(1));
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.iter().count(), 3);
+        // the freed slot is reused without spilling
+        map.insert(9, 9);
+        assert!(map.is_inline());
+        assert!([0, 2, 3, 9].iter().all(|k| map.contains_key(k)));
+    }
+
+    #[test]
+    fn test_get_mut_and_remove_entry() {
+        // 3 entries fit inline, 6 have spilled
+        for n in [3, 6] {
+            let mut map: SmallMap<String, i32, 4> = (0..n).map(|i| (i.to_string(), i)).collect();
+            assert_eq!(map.is_inline(), n <= 4);
+            *map.get_mut("1").unwrap() += 10;
+            assert_eq!(map.get_mut("x"), None);
+            assert_eq!(map.get("1"), Some(&11));
+            assert_eq!(map.remove_entry("2"), Some(("2".to_string(), 2)));
+            assert_eq!(map.remove_entry("2"), None);
+            assert_eq!(map.len(), n as usize - 1);
+        }
+    }
+
+    #[test]
+    fn test_retain() {
+        for n in [4, 10] {
+            let mut map: SmallMap<i32, i32, 4> = (0..n).map(|i| (i, i)).collect();
+            assert_eq!(map.is_inline(), n <= 4);
+            map.retain(|k, v| {
+                *v *= 10;
+                k % 2 == 0
+            });
+            assert_eq!(map.len(), n as usize / 2);
+            assert_eq!(map.iter().count(), map.len());
+            for k in 0..n {
+                assert_eq!(map.get(&k), (k % 2 == 0).then_some(&(k * 10)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut map: SmallMap<&str, i32, 1> = SmallMap::new();
+        *map.entry("foo").or_insert(0) += 1;
+        *map.entry("foo").or_insert(0) += 1;
+        assert!(map.is_inline());
+        // the vacant entry spills the map on insert
+        assert_eq!(map.entry("bar").or_insert_with(|| 42), &42);
+        assert!(!map.is_inline());
+        *map.entry("foo").or_default() += 1;
+        assert_eq!(map.get("foo"), Some(&3));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_iterators() {
+        for n in [2, 10] {
+            let pairs = [("foo", 42), ("bar", 23), ("baz", 142)];
+            let map: SmallMap<_, _, 4> = pairs.iter().cloned().take(n).collect();
+            let mut borrowed: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+            let mut owned: Vec<_> = map.into_iter().collect();
+            borrowed.sort_unstable();
+            owned.sort_unstable();
+            assert_eq!(borrowed, owned);
+            assert_eq!(owned.len(), n.min(3));
+        }
+        let map: SmallMap<i32, i32, 2> = (0..10).map(|i| (i, i)).collect();
+        assert_eq!(map.iter().count(), 10);
+        assert_eq!(map.into_iter().map(|(_, v)| v).sum::<i32>(), 45);
+    }
+}
@@ -0,0 +1,166 @@
+use alloc::vec::Vec;
+use core::{iter, mem, slice};
+
+use super::TableStrategy;
+use crate::Equivalent;
+
+const INITIAL_NBUCKETS: usize = 1;
+
+/// Separate chaining, the same layout `HashMap` uses: each bucket is a
+/// `Vec` of entries that hashed to it.
+pub struct Chaining<K, V> {
+    buckets: Vec<Vec<(u64, K, V)>>,
+    items: usize,
+}
+
+impl<K, V> Default for Chaining<K, V> {
+    fn default() -> Self {
+        Self {
+            buckets: Vec::new(),
+            items: 0,
+        }
+    }
+}
+
+impl<K, V> Chaining<K, V> {
+    fn bucket(&self, hash: u64) -> usize {
+        (hash % self.buckets.len() as u64) as usize
+    }
+
+    fn resize(&mut self) {
+        let target_size = match self.buckets.len() {
+            0 => INITIAL_NBUCKETS,
+            n => 2 * n,
+        };
+        let mut new_buckets: Vec<Vec<_>> = (0..target_size).map(|_| Vec::new()).collect();
+        for (hash, key, value) in self.buckets.drain(..).flatten() {
+            let bucket = (hash % target_size as u64) as usize;
+            new_buckets[bucket].push((hash, key, value));
+        }
+        mem::swap(&mut self.buckets, &mut new_buckets);
+    }
+}
+
+type Flat<'a, K, V> = iter::Flatten<slice::Iter<'a, Vec<(u64, K, V)>>>;
+
+/// Iterator over the chains of a `Chaining` table
+pub struct Iter<'a, K, V> {
+    inner: Flat<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, k, v)| (k, v))
+    }
+}
+
+impl<K, V> TableStrategy<K, V> for Chaining<K, V> {
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.items
+    }
+
+    fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        self.buckets[self.bucket(hash)]
+            .iter()
+            .find(|(h, k, _)| *h == hash && key.equivalent(k))
+            .map(|(_, k, v)| (k, v))
+    }
+
+    fn get_mut<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut V>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(hash);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(h, k, _)| *h == hash && key.equivalent(k))
+            .map(|(_, _, v)| v)
+    }
+
+    fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq,
+    {
+        // If buckets is empty or if map is 3/4th full
+        if self.buckets.is_empty() || self.items > self.buckets.len() * 3 / 4 {
+            self.resize();
+        }
+        let bucket = self.bucket(hash);
+        let bucket = &mut self.buckets[bucket];
+        for (h, ekey, evalue) in bucket.iter_mut() {
+            if *h == hash && *ekey == key {
+                return Some(mem::replace(evalue, value));
+            }
+        }
+        bucket.push((hash, key, value));
+        self.items += 1;
+        None
+    }
+
+    fn remove<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(hash);
+        let bucket = &mut self.buckets[bucket];
+        let i = bucket
+            .iter()
+            .position(|(h, k, _)| *h == hash && key.equivalent(k))?;
+        let (_, k, v) = bucket.swap_remove(i);
+        self.items -= 1;
+        Some((k, v))
+    }
+
+    fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.items = 0;
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter {
+            inner: self.buckets.iter().flatten(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_bucket() {
+        let mut table = Chaining::default();
+        // identical hashes land in the same chain
+        table.insert(5, "a", 1);
+        table.insert(5, "b", 2);
+        table.insert(5, "c", 3);
+        assert_eq!(table.remove(5, "b"), Some(("b", 2)));
+        assert_eq!(table.get(5, "a"), Some((&"a", &1)));
+        assert_eq!(table.get(5, "c"), Some((&"c", &3)));
+        assert_eq!(table.len(), 2);
+    }
+}
@@ -0,0 +1,255 @@
+use alloc::{vec, vec::Vec};
+use core::{mem, slice};
+
+use super::TableStrategy;
+use crate::Equivalent;
+
+const INITIAL_NBUCKETS: usize = 1;
+// end of a chain or of the free list
+const NIL: u32 = u32::MAX;
+
+struct Node<K, V> {
+    next: u32,
+    // None while the node is on the free list
+    entry: Option<(u64, K, V)>,
+}
+
+/// Separate chaining without a `Vec` per bucket.
+///
+/// All entries live in one `nodes` vector and each bucket stores the index
+/// of the first node of its chain, with the rest linked through `next`.
+/// Removed nodes go on a free list and are reused by later inserts. A
+/// resize only allocates the new bucket array and relinks the nodes in
+/// place, instead of allocating every bucket again like `Chaining` does.
+pub struct IndexChaining<K, V> {
+    heads: Vec<u32>,
+    nodes: Vec<Node<K, V>>,
+    free: u32,
+    items: usize,
+}
+
+impl<K, V> Default for IndexChaining<K, V> {
+    fn default() -> Self {
+        Self {
+            heads: Vec::new(),
+            nodes: Vec::new(),
+            free: NIL,
+            items: 0,
+        }
+    }
+}
+
+impl<K, V> IndexChaining<K, V> {
+    fn bucket(&self, hash: u64) -> usize {
+        (hash % self.heads.len() as u64) as usize
+    }
+
+    fn resize(&mut self) {
+        let target_size = match self.heads.len() {
+            0 => INITIAL_NBUCKETS,
+            n => 2 * n,
+        };
+        self.heads = vec![NIL; target_size];
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            if let Some((hash, _, _)) = node.entry {
+                let bucket = (hash % target_size as u64) as usize;
+                node.next = mem::replace(&mut self.heads[bucket], i as u32);
+            }
+        }
+    }
+
+    /// Return the index of the node holding the key, if any
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.heads.is_empty() {
+            return None;
+        }
+        let mut idx = self.heads[self.bucket(hash)];
+        while idx != NIL {
+            let node = &self.nodes[idx as usize];
+            match &node.entry {
+                Some((h, k, _)) if *h == hash && key.equivalent(k) => {
+                    return Some(idx as usize);
+                }
+                _ => idx = node.next,
+            }
+        }
+        None
+    }
+
+    /// Take a node off the free list, or append a new one
+    fn alloc(&mut self, entry: (u64, K, V), next: u32) -> u32 {
+        if self.free != NIL {
+            let idx = self.free;
+            let node = &mut self.nodes[idx as usize];
+            self.free = node.next;
+            node.next = next;
+            node.entry = Some(entry);
+            return idx;
+        }
+        let idx = u32::try_from(self.nodes.len())
+            .ok()
+            .filter(|&idx| idx != NIL)
+            .expect("too many entries");
+        self.nodes.push(Node {
+            next,
+            entry: Some(entry),
+        });
+        idx
+    }
+}
+
+/// Iterator over the live nodes of an `IndexChaining` table
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find_map(|node| node.entry.as_ref().map(|(_, k, v)| (k, v)))
+    }
+}
+
+impl<K, V> TableStrategy<K, V> for IndexChaining<K, V> {
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.items
+    }
+
+    fn capacity(&self) -> usize {
+        self.heads.len()
+    }
+
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        self.nodes[idx].entry.as_ref().map(|(_, k, v)| (k, v))
+    }
+
+    fn get_mut<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut V>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        self.nodes[idx].entry.as_mut().map(|(_, _, v)| v)
+    }
+
+    fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq,
+    {
+        if let Some(v) = self.get_mut(hash, &key) {
+            return Some(mem::replace(v, value));
+        }
+        // If buckets is empty or if map is 3/4th full
+        if self.heads.is_empty() || self.items > self.heads.len() * 3 / 4 {
+            self.resize();
+        }
+        let bucket = self.bucket(hash);
+        let head = self.heads[bucket];
+        self.heads[bucket] = self.alloc((hash, key, value), head);
+        self.items += 1;
+        None
+    }
+
+    fn remove<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.heads.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket(hash);
+        let mut prev = NIL;
+        let mut idx = self.heads[bucket];
+        while idx != NIL {
+            let node = &self.nodes[idx as usize];
+            match &node.entry {
+                Some((h, k, _)) if *h == hash && key.equivalent(k) => break,
+                _ => {
+                    prev = idx;
+                    idx = node.next;
+                }
+            }
+        }
+        if idx == NIL {
+            return None;
+        }
+        // unlink the node and put it on the free list
+        let node = &mut self.nodes[idx as usize];
+        let next = mem::replace(&mut node.next, self.free);
+        let (_, k, v) = node.entry.take().expect("chained nodes are full");
+        match prev {
+            NIL => self.heads[bucket] = next,
+            prev => self.nodes[prev as usize].next = next,
+        }
+        self.free = idx;
+        self.items -= 1;
+        Some((k, v))
+    }
+
+    fn clear(&mut self) {
+        self.heads.iter_mut().for_each(|head| *head = NIL);
+        self.nodes.clear();
+        self.free = NIL;
+        self.items = 0;
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter {
+            inner: self.nodes.iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_bucket() {
+        let mut table = IndexChaining::default();
+        // identical hashes land in the same chain
+        table.insert(5, "a", 1);
+        table.insert(5, "b", 2);
+        table.insert(5, "c", 3);
+        assert_eq!(table.remove(5, "b"), Some(("b", 2)));
+        assert_eq!(table.get(5, "a"), Some((&"a", &1)));
+        assert_eq!(table.get(5, "c"), Some((&"c", &3)));
+        assert_eq!(table.remove(5, "c"), Some(("c", 3)));
+        assert_eq!(table.remove(5, "a"), Some(("a", 1)));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_free_list_reuse() {
+        let mut table = IndexChaining::default();
+        for i in 0..100u64 {
+            table.insert(i, i, i);
+        }
+        let nodes = table.nodes.len();
+        let buffer = table.nodes.as_ptr();
+        // churn reuses freed nodes instead of growing the node vector
+        for i in 100..1000u64 {
+            assert_eq!(table.remove(i - 100, &(i - 100)), Some((i - 100, i - 100)));
+            table.insert(i, i, i);
+        }
+        assert_eq!(table.nodes.len(), nodes);
+        assert_eq!(table.nodes.as_ptr(), buffer);
+        assert_eq!(table.len(), 100);
+        assert_eq!(table.get(950, &950), Some((&950, &950)));
+    }
+}
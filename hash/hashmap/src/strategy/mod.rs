@@ -0,0 +1,277 @@
+//! Pluggable collision resolution.
+//!
+//! `StrategyMap` is a map front-end that hashes keys and leaves the
+//! placement of entries to a `TableStrategy`. The strategy is picked with
+//! a type parameter, so the same calling code can run on separate chaining
+//! (with a `Vec` per bucket or index-linked), linear probing, quadratic
+//! probing or Robin Hood hashing:
+//!
+//! ```
+//! use hashmap::strategy::{RobinHood, StrategyMap};
+//!
+//! let mut map: StrategyMap<&str, i32, RobinHood<_, _>> = StrategyMap::new();
+//! map.insert("foo", 42);
+//! assert_eq!(map.get("foo"), Some(&42));
+//! ```
+
+use core::{hash::Hash, iter::FromIterator, marker::PhantomData, ops};
+
+use crate::{hashers::make_hash, Equivalent};
+
+mod chaining;
+mod index_chaining;
+mod open;
+mod robin_hood;
+
+pub use chaining::Chaining;
+pub use index_chaining::IndexChaining;
+pub use open::{Linear, LinearProbing, OpenAddressing, Probe, Quadratic, QuadraticProbing};
+pub use robin_hood::RobinHood;
+
+/// A table layout that stores `(K, V)` pairs under a precomputed hash.
+///
+/// The front-end computes the hash once and hands it to the strategy
+/// together with the key. Strategies keep the hash next to each entry so
+/// that they can grow without having to hash the keys again.
+pub trait TableStrategy<K, V>: Default {
+    /// Iterator over the entries of the table in slot order
+    type Iter<'a>: Iterator<Item = (&'a K, &'a V)>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    /// Number of entries stored in the table
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of buckets or slots currently allocated
+    fn capacity(&self) -> usize;
+
+    /// Look up the entry with the given hash and key
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + ?Sized;
+
+    /// Look up the value with the given hash and key for modification
+    fn get_mut<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut V>
+    where
+        Q: Equivalent<K> + ?Sized;
+
+    /// Insert a pair, returning the previous value if the key was present
+    fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq;
+
+    /// Remove the entry with the given hash and key
+    fn remove<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, V)>
+    where
+        Q: Equivalent<K> + ?Sized;
+
+    /// Drop every entry but keep the allocation
+    fn clear(&mut self);
+
+    fn iter(&self) -> Self::Iter<'_>;
+}
+
+/// Map front-end that is generic over its collision-resolution strategy
+pub struct StrategyMap<K, V, S = Chaining<K, V>> {
+    table: S,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, S: Default> Default for StrategyMap<K, V, S> {
+    fn default() -> Self {
+        Self {
+            table: S::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> StrategyMap<K, V, S>
+where
+    K: Hash + PartialEq,
+    S: TableStrategy<K, V>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = make_hash(&key);
+        self.table.insert(hash, key, value)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.table.get(make_hash(key), key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.table.get_mut(make_hash(key), key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.table.remove(make_hash(key), key).map(|(_, v)| v)
+    }
+
+    /// return the number of elements in the map
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// return true if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// return the number of buckets or slots allocated by the strategy
+    pub fn capacity(&self) -> usize {
+        self.table.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn iter(&self) -> S::Iter<'_> {
+        self.table.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a StrategyMap<K, V, S>
+where
+    K: Hash + PartialEq,
+    S: TableStrategy<K, V>,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = S::Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for StrategyMap<K, V, S>
+where
+    K: Hash + PartialEq,
+    S: TableStrategy<K, V>,
+{
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = StrategyMap::new();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+impl<K, Q: ?Sized, V, S> ops::Index<&Q> for StrategyMap<K, V, S>
+where
+    K: Hash + PartialEq,
+    Q: Hash + Equivalent<K>,
+    S: TableStrategy<K, V>,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    // Runs the same workload on every strategy so that they can be
+    // checked against each other.
+    fn exercise<S: TableStrategy<u32, u32>>() {
+        let mut map: StrategyMap<u32, u32, S> = StrategyMap::new();
+        assert!(map.is_empty());
+        for i in 0..1000 {
+            assert_eq!(map.insert(i, i * 2), None);
+        }
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.insert(7, 0), Some(14));
+        assert_eq!(map.len(), 1000);
+        for i in (0..1000).step_by(2) {
+            assert!(map.remove(&i).is_some());
+        }
+        assert_eq!(map.len(), 500);
+        for i in 0..1000 {
+            let expected = match i {
+                7 => Some(0),
+                i if i % 2 == 1 => Some(i * 2),
+                _ => None,
+            };
+            assert_eq!(map.get(&i).copied(), expected);
+        }
+        *map.get_mut(&9).unwrap() += 1;
+        assert_eq!(map[&9], 19);
+        assert_eq!(map.iter().count(), 500);
+        let sum: u32 = map.iter().map(|(k, _)| k).sum();
+        assert_eq!(sum, (1..1000).step_by(2).sum());
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&1), None);
+    }
+
+    #[test]
+    fn test_chaining() {
+        exercise::<Chaining<u32, u32>>();
+    }
+
+    #[test]
+    fn test_index_chaining() {
+        exercise::<IndexChaining<u32, u32>>();
+    }
+
+    #[test]
+    fn test_linear_probing() {
+        exercise::<LinearProbing<u32, u32>>();
+    }
+
+    #[test]
+    fn test_quadratic_probing() {
+        exercise::<QuadraticProbing<u32, u32>>();
+    }
+
+    #[test]
+    fn test_robin_hood() {
+        exercise::<RobinHood<u32, u32>>();
+    }
+
+    #[test]
+    fn test_borrowed_keys() {
+        let map: StrategyMap<String, i32, RobinHood<_, _>> =
+            [("foo".to_string(), 42), ("bar".to_string(), 23)]
+                .into_iter()
+                .collect();
+        assert_eq!(map.get("foo"), Some(&42));
+        assert_eq!(map["bar"], 23);
+        assert!(!map.contains_key("baz"));
+    }
+}
@@ -0,0 +1,306 @@
+use alloc::vec::Vec;
+use core::{marker::PhantomData, mem, slice};
+
+use super::TableStrategy;
+use crate::Equivalent;
+
+const MIN_SLOTS: usize = 8;
+
+/// Probe sequence used by `OpenAddressing` to find the next slot to try.
+///
+/// `offset(i)` is the distance from the home slot on the i-th probe. The
+/// table size is always a power of two, so the sequence must visit every
+/// slot of such a table before repeating.
+pub trait Probe {
+    fn offset(i: usize) -> usize;
+}
+
+/// Probe consecutive slots: `h, h + 1, h + 2, ...`
+pub struct Linear;
+
+impl Probe for Linear {
+    fn offset(i: usize) -> usize {
+        i
+    }
+}
+
+/// Probe with triangular numbers: `h, h + 1, h + 3, h + 6, ...`, which
+/// covers every slot of a power of two sized table.
+pub struct Quadratic;
+
+impl Probe for Quadratic {
+    fn offset(i: usize) -> usize {
+        i * (i + 1) / 2
+    }
+}
+
+enum Slot<K, V> {
+    Empty,
+    // left behind by a removal so that probe sequences passing through
+    // this slot keep going
+    Tombstone,
+    Full(u64, K, V),
+}
+
+/// Open addressing with tombstone deletion. The probe sequence is chosen
+/// with the `P` parameter; see `LinearProbing` and `QuadraticProbing`.
+pub struct OpenAddressing<K, V, P> {
+    slots: Vec<Slot<K, V>>,
+    items: usize,
+    tombstones: usize,
+    _probe: PhantomData<P>,
+}
+
+pub type LinearProbing<K, V> = OpenAddressing<K, V, Linear>;
+pub type QuadraticProbing<K, V> = OpenAddressing<K, V, Quadratic>;
+
+impl<K, V, P> Default for OpenAddressing<K, V, P> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            items: 0,
+            tombstones: 0,
+            _probe: PhantomData,
+        }
+    }
+}
+
+impl<K, V, P: Probe> OpenAddressing<K, V, P> {
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// Walk the probe sequence for `hash` and return the slot holding the
+    /// matching key, if any.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mask = self.mask();
+        let home = hash as usize & mask;
+        for i in 0..self.slots.len() {
+            let idx = (home + P::offset(i)) & mask;
+            match &self.slots[idx] {
+                Slot::Empty => return None,
+                Slot::Tombstone => {}
+                Slot::Full(h, k, _) => {
+                    if *h == hash && key.equivalent(k) {
+                        return Some(idx);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Rebuild the table with `target_size` slots, dropping all tombstones
+    fn rehash(&mut self, target_size: usize) {
+        let old = mem::replace(
+            &mut self.slots,
+            (0..target_size).map(|_| Slot::Empty).collect(),
+        );
+        self.tombstones = 0;
+        let mask = self.mask();
+        for slot in old {
+            if let Slot::Full(hash, key, value) = slot {
+                let home = hash as usize & mask;
+                let mut i = 0;
+                loop {
+                    let idx = (home + P::offset(i)) & mask;
+                    if let Slot::Empty = self.slots[idx] {
+                        self.slots[idx] = Slot::Full(hash, key, value);
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    fn reserve_one(&mut self) {
+        let used = self.items + self.tombstones + 1;
+        if used > self.slots.len() * 3 / 4 {
+            // Only grow when live entries need the room. Otherwise the
+            // rehash at the same size is just clearing out tombstones.
+            let target_size = if (self.items + 1) * 2 > self.slots.len() {
+                (self.slots.len() * 2).max(MIN_SLOTS)
+            } else {
+                self.slots.len()
+            };
+            self.rehash(target_size);
+        }
+    }
+}
+
+/// Iterator over the occupied slots of an `OpenAddressing` table
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Slot<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for slot in self.inner.by_ref() {
+            if let Slot::Full(_, k, v) = slot {
+                return Some((k, v));
+            }
+        }
+        None
+    }
+}
+
+impl<K, V, P: Probe> TableStrategy<K, V> for OpenAddressing<K, V, P> {
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a,
+        P: 'a;
+
+    fn len(&self) -> usize {
+        self.items
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        match &self.slots[self.find(hash, key)?] {
+            Slot::Full(_, k, v) => Some((k, v)),
+            _ => unreachable!("find only returns occupied slots"),
+        }
+    }
+
+    fn get_mut<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut V>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        match &mut self.slots[idx] {
+            Slot::Full(_, _, v) => Some(v),
+            _ => unreachable!("find only returns occupied slots"),
+        }
+    }
+
+    fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq,
+    {
+        if let Some(v) = self.get_mut(hash, &key) {
+            return Some(mem::replace(v, value));
+        }
+        self.reserve_one();
+        // The key is known to be absent, so reuse the first tombstone or
+        // empty slot on the probe sequence.
+        let mask = self.mask();
+        let home = hash as usize & mask;
+        let mut i = 0;
+        loop {
+            let idx = (home + P::offset(i)) & mask;
+            match self.slots[idx] {
+                Slot::Full(..) => i += 1,
+                Slot::Tombstone => {
+                    self.tombstones -= 1;
+                    self.slots[idx] = Slot::Full(hash, key, value);
+                    break;
+                }
+                Slot::Empty => {
+                    self.slots[idx] = Slot::Full(hash, key, value);
+                    break;
+                }
+            }
+        }
+        self.items += 1;
+        None
+    }
+
+    fn remove<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        self.items -= 1;
+        self.tombstones += 1;
+        match mem::replace(&mut self.slots[idx], Slot::Tombstone) {
+            Slot::Full(_, k, v) => Some((k, v)),
+            _ => unreachable!("find only returns occupied slots"),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = Slot::Empty);
+        self.items = 0;
+        self.tombstones = 0;
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter {
+            inner: self.slots.iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn probe_past_tombstone<P: Probe>() {
+        let mut table: OpenAddressing<_, _, P> = OpenAddressing::default();
+        // all three keys share a home slot and form one probe sequence
+        table.insert(3, "a", 1);
+        table.insert(3, "b", 2);
+        table.insert(3, "c", 3);
+        assert_eq!(table.remove(3, "b"), Some(("b", 2)));
+        assert_eq!(table.get(3, "c"), Some((&"c", &3)));
+        // the tombstone is reused by the next insert on the same sequence
+        table.insert(3, "d", 4);
+        assert_eq!(table.tombstones, 0);
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_linear_tombstone() {
+        probe_past_tombstone::<Linear>();
+    }
+
+    #[test]
+    fn test_quadratic_tombstone() {
+        probe_past_tombstone::<Quadratic>();
+    }
+
+    #[test]
+    fn test_quadratic_covers_table() {
+        let size = 64;
+        let mut seen = vec![false; size];
+        for i in 0..size {
+            seen[Quadratic::offset(i) & (size - 1)] = true;
+        }
+        assert!(seen.into_iter().all(|s| s));
+    }
+
+    #[test]
+    fn test_churn_does_not_grow() {
+        let mut table: LinearProbing<u64, u64> = OpenAddressing::default();
+        for i in 0..4 {
+            table.insert(i, i, i);
+        }
+        let capacity = table.capacity();
+        // removing and inserting fresh keys leaves tombstones behind, which
+        // must be cleaned up without doubling the table every time
+        for i in 4..1000 {
+            table.remove(i - 4, &(i - 4));
+            table.insert(i, i, i);
+        }
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.capacity(), capacity);
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::vec::Vec;
+use core::{mem, slice};
+
+use super::TableStrategy;
+use crate::Equivalent;
+
+const MIN_SLOTS: usize = 8;
+
+/// Robin Hood hashing with backward-shift deletion.
+///
+/// Linear probing where an inserted entry takes the slot of any entry that
+/// is closer to its home slot than the new one is, so probe lengths stay
+/// short and even. Removal shifts the following run back by one slot
+/// instead of leaving a tombstone.
+pub struct RobinHood<K, V> {
+    slots: Vec<Option<(u64, K, V)>>,
+    items: usize,
+}
+
+impl<K, V> Default for RobinHood<K, V> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            items: 0,
+        }
+    }
+}
+
+impl<K, V> RobinHood<K, V> {
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// How far the entry with `hash` sitting at `idx` is from its home slot
+    fn distance(&self, hash: u64, idx: usize) -> usize {
+        idx.wrapping_sub(hash as usize) & self.mask()
+    }
+
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mask = self.mask();
+        let mut idx = hash as usize & mask;
+        let mut dist = 0;
+        loop {
+            match &self.slots[idx] {
+                None => return None,
+                Some((h, k, _)) => {
+                    // An entry closer to home than we are means the key
+                    // would have displaced it on insert, so it is absent.
+                    if self.distance(*h, idx) < dist {
+                        return None;
+                    }
+                    if *h == hash && key.equivalent(k) {
+                        return Some(idx);
+                    }
+                }
+            }
+            idx = (idx + 1) & mask;
+            dist += 1;
+        }
+    }
+
+    /// Place an entry known to be absent from the table
+    fn place(&mut self, mut entry: (u64, K, V)) {
+        let mask = self.mask();
+        let mut idx = entry.0 as usize & mask;
+        let mut dist = 0;
+        loop {
+            match &mut self.slots[idx] {
+                slot @ None => {
+                    *slot = Some(entry);
+                    return;
+                }
+                Some(existing) => {
+                    let existing_dist = idx.wrapping_sub(existing.0 as usize) & mask;
+                    if existing_dist < dist {
+                        // take from the rich, carry on with the evicted entry
+                        mem::swap(existing, &mut entry);
+                        dist = existing_dist;
+                    }
+                }
+            }
+            idx = (idx + 1) & mask;
+            dist += 1;
+        }
+    }
+
+    fn resize(&mut self) {
+        let target_size = (self.slots.len() * 2).max(MIN_SLOTS);
+        let old = mem::replace(&mut self.slots, (0..target_size).map(|_| None).collect());
+        for entry in old.into_iter().flatten() {
+            self.place(entry);
+        }
+    }
+}
+
+/// Iterator over the occupied slots of a `RobinHood` table
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Option<(u64, K, V)>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.by_ref().flatten().next().map(|(_, k, v)| (k, v))
+    }
+}
+
+impl<K, V> TableStrategy<K, V> for RobinHood<K, V> {
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.items
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        self.slots[idx].as_ref().map(|(_, k, v)| (k, v))
+    }
+
+    fn get_mut<Q>(&mut self, hash: u64, key: &Q) -> Option<&mut V>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let idx = self.find(hash, key)?;
+        self.slots[idx].as_mut().map(|(_, _, v)| v)
+    }
+
+    fn insert(&mut self, hash: u64, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq,
+    {
+        if let Some(v) = self.get_mut(hash, &key) {
+            return Some(mem::replace(v, value));
+        }
+        // Robin Hood tolerates a higher load factor than plain probing
+        if (self.items + 1) * 8 > self.slots.len() * 7 {
+            self.resize();
+        }
+        self.place((hash, key, value));
+        self.items += 1;
+        None
+    }
+
+    fn remove<Q>(&mut self, hash: u64, key: &Q) -> Option<(K, V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        let mut idx = self.find(hash, key)?;
+        let (_, k, v) = self.slots[idx].take()?;
+        self.items -= 1;
+        // Backward shift: pull every displaced entry after the hole one
+        // slot closer to home until an empty slot or an entry already in
+        // its home slot ends the run.
+        let mask = self.mask();
+        loop {
+            let next = (idx + 1) & mask;
+            match &self.slots[next] {
+                Some((h, _, _)) if self.distance(*h, next) > 0 => {
+                    self.slots[idx] = self.slots[next].take();
+                    idx = next;
+                }
+                _ => break,
+            }
+        }
+        Some((k, v))
+    }
+
+    fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.items = 0;
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter {
+            inner: self.slots.iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backward_shift() {
+        let mut table = RobinHood::default();
+        table.insert(1, "a", 1);
+        table.insert(1, "b", 2);
+        table.insert(2, "c", 3);
+        table.insert(1, "d", 4);
+        assert_eq!(table.remove(1, "a"), Some(("a", 1)));
+        for (key, hash) in [("b", 1), ("c", 2), ("d", 1)] {
+            assert!(table.get(hash, key).is_some());
+        }
+        // after the shift the home slot of "b" is occupied by "b" itself
+        assert_eq!(table.slots[1].as_ref().map(|e| e.1), Some("b"));
+        assert_eq!(table.slots[4], None);
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_crowded_removal() {
+        let mut table = RobinHood::default();
+        for i in 0u64..64 {
+            // crowd everything into a few home slots
+            table.insert(i % 4, i, ());
+        }
+        for i in (0u64..64).step_by(3) {
+            assert_eq!(table.remove(i % 4, &i), Some((i, ())));
+        }
+        for i in 0u64..64 {
+            assert_eq!(table.get(i % 4, &i).is_some(), i % 3 != 0);
+        }
+        // backward shift leaves no gap in front of a displaced entry
+        for (idx, slot) in table.slots.iter().enumerate() {
+            if let Some((h, _, _)) = slot {
+                if table.distance(*h, idx) > 0 {
+                    let prev = (idx + table.slots.len() - 1) & table.mask();
+                    assert!(table.slots[prev].is_some());
+                }
+            }
+        }
+    }
+}
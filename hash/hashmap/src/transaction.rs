@@ -0,0 +1,284 @@
+//! All-or-nothing batches of updates.
+//!
+//! `HashMap::begin` returns a `Transaction` that mutates the map directly
+//! and records how to undo every change. `rollback` (or dropping the
+//! transaction without calling `commit`) replays the undo log backwards,
+//! which leaves the map with exactly the entries it had before. The bucket
+//! array is not shrunk back, so the capacity may stay larger.
+//!
+//! `savepoint` starts a nested transaction. Rolling it back only undoes
+//! the changes made since the savepoint; committing it hands its undo log
+//! to the enclosing transaction, so they are still undone if that one is
+//! rolled back.
+
+use alloc::vec::Vec;
+use core::{
+    hash::{BuildHasher, Hash},
+    ops,
+};
+
+use crate::{
+    allocator::{Allocator, Global},
+    hashers::DefaultHashBuilder,
+    Entry, Equivalent, HashMap,
+};
+
+enum Undo<K, V> {
+    // the key was not in the map before
+    Remove(K),
+    // the key had this value before
+    Restore(K, V),
+}
+
+/// Handle for a batch of changes to a `HashMap`. Reads go through `Deref`.
+pub struct Transaction<'a, K, V, S = DefaultHashBuilder, A = Global>
+where
+    K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    map: &'a mut HashMap<K, V, S, A>,
+    log: Vec<Undo<K, V>>,
+    // undo log of the enclosing transaction, if this is a savepoint
+    parent: Option<&'a mut Vec<Undo<K, V>>>,
+    finished: bool,
+}
+
+impl<K, V, S, A> HashMap<K, V, S, A>
+where
+    K: Hash + PartialEq + Clone,
+    V: Clone,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    /// Start a transaction that is rolled back unless it is committed
+    pub fn begin(&mut self) -> Transaction<'_, K, V, S, A> {
+        Transaction {
+            map: self,
+            log: Vec::new(),
+            parent: None,
+            finished: false,
+        }
+    }
+}
+
+impl<K, V, S, A> Transaction<'_, K, V, S, A>
+where
+    K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn undo(&mut self) {
+        while let Some(undo) = self.log.pop() {
+            match undo {
+                Undo::Remove(key) => {
+                    self.map.remove(&key);
+                }
+                Undo::Restore(key, value) => {
+                    self.map.insert(key, value);
+                }
+            }
+        }
+        self.finished = true;
+    }
+}
+
+impl<K, V, S, A> Transaction<'_, K, V, S, A>
+where
+    K: Hash + PartialEq + Clone,
+    V: Clone,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let undo_key = key.clone();
+        let old = self.map.insert(key, value);
+        self.log.push(match &old {
+            Some(old) => Undo::Restore(undo_key, old.clone()),
+            None => Undo::Remove(undo_key),
+        });
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let (key, value) = self.map.remove_entry(key)?;
+        self.log.push(Undo::Restore(key, value.clone()));
+        Some(value)
+    }
+
+    /// Entry API for the transaction. The current state of the key is
+    /// logged up front, so any change made through the entry is undone.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A> {
+        self.log.push(match self.map.get(&key) {
+            Some(value) => Undo::Restore(key.clone(), value.clone()),
+            None => Undo::Remove(key.clone()),
+        });
+        self.map.entry(key)
+    }
+
+    /// Start a nested transaction that can be rolled back on its own
+    pub fn savepoint(&mut self) -> Transaction<'_, K, V, S, A> {
+        Transaction {
+            map: self.map,
+            log: Vec::new(),
+            parent: Some(&mut self.log),
+            finished: false,
+        }
+    }
+
+    /// Keep the changes. For a savepoint they become part of the enclosing
+    /// transaction.
+    pub fn commit(mut self) {
+        if let Some(parent) = self.parent.as_mut() {
+            parent.append(&mut self.log);
+        }
+        self.finished = true;
+    }
+
+    /// Undo every change made in this transaction
+    pub fn rollback(mut self) {
+        self.undo();
+    }
+}
+
+impl<K, V, S, A> ops::Deref for Transaction<'_, K, V, S, A>
+where
+    K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    type Target = HashMap<K, V, S, A>;
+
+    fn deref(&self) -> &HashMap<K, V, S, A> {
+        self.map
+    }
+}
+
+impl<K, V, S, A> Drop for Transaction<'_, K, V, S, A>
+where
+    K: Hash + PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn drop(&mut self) {
+        if !self.finished {
+            self.undo();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{allocator::Bump, hashers::BuildFnvHasher};
+    use alloc::{
+        format,
+        string::{String, ToString},
+        vec::Vec,
+    };
+
+    fn snapshot(map: &HashMap<String, i32>) -> Vec<(String, i32)> {
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        entries.sort();
+        entries
+    }
+
+    fn sample() -> HashMap<String, i32> {
+        (0..20).map(|i| (format!("key{i}"), i)).collect()
+    }
+
+    fn mutate(tx: &mut Transaction<'_, String, i32>) {
+        tx.insert("key1".to_string(), 100);
+        tx.insert("new".to_string(), 1);
+        tx.insert("new".to_string(), 2);
+        tx.remove("key2");
+        tx.remove("missing");
+        *tx.entry("key3".to_string()).or_insert(0) += 10;
+        tx.entry("fresh".to_string()).or_insert(5);
+        // enough inserts to resize the table
+        for i in 100..200 {
+            tx.insert(format!("key{i}"), i);
+        }
+    }
+
+    #[test]
+    fn test_rollback_restores_map() {
+        let mut map = sample();
+        let before = snapshot(&map);
+        let mut tx = map.begin();
+        mutate(&mut tx);
+        assert_eq!(tx.get("key1"), Some(&100));
+        assert_eq!(tx.get("key3"), Some(&13));
+        tx.rollback();
+        assert_eq!(snapshot(&map), before);
+        assert_eq!(map.len(), before.len());
+    }
+
+    #[test]
+    fn test_drop_rolls_back() {
+        let mut map = sample();
+        let before = snapshot(&map);
+        {
+            let mut tx = map.begin();
+            mutate(&mut tx);
+        }
+        assert_eq!(snapshot(&map), before);
+    }
+
+    #[test]
+    fn test_commit_keeps_changes() {
+        let mut map = sample();
+        let mut tx = map.begin();
+        mutate(&mut tx);
+        tx.commit();
+        assert_eq!(map.get("key1"), Some(&100));
+        assert_eq!(map.get("key2"), None);
+        assert_eq!(map.get("fresh"), Some(&5));
+        assert_eq!(map.len(), 20 + 2 + 100 - 1);
+    }
+
+    #[test]
+    fn test_custom_hasher_and_allocator() {
+        let arena = Bump::new();
+        let mut map = HashMap::with_hasher_in(BuildFnvHasher::default(), &arena);
+        map.insert(1, 1);
+        let mut tx = map.begin();
+        tx.insert(1, 10);
+        for i in 2..100 {
+            tx.insert(i, i);
+        }
+        tx.rollback();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn test_savepoints() {
+        let mut map = sample();
+        let before = snapshot(&map);
+        let mut tx = map.begin();
+        tx.insert("outer".to_string(), 1);
+
+        let mut sp = tx.savepoint();
+        sp.insert("inner".to_string(), 2);
+        sp.remove("outer");
+        sp.rollback();
+        assert_eq!(tx.get("outer"), Some(&1));
+        assert_eq!(tx.get("inner"), None);
+
+        let mut sp = tx.savepoint();
+        sp.insert("inner".to_string(), 3);
+        let mut nested = sp.savepoint();
+        nested.insert("deep".to_string(), 4);
+        nested.commit();
+        sp.commit();
+        assert_eq!(tx.get("deep"), Some(&4));
+
+        // committed savepoints are still undone with the outer transaction
+        tx.rollback();
+        assert_eq!(snapshot(&map), before);
+    }
+}
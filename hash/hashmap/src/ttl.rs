@@ -0,0 +1,484 @@
+//! A `HashMap` whose entries can expire.
+//!
+//! Expired entries are dropped lazily when they are looked up, and in bulk
+//! by `purge_expired`, which pops deadlines off a min-heap instead of
+//! walking the whole map. Time comes from a `Clock` so that tests can move
+//! it by hand.
+
+use std::{
+    cell::Cell,
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    hash::Hash,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{Equivalent, HashMap};
+
+/// Source of the current time, as an offset from a fixed origin
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// Wall clock time measured from when the clock was created
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Clock that only moves when told to. Clones share the same time, so a
+/// test can keep one handle and give the other to the map.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now.set(now);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+struct TtlEntry<V> {
+    value: V,
+    deadline: Option<Duration>,
+    // identifies the heap item that belongs to this entry; heap items
+    // left over from an overwritten or removed entry no longer match
+    stamp: u64,
+}
+
+/// Heap item ordered by deadline only, so keys do not need to be `Ord`
+struct Deadline<K> {
+    at: Duration,
+    stamp: u64,
+    key: K,
+}
+
+impl<K> PartialEq for Deadline<K> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.stamp) == (other.at, other.stamp)
+    }
+}
+
+impl<K> Eq for Deadline<K> {}
+
+impl<K> PartialOrd for Deadline<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for Deadline<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.stamp).cmp(&(other.at, other.stamp))
+    }
+}
+
+/// Map with optional per-entry time-to-live
+pub struct TtlMap<K, V, C = SystemClock> {
+    map: HashMap<K, TtlEntry<V>>,
+    deadlines: BinaryHeap<Reverse<Deadline<K>>>,
+    clock: C,
+    next_stamp: u64,
+}
+
+impl<K, V> Default for TtlMap<K, V, SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> TtlMap<K, V, SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::default())
+    }
+}
+
+impl<K, V, C> TtlMap<K, V, C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            map: HashMap::new(),
+            deadlines: BinaryHeap::new(),
+            clock,
+            next_stamp: 0,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// return the number of entries, including expired entries that have
+    /// not been purged yet
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// return true if the map holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn expired(entry: &TtlEntry<V>, now: Duration) -> bool {
+        matches!(entry.deadline, Some(at) if at <= now)
+    }
+}
+
+impl<K, V, C> TtlMap<K, V, C>
+where
+    K: Hash + PartialEq + Clone,
+    C: Clock,
+{
+    fn store(&mut self, key: K, value: V, deadline: Option<Duration>) -> Option<V> {
+        let now = self.clock.now();
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        if let Some(at) = deadline {
+            self.deadlines.push(Reverse(Deadline {
+                at,
+                stamp,
+                key: key.clone(),
+            }));
+        }
+        let entry = TtlEntry {
+            value,
+            deadline,
+            stamp,
+        };
+        let old = self.map.insert(key, entry);
+        self.compact_deadlines();
+        match old {
+            Some(old) if !Self::expired(&old, now) => Some(old.value),
+            _ => None,
+        }
+    }
+
+    /// Overwrites, removes and lazy expiry leave their heap items behind.
+    /// Once those outnumber the entries, rebuild the heap from the live
+    /// deadlines so that refreshing the same keys doesn't grow it forever.
+    fn compact_deadlines(&mut self) {
+        if self.deadlines.len() <= 2 * self.map.len() {
+            return;
+        }
+        self.deadlines = self
+            .map
+            .iter()
+            .filter_map(|(key, entry)| {
+                Some(Reverse(Deadline {
+                    at: entry.deadline?,
+                    stamp: entry.stamp,
+                    key: key.clone(),
+                }))
+            })
+            .collect();
+    }
+
+    /// Insert an entry that never expires. Returns the previous value if
+    /// it had not expired.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.store(key, value, None)
+    }
+
+    /// Insert an entry that expires `ttl` from now
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let deadline = self.clock.now() + ttl;
+        self.store(key, value, Some(deadline))
+    }
+
+    /// Look up a live entry, dropping it first if it has expired
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let now = self.clock.now();
+        if Self::expired(self.map.get(key)?, now) {
+            self.map.remove(key);
+            return None;
+        }
+        self.map.get(key).map(|e| &e.value)
+    }
+
+    /// Mutable version of `get`. The entry keeps its deadline.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let now = self.clock.now();
+        if Self::expired(self.map.get(key)?, now) {
+            self.map.remove(key);
+            return None;
+        }
+        self.map.get_mut(key).map(|e| &mut e.value)
+    }
+
+    /// Give a live entry a new time-to-live, or none at all. Returns false
+    /// if there is no live entry for `key`.
+    pub fn set_ttl<Q>(&mut self, key: &Q, ttl: Option<Duration>) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let now = self.clock.now();
+        let Some((key, entry)) = self.map.remove_entry(key) else {
+            return false;
+        };
+        if Self::expired(&entry, now) {
+            return false;
+        }
+        self.store(key, entry.value, ttl.map(|ttl| now + ttl));
+        true
+    }
+
+    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Remove an entry, returning its value if it had not expired
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let now = self.clock.now();
+        let entry = self.map.remove(key)?;
+        match Self::expired(&entry, now) {
+            true => None,
+            false => Some(entry.value),
+        }
+    }
+
+    /// Time left before `key` expires. None if the key is missing, has
+    /// expired or was inserted without a TTL.
+    pub fn remaining_ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let now = self.clock.now();
+        let at = self.map.get(key)?.deadline?;
+        at.checked_sub(now).filter(|left| !left.is_zero())
+    }
+
+    /// Drop every entry whose deadline is at or before `now` and return
+    /// how many were dropped. Only the expired part of the heap is visited.
+    pub fn purge_expired(&mut self, now: Duration) -> usize {
+        let mut purged = 0;
+        while let Some(Reverse(top)) = self.deadlines.peek() {
+            if top.at > now {
+                break;
+            }
+            let Reverse(item) = self.deadlines.pop().unwrap();
+            // skip heap items whose entry has since been replaced
+            let live = matches!(self.map.get(&item.key), Some(e) if e.stamp == item.stamp);
+            if live {
+                self.map.remove(&item.key);
+                purged += 1;
+            }
+        }
+        purged
+    }
+
+    /// `purge_expired` at the clock's current time
+    pub fn purge(&mut self) -> usize {
+        let now = self.clock.now();
+        self.purge_expired(now)
+    }
+}
+
+impl<K, V, C: Clock> TtlMap<K, V, C> {
+    /// Iterate over the entries that have not expired, in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let now = self.clock.now();
+        self.map
+            .iter()
+            .filter(move |(_, e)| !Self::expired(e, now))
+            .map(|(k, e)| (k, &e.value))
+    }
+
+    /// `HashMap::scan` that skips expired entries, so a batch can come
+    /// back empty before the scan is over
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&K, &V)>) {
+        let now = self.clock.now();
+        let (next, entries) = self.map.scan(cursor, count);
+        let live = entries
+            .into_iter()
+            .filter(|(_, e)| !Self::expired(e, now))
+            .map(|(k, e)| (k, &e.value))
+            .collect();
+        (next, live)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    fn manual() -> (ManualClock, TtlMap<&'static str, i32, ManualClock>) {
+        let clock = ManualClock::new();
+        let map = TtlMap::with_clock(clock.clone());
+        (clock, map)
+    }
+
+    #[test]
+    fn test_lazy_expiry() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("foo", 42, secs(10));
+        map.insert("bar", 23);
+        assert_eq!(map.get("foo"), Some(&42));
+        clock.advance(secs(9));
+        assert_eq!(map.get("foo"), Some(&42));
+        clock.advance(secs(1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("foo"), None);
+        // the lookup removed the expired entry
+        assert_eq!(map.len(), 1);
+        clock.advance(secs(1000));
+        assert_eq!(map.get("bar"), Some(&23));
+    }
+
+    #[test]
+    fn test_remaining_ttl() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("foo", 42, secs(10));
+        map.insert("bar", 23);
+        clock.advance(secs(4));
+        assert_eq!(map.remaining_ttl("foo"), Some(secs(6)));
+        assert_eq!(map.remaining_ttl("bar"), None);
+        assert_eq!(map.remaining_ttl("baz"), None);
+        clock.advance(secs(6));
+        assert_eq!(map.remaining_ttl("foo"), None);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("a", 1, secs(1));
+        map.insert_with_ttl("b", 2, secs(2));
+        map.insert_with_ttl("c", 3, secs(3));
+        map.insert("d", 4);
+        assert_eq!(map.purge_expired(secs(2)), 2);
+        assert_eq!(map.len(), 2);
+        clock.set(secs(5));
+        assert_eq!(map.purge(), 1);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get("d"), Some(&4));
+    }
+
+    #[test]
+    fn test_overwrite_replaces_deadline() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("foo", 1, secs(1));
+        // the old heap item must not expire the new entry
+        assert_eq!(map.insert_with_ttl("foo", 2, secs(10)), Some(1));
+        assert_eq!(map.len(), 1);
+        clock.advance(secs(5));
+        assert_eq!(map.purge(), 0);
+        assert_eq!(map.get("foo"), Some(&2));
+        // dropping the deadline keeps the entry forever
+        map.insert("foo", 3);
+        clock.advance(secs(100));
+        assert_eq!(map.purge(), 0);
+        assert_eq!(map.get("foo"), Some(&3));
+    }
+
+    #[test]
+    fn test_refreshing_keeps_the_heap_bounded() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("other", 0, secs(3600));
+        for i in 0..1000 {
+            map.insert_with_ttl("foo", i, secs(3600));
+            assert!(map.deadlines.len() <= 2 * map.len());
+        }
+        assert_eq!(map.get("foo"), Some(&999));
+        clock.advance(secs(3600));
+        assert_eq!(map.purge(), 2);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_set_ttl() {
+        let (clock, mut map) = manual();
+        map.insert("foo", 1);
+        map.insert_with_ttl("bar", 2, secs(1));
+        assert!(map.set_ttl("foo", Some(secs(10))));
+        assert!(map.set_ttl("bar", None));
+        assert!(!map.set_ttl("baz", Some(secs(1))));
+        *map.get_mut("foo").unwrap() += 10;
+        clock.advance(secs(5));
+        assert_eq!(map.remaining_ttl("foo"), Some(secs(5)));
+        assert_eq!(map.purge(), 0);
+        clock.advance(secs(5));
+        assert_eq!(map.purge(), 1);
+        assert_eq!(map.get_mut("foo"), None);
+        assert_eq!(map.get("bar"), Some(&2));
+    }
+
+    #[test]
+    fn test_iter_and_scan_skip_expired() {
+        let (clock, mut map) = manual();
+        map.insert("a", 1);
+        map.insert_with_ttl("b", 2, secs(1));
+        map.insert_with_ttl("c", 3, secs(10));
+        clock.advance(secs(1));
+        let mut live: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        live.sort();
+        assert_eq!(live, [("a", 1), ("c", 3)]);
+
+        let mut scanned = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = map.scan(cursor, 1);
+            scanned.extend(batch.into_iter().map(|(k, v)| (*k, *v)));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        scanned.sort();
+        assert_eq!(scanned, live);
+    }
+
+    #[test]
+    fn test_expired_values_are_not_returned() {
+        let (clock, mut map) = manual();
+        map.insert_with_ttl("foo", 1, secs(1));
+        clock.advance(secs(1));
+        assert_eq!(map.insert("foo", 2), None);
+        map.insert_with_ttl("bar", 1, secs(1));
+        clock.advance(secs(1));
+        assert_eq!(map.remove("bar"), None);
+        assert!(!map.contains_key("bar"));
+    }
+}
@@ -0,0 +1,384 @@
+//! A map keyed by weak references.
+//!
+//! `WeakKeyHashMap` attaches values to `Rc`/`Arc`-owned objects without
+//! keeping the objects alive. Once the last strong reference to a key is
+//! dropped its entry is dead: lookups no longer find it, and it is removed
+//! by `prune()` or by the periodic pruning that `insert` does.
+
+use alloc::{
+    rc,
+    sync::{self, Arc},
+};
+use core::{
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+use crate::{hashers::make_hash, Entry, Equivalent, HashMap};
+
+// inserts between automatic prunes never drop below this
+const MIN_PRUNE_INTERVAL: usize = 16;
+
+/// A weak pointer that can be upgraded to `Strong`
+pub trait WeakRef<K: ?Sized> {
+    type Strong: Clone;
+
+    fn downgrade(strong: &Self::Strong) -> Self;
+    fn upgrade(&self) -> Option<Self::Strong>;
+    fn target(strong: &Self::Strong) -> &K;
+    fn is_dead(&self) -> bool;
+    /// Address of the pointee, used for identity
+    fn addr(&self) -> *const ();
+    fn strong_addr(strong: &Self::Strong) -> *const ();
+}
+
+impl<K: ?Sized> WeakRef<K> for rc::Weak<K> {
+    type Strong = rc::Rc<K>;
+
+    fn downgrade(strong: &Self::Strong) -> Self {
+        rc::Rc::downgrade(strong)
+    }
+    fn upgrade(&self) -> Option<Self::Strong> {
+        rc::Weak::upgrade(self)
+    }
+    fn target(strong: &Self::Strong) -> &K {
+        strong
+    }
+    fn is_dead(&self) -> bool {
+        self.strong_count() == 0
+    }
+    fn addr(&self) -> *const () {
+        self.as_ptr() as *const ()
+    }
+    fn strong_addr(strong: &Self::Strong) -> *const () {
+        rc::Rc::as_ptr(strong) as *const ()
+    }
+}
+
+impl<K: ?Sized> WeakRef<K> for sync::Weak<K> {
+    type Strong = Arc<K>;
+
+    fn downgrade(strong: &Self::Strong) -> Self {
+        Arc::downgrade(strong)
+    }
+    fn upgrade(&self) -> Option<Self::Strong> {
+        sync::Weak::upgrade(self)
+    }
+    fn target(strong: &Self::Strong) -> &K {
+        strong
+    }
+    fn is_dead(&self) -> bool {
+        self.strong_count() == 0
+    }
+    fn addr(&self) -> *const () {
+        self.as_ptr() as *const ()
+    }
+    fn strong_addr(strong: &Self::Strong) -> *const () {
+        Arc::as_ptr(strong) as *const ()
+    }
+}
+
+/// How keys are hashed and compared
+pub trait KeyMode<K: ?Sized> {
+    fn hash(key: &K, addr: *const ()) -> u64;
+    fn eq(a: &K, a_addr: *const (), b: &K, b_addr: *const ()) -> bool;
+}
+
+/// Keys are the same only if they point to the same object
+pub struct ByPtr;
+
+impl<K: ?Sized> KeyMode<K> for ByPtr {
+    fn hash(_: &K, addr: *const ()) -> u64 {
+        make_hash(&(addr as usize))
+    }
+    fn eq(_: &K, a_addr: *const (), _: &K, b_addr: *const ()) -> bool {
+        a_addr == b_addr
+    }
+}
+
+/// Keys are the same if the objects they point to compare equal
+pub struct ByContent;
+
+impl<K: Hash + Eq + ?Sized> KeyMode<K> for ByContent {
+    fn hash(key: &K, _: *const ()) -> u64 {
+        make_hash(key)
+    }
+    fn eq(a: &K, _: *const (), b: &K, _: *const ()) -> bool {
+        a == b
+    }
+}
+
+/// Stored key: the weak pointer and the hash it had while alive, which
+/// keeps dead keys in the right bucket until they are pruned.
+struct WeakKey<K: ?Sized, M, W> {
+    weak: W,
+    hash: u64,
+    _marker: PhantomData<fn(&K) -> M>,
+}
+
+impl<K: ?Sized, M, W> Hash for WeakKey<K, M, W> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl<K, M, W> PartialEq for WeakKey<K, M, W>
+where
+    K: ?Sized,
+    M: KeyMode<K>,
+    W: WeakRef<K>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        if self.weak.addr() == other.weak.addr() {
+            return true;
+        }
+        // a dead key is only ever equal to itself
+        match (self.weak.upgrade(), other.weak.upgrade()) {
+            (Some(a), Some(b)) => M::eq(
+                W::target(&a),
+                W::strong_addr(&a),
+                W::target(&b),
+                W::strong_addr(&b),
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Lookup key built from a strong reference, hashed like `WeakKey`
+struct Query<'a, K: ?Sized, W: WeakRef<K>> {
+    strong: &'a W::Strong,
+    hash: u64,
+}
+
+impl<K: ?Sized, W: WeakRef<K>> Hash for Query<'_, K, W> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl<K, M, W> Equivalent<WeakKey<K, M, W>> for Query<'_, K, W>
+where
+    K: ?Sized,
+    M: KeyMode<K>,
+    W: WeakRef<K>,
+{
+    fn equivalent(&self, key: &WeakKey<K, M, W>) -> bool {
+        let addr = W::strong_addr(self.strong);
+        if key.weak.addr() == addr {
+            return true;
+        }
+        match key.weak.upgrade() {
+            Some(other) => M::eq(
+                W::target(self.strong),
+                addr,
+                W::target(&other),
+                W::strong_addr(&other),
+            ),
+            None => false,
+        }
+    }
+}
+
+/// Map from weakly held `Rc<K>` (or `Arc<K>`, see `ArcWeakKeyHashMap`)
+/// keys to values. `M` picks identity (`ByPtr`, the default) or content
+/// (`ByContent`) comparison of keys.
+pub struct WeakKeyHashMap<K: ?Sized, V, M = ByPtr, W = rc::Weak<K>> {
+    map: HashMap<WeakKey<K, M, W>, V>,
+    inserts_until_prune: usize,
+}
+
+/// `WeakKeyHashMap` for keys shared through `Arc`
+pub type ArcWeakKeyHashMap<K, V, M = ByPtr> = WeakKeyHashMap<K, V, M, sync::Weak<K>>;
+
+impl<K: ?Sized, V, M, W> Default for WeakKeyHashMap<K, V, M, W> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            inserts_until_prune: MIN_PRUNE_INTERVAL,
+        }
+    }
+}
+
+impl<K: ?Sized, V> WeakKeyHashMap<K, V> {
+    /// Map keyed by `Rc` identity. Use `default()` to pick another mode or
+    /// `Arc` keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K, V, M, W> WeakKeyHashMap<K, V, M, W>
+where
+    K: ?Sized,
+    M: KeyMode<K>,
+    W: WeakRef<K>,
+{
+    fn query<'a>(&self, key: &'a W::Strong) -> Query<'a, K, W> {
+        Query {
+            strong: key,
+            hash: M::hash(W::target(key), W::strong_addr(key)),
+        }
+    }
+
+    /// Insert a value for `key` without taking ownership of it. Returns
+    /// the previous value if the key was already present.
+    pub fn insert(&mut self, key: &W::Strong, value: V) -> Option<V> {
+        // Prune after as many inserts as there were entries left by the
+        // last prune, so the cost is amortized O(1) and dead entries can at
+        // most double the size of the map.
+        match self.inserts_until_prune {
+            0 => {
+                self.prune();
+            }
+            _ => self.inserts_until_prune -= 1,
+        }
+        let new_key = WeakKey {
+            weak: W::downgrade(key),
+            hash: M::hash(W::target(key), W::strong_addr(key)),
+            _marker: PhantomData,
+        };
+        match self.map.entry(new_key) {
+            Entry::Occupied(mut entry) => {
+                // By content, an equal key can be another object that may
+                // die first. Keep the caller's, so the entry lives as long
+                // as the key it was just inserted with.
+                let stored = entry.key_mut();
+                if stored.weak.addr() != W::strong_addr(key) {
+                    stored.weak = W::downgrade(key);
+                }
+                Some(entry.insert(value))
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &W::Strong) -> Option<&V> {
+        self.map.get(&self.query(key))
+    }
+
+    pub fn contains_key(&self, key: &W::Strong) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &W::Strong) -> Option<V> {
+        let query = self.query(key);
+        self.map.remove(&query)
+    }
+
+    /// Drop all entries whose key has died and return how many there were
+    pub fn prune(&mut self) -> usize {
+        let before = self.map.len();
+        self.map.retain(|key, _| !key.weak.is_dead());
+        self.inserts_until_prune = self.map.len().max(MIN_PRUNE_INTERVAL);
+        before - self.map.len()
+    }
+
+    /// return the number of entries whose key is still alive. Dead entries
+    /// that have not been pruned yet are skipped, which makes this O(n).
+    pub fn len(&self) -> usize {
+        self.map
+            .iter()
+            .filter(|(key, _)| !key.weak.is_dead())
+            .count()
+    }
+
+    /// return true if no entry has a live key
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the live entries, handing out a strong reference to
+    /// each key for the duration of the iteration step.
+    pub fn iter(&self) -> impl Iterator<Item = (W::Strong, &V)> {
+        self.map
+            .iter()
+            .filter_map(|(key, value)| key.weak.upgrade().map(|k| (k, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{rc::Rc, string::ToString};
+
+    #[test]
+    fn test_entries_die_with_keys() {
+        let mut map = WeakKeyHashMap::new();
+        let a = Rc::new("a".to_string());
+        let b = Rc::new("b".to_string());
+        map.insert(&a, 1);
+        map.insert(&b, 2);
+        assert_eq!(map.get(&a), Some(&1));
+        assert_eq!(map.len(), 2);
+        drop(a);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.prune(), 1);
+        assert_eq!(map.prune(), 0);
+        assert_eq!(map.get(&b), Some(&2));
+        assert_eq!(map.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_identity_vs_content() {
+        let a = Rc::new(7);
+        let twin = Rc::new(7);
+
+        let mut by_ptr: WeakKeyHashMap<i32, &str> = WeakKeyHashMap::new();
+        by_ptr.insert(&a, "a");
+        assert_eq!(by_ptr.get(&twin), None);
+        assert_eq!(by_ptr.insert(&twin, "twin"), None);
+        assert_eq!(by_ptr.len(), 2);
+
+        let mut by_content: WeakKeyHashMap<i32, &str, ByContent> = WeakKeyHashMap::default();
+        by_content.insert(&a, "a");
+        assert_eq!(by_content.get(&twin), Some(&"a"));
+        assert_eq!(by_content.insert(&twin, "twin"), Some("a"));
+        assert_eq!(by_content.len(), 1);
+        assert_eq!(by_content.remove(&a), Some("twin"));
+        assert!(by_content.is_empty());
+    }
+
+    #[test]
+    fn test_reinsert_by_content_keeps_the_new_key() {
+        let mut map: WeakKeyHashMap<i32, &str, ByContent> = WeakKeyHashMap::default();
+        let first = Rc::new(7);
+        let second = Rc::new(7);
+        map.insert(&first, "first");
+        assert_eq!(map.insert(&second, "second"), Some("first"));
+        drop(first);
+        map.prune();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&second), Some(&"second"));
+        assert_eq!(map.get(&Rc::new(7)), Some(&"second"));
+    }
+
+    #[test]
+    fn test_insert_prunes_lazily() {
+        let mut map = WeakKeyHashMap::new();
+        let keep = Rc::new(0);
+        map.insert(&keep, 0);
+        for i in 1..1000 {
+            // every key dies right after its insert
+            let key = Rc::new(i);
+            map.insert(&key, i);
+        }
+        // dead entries never pile up to more than about twice the live ones
+        assert!(map.map.len() <= 2 * MIN_PRUNE_INTERVAL + 1);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&keep), Some(&0));
+    }
+
+    #[test]
+    fn test_arc_keys() {
+        let mut map: ArcWeakKeyHashMap<str, u32> = WeakKeyHashMap::default();
+        let key: Arc<str> = Arc::from("session");
+        map.insert(&key, 1);
+        assert!(map.contains_key(&key));
+        drop(key);
+        assert_eq!(map.prune(), 1);
+    }
+}
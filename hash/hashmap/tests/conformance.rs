@@ -0,0 +1,839 @@
+//! Behavior tests for `std::collections::HashMap`, ported from std's docs
+//! and test suite, run against `hashmap::HashMap`.
+//!
+//! Each test is written once against the `Map` adapter and runs twice:
+//! under `reference::` with std's map, which keeps the tests honest, and
+//! under `ours::` with this crate's map. Where our map lacks part of std's
+//! API the adapter panics and the test is ignored with a `gap:` reason, so
+//! `cargo test --test conformance -- --ignored` lists what parity is still
+//! missing. Close a gap by filling in the adapter and removing the ignore.
+
+use std::{
+    borrow::Borrow,
+    cell::RefCell,
+    collections::HashMap as StdHashMap,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    panic::{self, AssertUnwindSafe},
+};
+
+use hashmap::HashMap;
+
+/// The part of std's `HashMap` API under test
+trait Map<K: Hash + Eq, V>: Default + Sized {
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+    fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn index<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn clear(&mut self);
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, keep: F);
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_>;
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (&K, &mut V)> + '_>;
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_>;
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_>;
+    /// `size_hint` of a fresh `iter()`
+    fn iter_size_hint(&self) -> (usize, Option<usize>);
+    /// Take `n` entries from `into_iter` and drop the iterator
+    fn into_iter_take(self, n: usize) -> Vec<(K, V)>;
+    fn from_pairs(pairs: Vec<(K, V)>) -> Self;
+    fn extend(&mut self, pairs: Vec<(K, V)>);
+    fn with_capacity(capacity: usize) -> Self;
+    fn capacity(&self) -> usize;
+    fn clone_map(&self) -> Self
+    where
+        K: Clone,
+        V: Clone;
+    fn equals(&self, other: &Self) -> bool
+    where
+        V: PartialEq;
+    fn debug(&self) -> String
+    where
+        K: Debug,
+        V: Debug;
+    fn entry_or_insert(&mut self, key: K, value: V) -> &mut V;
+    fn entry_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, make: F) -> &mut V;
+    fn entry_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default;
+    fn entry_and_modify<F: FnOnce(&mut V)>(&mut self, key: K, f: F, default: V) -> &mut V;
+    /// `OccupiedEntry::remove_entry` if `key` is present
+    fn entry_remove(&mut self, key: K) -> Option<(K, V)>;
+}
+
+/// Picks the map type for any key and value types
+trait Family {
+    type Map<K: Hash + Eq, V>: Map<K, V>;
+}
+
+struct Std;
+struct Ours;
+
+impl Family for Std {
+    type Map<K: Hash + Eq, V> = StdHashMap<K, V>;
+}
+
+impl Family for Ours {
+    type Map<K: Hash + Eq, V> = HashMap<K, V>;
+}
+
+impl<K: Hash + Eq, V> Map<K, V> for StdHashMap<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+    fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+    fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key)
+    }
+    fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key)
+    }
+    fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.contains_key(key)
+    }
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key)
+    }
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key)
+    }
+    fn index<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        &self[key]
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn clear(&mut self) {
+        self.clear()
+    }
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, keep: F) {
+        self.retain(keep)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        Box::new(self.iter())
+    }
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (&K, &mut V)> + '_> {
+        Box::new(self.iter_mut())
+    }
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.keys())
+    }
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        Box::new(self.values())
+    }
+    fn iter_size_hint(&self) -> (usize, Option<usize>) {
+        self.iter().size_hint()
+    }
+    fn into_iter_take(self, n: usize) -> Vec<(K, V)> {
+        self.into_iter().take(n).collect()
+    }
+    fn from_pairs(pairs: Vec<(K, V)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn extend(&mut self, pairs: Vec<(K, V)>) {
+        Extend::extend(self, pairs)
+    }
+    fn with_capacity(capacity: usize) -> Self {
+        StdHashMap::with_capacity(capacity)
+    }
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+    fn clone_map(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.clone()
+    }
+    fn equals(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        self == other
+    }
+    fn debug(&self) -> String
+    where
+        K: Debug,
+        V: Debug,
+    {
+        format!("{self:?}")
+    }
+    fn entry_or_insert(&mut self, key: K, value: V) -> &mut V {
+        self.entry(key).or_insert(value)
+    }
+    fn entry_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, make: F) -> &mut V {
+        self.entry(key).or_insert_with(make)
+    }
+    fn entry_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.entry(key).or_default()
+    }
+    fn entry_and_modify<F: FnOnce(&mut V)>(&mut self, key: K, f: F, default: V) -> &mut V {
+        self.entry(key).and_modify(f).or_insert(default)
+    }
+    fn entry_remove(&mut self, key: K) -> Option<(K, V)> {
+        match self.entry(key) {
+            std::collections::hash_map::Entry::Occupied(e) => Some(e.remove_entry()),
+            std::collections::hash_map::Entry::Vacant(_) => None,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Map<K, V> for HashMap<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+    fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+    fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(key)
+    }
+    fn get_key_value<Q>(&self, _key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        unimplemented!("gap: no get_key_value")
+    }
+    fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.contains_key(key)
+    }
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key)
+    }
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key)
+    }
+    fn index<Q>(&self, key: &Q) -> &V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        &self[key]
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+    fn clear(&mut self) {
+        unimplemented!("gap: no clear")
+    }
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, keep: F) {
+        self.retain(keep)
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        Box::new(self.iter())
+    }
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (&K, &mut V)> + '_> {
+        unimplemented!("gap: no iter_mut")
+    }
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        unimplemented!("gap: no keys")
+    }
+    fn values(&self) -> Box<dyn Iterator<Item = &V> + '_> {
+        unimplemented!("gap: no values")
+    }
+    fn iter_size_hint(&self) -> (usize, Option<usize>) {
+        self.iter().size_hint()
+    }
+    fn into_iter_take(self, n: usize) -> Vec<(K, V)> {
+        self.into_iter().take(n).collect()
+    }
+    fn from_pairs(pairs: Vec<(K, V)>) -> Self {
+        pairs.into_iter().collect()
+    }
+    fn extend(&mut self, _pairs: Vec<(K, V)>) {
+        unimplemented!("gap: no Extend impl")
+    }
+    fn with_capacity(_capacity: usize) -> Self {
+        unimplemented!("gap: no with_capacity")
+    }
+    fn capacity(&self) -> usize {
+        unimplemented!("gap: no capacity")
+    }
+    fn clone_map(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        unimplemented!("gap: no Clone impl")
+    }
+    fn equals(&self, _other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        unimplemented!("gap: no PartialEq impl")
+    }
+    fn debug(&self) -> String
+    where
+        K: Debug,
+        V: Debug,
+    {
+        format!("{self:?}")
+    }
+    fn entry_or_insert(&mut self, key: K, value: V) -> &mut V {
+        self.entry(key).or_insert(value)
+    }
+    fn entry_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, make: F) -> &mut V {
+        self.entry(key).or_insert_with(make)
+    }
+    fn entry_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.entry(key).or_default()
+    }
+    fn entry_and_modify<F: FnOnce(&mut V)>(&mut self, _key: K, _f: F, _default: V) -> &mut V {
+        unimplemented!("gap: no Entry::and_modify")
+    }
+    fn entry_remove(&mut self, _key: K) -> Option<(K, V)> {
+        unimplemented!("gap: no OccupiedEntry::remove_entry")
+    }
+}
+
+thread_local! {
+    // drop count per Tracked id, per test thread
+    static DROPS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+fn reset_drops(n: usize) {
+    DROPS.with(|d| *d.borrow_mut() = vec![0; n]);
+}
+
+fn drop_count(id: usize) -> usize {
+    DROPS.with(|d| d.borrow()[id])
+}
+
+/// Counts its drops in `DROPS`
+#[derive(Hash, PartialEq, Eq, Debug)]
+struct Tracked(usize);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        DROPS.with(|d| d.borrow_mut()[self.0] += 1);
+    }
+}
+
+/// Equal when the ids are, whatever the labels say
+#[derive(Debug, Clone)]
+struct Labeled(i32, &'static str);
+
+impl PartialEq for Labeled {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Labeled {}
+
+impl Hash for Labeled {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+fn panics<R>(f: impl FnOnce() -> R) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+}
+
+fn empty_map<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    assert!(map.is_empty());
+    assert_eq!(map.len(), 0);
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.get_mut(&1), None);
+    assert_eq!(map.remove(&1), None);
+    assert_eq!(map.remove_entry(&1), None);
+    assert!(!map.contains_key(&1));
+    assert_eq!(map.iter().count(), 0);
+    assert_eq!(map.into_iter_take(usize::MAX), []);
+}
+
+fn insert_and_get<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    assert_eq!(map.insert(1, 2), None);
+    assert_eq!(map.insert(2, 4), None);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&1), Some(&2));
+    assert_eq!(map.get(&2), Some(&4));
+    assert_eq!(map.get(&3), None);
+}
+
+fn insert_overwrite<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    assert_eq!(map.insert(1, 2), None);
+    assert_eq!(map.insert(1, 3), Some(2));
+    assert_eq!(map.get(&1), Some(&3));
+    assert_eq!(map.len(), 1);
+}
+
+fn insert_keeps_old_key<F: Family>() {
+    let mut map = F::Map::<Labeled, i32>::default();
+    map.insert(Labeled(1, "first"), 1);
+    map.insert(Labeled(1, "second"), 2);
+    let (key, value) = map.iter().next().unwrap();
+    assert_eq!((key.1, *value), ("first", 2));
+}
+
+fn insert_conflicts<F: Family>() {
+    // keys that share buckets in small tables
+    let mut map = F::Map::<i32, i32>::default();
+    for key in [1, 5, 9] {
+        assert_eq!(map.insert(key, key * 2), None);
+    }
+    for key in [1, 5, 9] {
+        assert_eq!(map.get(&key), Some(&(key * 2)));
+    }
+    assert_eq!(map.remove(&5), Some(10));
+    assert_eq!(map.get(&1), Some(&2));
+    assert_eq!(map.get(&9), Some(&18));
+    assert_eq!(map.get(&5), None);
+}
+
+fn lots_of_insertions<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    // several rounds of growing and emptying the same map
+    for _ in 0..5 {
+        assert!(map.is_empty());
+        for i in 1..=1000 {
+            assert_eq!(map.insert(i, i), None);
+            for j in (1..=i).step_by(97) {
+                assert_eq!(map.get(&j), Some(&j));
+            }
+            assert_eq!(map.get(&(i + 1)), None);
+        }
+        for i in 1..=1000 {
+            assert_eq!(map.remove(&i), Some(i));
+            assert_eq!(map.get(&i), None);
+        }
+    }
+}
+
+fn get_mut<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    map.insert(1, 12);
+    map.insert(2, 8);
+    *map.get_mut(&1).unwrap() = 5;
+    assert_eq!(map.get(&1), Some(&5));
+    assert_eq!(map.get_mut(&3), None);
+}
+
+fn borrow_lookups<F: Family>() {
+    let mut map = F::Map::<String, i32>::default();
+    map.insert("foo".to_string(), 1);
+    assert_eq!(map.get("foo"), Some(&1));
+    assert!(map.contains_key("foo"));
+    *map.get_mut("foo").unwrap() += 1;
+    assert_eq!(map.index("foo"), &2);
+    assert_eq!(map.remove("foo"), Some(2));
+
+    let mut bytes = F::Map::<Vec<u8>, i32>::default();
+    bytes.insert(b"key".to_vec(), 7);
+    assert_eq!(bytes.get(&b"key"[..]), Some(&7));
+    assert_eq!(bytes.remove_entry(&b"key"[..]), Some((b"key".to_vec(), 7)));
+}
+
+fn get_key_value<F: Family>() {
+    let mut map = F::Map::<Labeled, i32>::default();
+    map.insert(Labeled(1, "stored"), 1);
+    let (key, _) = map.get_key_value(&Labeled(1, "query")).unwrap();
+    assert_eq!(key.1, "stored");
+}
+
+fn remove<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    map.insert(1, 2);
+    assert_eq!(map.remove(&1), Some(2));
+    assert_eq!(map.remove(&1), None);
+    assert!(map.is_empty());
+}
+
+fn remove_entry_returns_stored_key<F: Family>() {
+    let mut map = F::Map::<Labeled, i32>::default();
+    map.insert(Labeled(1, "stored"), 1);
+    let (key, value) = map.remove_entry(&Labeled(1, "query")).unwrap();
+    assert_eq!((key.1, value), ("stored", 1));
+    assert!(map.remove_entry(&Labeled(1, "query")).is_none());
+}
+
+fn is_empty<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    map.insert(1, 2);
+    assert!(!map.is_empty());
+    assert!(map.remove(&1).is_some());
+    assert!(map.is_empty());
+}
+
+fn index<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    map.insert(1, 2);
+    map.insert(2, 1);
+    assert_eq!(map.index(&2), &1);
+    assert!(panics(|| *map.index(&4)));
+}
+
+fn iterate<F: Family>() {
+    let mut map = F::Map::<u32, u32>::default();
+    for i in 0..32 {
+        map.insert(i, i * 2);
+    }
+    let mut observed = 0u32;
+    for (k, v) in map.iter() {
+        assert_eq!(*v, *k * 2);
+        observed |= 1 << *k;
+    }
+    assert_eq!(observed, u32::MAX);
+    assert_eq!(map.iter().count(), 32);
+}
+
+fn iter_size_hint<F: Family>() {
+    let map = F::Map::<i32, i32>::from_pairs((0..10).map(|i| (i, i)).collect());
+    assert_eq!(map.iter_size_hint(), (10, Some(10)));
+}
+
+fn iter_mut<F: Family>() {
+    let mut map = F::Map::<i32, i32>::from_pairs((0..10).map(|i| (i, i)).collect());
+    for (_, v) in map.iter_mut() {
+        *v *= 10;
+    }
+    assert_eq!(map.get(&7), Some(&70));
+}
+
+fn keys_and_values<F: Family>() {
+    let map = F::Map::<i32, char>::from_pairs(vec![(1, 'a'), (2, 'b'), (3, 'c')]);
+    let mut keys: Vec<_> = map.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, [1, 2, 3]);
+    let mut values: Vec<_> = map.values().copied().collect();
+    values.sort();
+    assert_eq!(values, ['a', 'b', 'c']);
+}
+
+fn from_iter<F: Family>() {
+    let pairs = vec![(1, 1), (2, 2), (3, 3), (2, 20), (4, 4)];
+    let map = F::Map::<i32, i32>::from_pairs(pairs);
+    assert_eq!(map.len(), 4);
+    // later values win
+    assert_eq!(map.get(&2), Some(&20));
+    assert_eq!(map.get(&4), Some(&4));
+}
+
+fn extend<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    map.insert(1, 1);
+    map.extend(vec![(1, 10), (2, 2)]);
+    assert_eq!(map.get(&1), Some(&10));
+    assert_eq!(map.len(), 2);
+}
+
+fn retain<F: Family>() {
+    let mut map = F::Map::<i32, i32>::from_pairs((0..100).map(|i| (i, i * 10)).collect());
+    map.retain(|&k, _| k % 2 == 0);
+    assert_eq!(map.len(), 50);
+    assert_eq!(map.get(&2), Some(&20));
+    assert_eq!(map.get(&3), None);
+}
+
+fn clear<F: Family>() {
+    let mut map = F::Map::<i32, i32>::from_pairs((0..10).map(|i| (i, i)).collect());
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.insert(1, 1), None);
+}
+
+fn capacity<F: Family>() {
+    let map = F::Map::<i32, i32>::with_capacity(100);
+    assert!(map.capacity() >= 100);
+    assert!(map.is_empty());
+}
+
+fn clone<F: Family>() {
+    let map = F::Map::<i32, String>::from_pairs(vec![(1, "one".to_string())]);
+    let copy = map.clone_map();
+    assert_eq!(copy.get(&1).map(String::as_str), Some("one"));
+}
+
+fn eq<F: Family>() {
+    let a = F::Map::<i32, i32>::from_pairs(vec![(1, 2), (2, 3), (3, 4)]);
+    let mut b = F::Map::<i32, i32>::from_pairs(vec![(3, 4), (1, 2)]);
+    assert!(!a.equals(&b));
+    b.insert(2, 3);
+    assert!(a.equals(&b));
+}
+
+fn debug<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    assert_eq!(map.debug(), "{}");
+    map.insert(1, 2);
+    assert_eq!(map.debug(), "{1: 2}");
+}
+
+fn zero_sized_keys<F: Family>() {
+    let mut map = F::Map::<(), ()>::default();
+    assert_eq!(map.insert((), ()), None);
+    assert_eq!(map.insert((), ()), Some(()));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.remove(&()), Some(()));
+}
+
+fn entry_or_insert<F: Family>() {
+    let mut map = F::Map::<&str, i32>::default();
+    *map.entry_or_insert("poneyland", 3) += 10;
+    assert_eq!(map.get("poneyland"), Some(&13));
+    *map.entry_or_insert("poneyland", 10) *= 2;
+    assert_eq!(map.get("poneyland"), Some(&26));
+}
+
+fn entry_or_insert_with<F: Family>() {
+    let mut map = F::Map::<&str, String>::default();
+    map.entry_or_insert_with("poneyland", || "hoho".to_string());
+    let mut called = false;
+    map.entry_or_insert_with("poneyland", || {
+        called = true;
+        String::new()
+    });
+    assert!(!called);
+    assert_eq!(map.get("poneyland").map(String::as_str), Some("hoho"));
+}
+
+fn entry_or_default<F: Family>() {
+    let mut map = F::Map::<&str, Option<u32>>::default();
+    map.entry_or_default("poneyland");
+    assert_eq!(map.get("poneyland"), Some(&None));
+    let mut counts = F::Map::<char, usize>::default();
+    for c in "hello".chars() {
+        *counts.entry_or_default(c) += 1;
+    }
+    assert_eq!(counts.get(&'l'), Some(&2));
+    assert_eq!(counts.len(), 4);
+}
+
+fn entry_and_modify<F: Family>() {
+    let mut map = F::Map::<&str, i32>::default();
+    map.entry_and_modify("poneyland", |e| *e += 1, 42);
+    assert_eq!(map.get("poneyland"), Some(&42));
+    map.entry_and_modify("poneyland", |e| *e += 1, 42);
+    assert_eq!(map.get("poneyland"), Some(&43));
+}
+
+fn entry_remove<F: Family>() {
+    let mut map = F::Map::<i32, i32>::from_pairs((0..50).map(|i| (i, i)).collect());
+    for i in 0..50 {
+        assert_eq!(map.entry_remove(i), Some((i, i)));
+        // the rest of the table is still intact
+        assert!((i + 1..50).all(|j| map.get(&j) == Some(&j)));
+    }
+    assert_eq!(map.entry_remove(0), None);
+}
+
+fn entry_on_empty_map<F: Family>() {
+    let mut map = F::Map::<i32, i32>::default();
+    assert_eq!(*map.entry_or_insert(1, 5), 5);
+    assert_eq!(map.len(), 1);
+}
+
+fn drops<F: Family>() {
+    reset_drops(200);
+    let mut map = F::Map::<Tracked, Tracked>::default();
+    for i in 0..100 {
+        map.insert(Tracked(i), Tracked(i + 100));
+    }
+    assert!((0..200).all(|i| drop_count(i) == 0));
+
+    for i in 0..50 {
+        let value = map.remove(&Tracked(i));
+        // the lookup key is dropped right away, the stored key with the entry
+        assert_eq!(drop_count(i), 2);
+        assert_eq!(drop_count(i + 100), 0);
+        drop(value);
+        assert_eq!(drop_count(i + 100), 1);
+    }
+    drop(map);
+    assert!((50..100).all(|i| drop_count(i) == 1 && drop_count(i + 100) == 1));
+}
+
+fn overwrite_drops<F: Family>() {
+    reset_drops(3);
+    let mut map = F::Map::<Tracked, Tracked>::default();
+    map.insert(Tracked(0), Tracked(1));
+    let old = map.insert(Tracked(0), Tracked(2));
+    // the new key is dropped and the stored key is kept
+    assert_eq!(drop_count(0), 1);
+    assert_eq!(drop_count(1), 0);
+    drop(old);
+    assert_eq!(drop_count(1), 1);
+    drop(map);
+    assert_eq!((drop_count(0), drop_count(1), drop_count(2)), (2, 1, 1));
+}
+
+fn into_iter_drops<F: Family>() {
+    reset_drops(200);
+    let mut map = F::Map::<Tracked, Tracked>::default();
+    for i in 0..100 {
+        map.insert(Tracked(i), Tracked(i + 100));
+    }
+    let taken = map.into_iter_take(30);
+    // the 70 entries left in the iterator went with it
+    assert_eq!((0..200).filter(|&i| drop_count(i) == 1).count(), 140);
+    drop(taken);
+    assert!((0..200).all(|i| drop_count(i) == 1));
+}
+
+/// Run every test against both maps; `#[ignore]` only applies to ours
+macro_rules! conformance {
+    ($($(#[$gap:meta])? $name:ident,)*) => {
+        mod reference {
+            $(
+                #[test]
+                fn $name() {
+                    super::$name::<super::Std>()
+                }
+            )*
+        }
+
+        mod ours {
+            $(
+                #[test]
+                $(#[$gap])?
+                fn $name() {
+                    super::$name::<super::Ours>()
+                }
+            )*
+        }
+    };
+}
+
+conformance! {
+    empty_map,
+    insert_and_get,
+    insert_overwrite,
+    insert_keeps_old_key,
+    insert_conflicts,
+    lots_of_insertions,
+    get_mut,
+    borrow_lookups,
+    #[ignore = "gap: no get_key_value"]
+    get_key_value,
+    remove,
+    remove_entry_returns_stored_key,
+    is_empty,
+    index,
+    iterate,
+    #[ignore = "gap: iterators don't implement size_hint"]
+    iter_size_hint,
+    #[ignore = "gap: no iter_mut"]
+    iter_mut,
+    #[ignore = "gap: no keys or values"]
+    keys_and_values,
+    from_iter,
+    #[ignore = "gap: no Extend impl"]
+    extend,
+    retain,
+    #[ignore = "gap: no clear"]
+    clear,
+    #[ignore = "gap: no with_capacity or capacity"]
+    capacity,
+    #[ignore = "gap: no Clone impl"]
+    clone,
+    #[ignore = "gap: no PartialEq impl"]
+    eq,
+    #[ignore = "gap: Debug prints the bucket layout"]
+    debug,
+    zero_sized_keys,
+    entry_or_insert,
+    entry_or_insert_with,
+    entry_or_default,
+    #[ignore = "gap: no Entry::and_modify"]
+    entry_and_modify,
+    #[ignore = "gap: no OccupiedEntry::remove_entry"]
+    entry_remove,
+    entry_on_empty_map,
+    drops,
+    overwrite_drops,
+    into_iter_drops,
+}
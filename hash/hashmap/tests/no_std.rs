@@ -0,0 +1,64 @@
+//! Uses the crate from a `#![no_std]` crate, the way a firmware build would.
+//!
+//! `cargo test-no-std` runs this together with the unit tests against the
+//! crate built without the `std` feature, on the host.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::hash::BuildHasher;
+
+use hashmap::{
+    hashers::{BuildFnvHasher, SipHasher13},
+    int::IntMap,
+    interner::Interner,
+    HashMap,
+};
+
+/// SipHash with secret keys, as firmware would seed from a hardware RNG
+struct KeyedSip(u64, u64);
+
+impl BuildHasher for KeyedSip {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.0, self.1)
+    }
+}
+
+fn exercise<S: BuildHasher>(mut map: HashMap<String, u32, S>) {
+    for i in 0..100u32 {
+        map.insert(alloc::format!("sensor{i}"), i);
+    }
+    *map.entry(String::from("sensor7")).or_insert(0) += 100;
+    assert_eq!(map.get("sensor7"), Some(&107));
+    assert_eq!(map.remove("sensor8"), Some(8));
+    map.retain(|_, v| *v % 2 == 0);
+    assert_eq!(map.len(), 49);
+    assert_eq!(map.iter().map(|(_, v)| v).sum::<u32>(), 2442);
+}
+
+#[test]
+fn test_default_hasher() {
+    exercise(HashMap::new());
+}
+
+#[test]
+fn test_supplied_hashers() {
+    exercise(HashMap::with_hasher(BuildFnvHasher::default()));
+    exercise(HashMap::with_hasher(KeyedSip(0x0123_4567, 0x89ab_cdef)));
+}
+
+#[test]
+fn test_collections_without_std() {
+    let mut interner = Interner::new();
+    let a = interner.intern("alpha");
+    assert_eq!(interner.intern("alpha"), a);
+    assert_eq!(interner.resolve(a), "alpha");
+
+    let map: IntMap<u8> = (0..10).map(|i| (i * 300, i as u8)).collect();
+    let keys: Vec<u32> = map.iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, (0..10).map(|i| i * 300).collect::<Vec<_>>());
+}
@@ -0,0 +1,311 @@
+//! Panics from user code in the middle of `HashMap` operations.
+//!
+//! `Key`s and `Val`s are instrumented: every instance has a unique id and
+//! counts its drops, and a key's `Hash` or `PartialEq` impl can be armed to
+//! panic on its Nth call. After each panic the map must still agree with
+//! itself, and once everything is dropped every instance must have been
+//! dropped exactly once.
+#![cfg(feature = "std")]
+
+use std::{
+    hash::{Hash, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use hashmap::HashMap;
+
+const DISARMED: usize = usize::MAX;
+
+/// Shared state of the instances made by one test
+struct Ctx {
+    hash_fuse: AtomicUsize,
+    eq_fuse: AtomicUsize,
+    next_id: AtomicUsize,
+    drops: Vec<AtomicUsize>,
+}
+
+impl Ctx {
+    fn new() -> &'static Ctx {
+        Box::leak(Box::new(Ctx {
+            hash_fuse: AtomicUsize::new(DISARMED),
+            eq_fuse: AtomicUsize::new(DISARMED),
+            next_id: AtomicUsize::new(0),
+            drops: (0..10_000).map(|_| AtomicUsize::new(0)).collect(),
+        }))
+    }
+
+    fn id(&self) -> usize {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn key(&'static self, k: u64) -> Key {
+        Key {
+            k,
+            id: self.id(),
+            ctx: self,
+        }
+    }
+
+    fn val(&'static self, v: u64) -> Val {
+        Val {
+            v,
+            id: self.id(),
+            ctx: self,
+            bomb: AtomicBool::new(false),
+        }
+    }
+
+    fn entry(&'static self, k: u64) -> (Key, Val) {
+        (self.key(k), self.val(k))
+    }
+
+    /// Panic on the `n`th hash from now on
+    fn arm_hash(&self, n: usize) {
+        self.hash_fuse.store(n, Ordering::SeqCst);
+    }
+
+    /// Panic on the `n`th comparison from now on
+    fn arm_eq(&self, n: usize) {
+        self.eq_fuse.store(n, Ordering::SeqCst);
+    }
+
+    fn disarm(&self) {
+        self.arm_hash(DISARMED);
+        self.arm_eq(DISARMED);
+    }
+
+    fn drops(&self, id: usize) -> usize {
+        self.drops[id].load(Ordering::SeqCst)
+    }
+
+    /// Every instance made so far was dropped exactly once
+    fn all_dropped_once(&self) {
+        for id in 0..self.next_id.load(Ordering::SeqCst) {
+            assert_eq!(self.drops(id), 1, "instance {id}");
+        }
+    }
+}
+
+fn burn(fuse: &AtomicUsize, what: &str) {
+    let lit = fuse.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+        DISARMED | 0 => None,
+        n => Some(n - 1),
+    });
+    if lit == Ok(1) {
+        panic!("{what} bomb");
+    }
+}
+
+struct Key {
+    k: u64,
+    id: usize,
+    ctx: &'static Ctx,
+}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        burn(&self.ctx.hash_fuse, "hash");
+        self.k.hash(state)
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        burn(&self.ctx.eq_fuse, "eq");
+        self.k == other.k
+    }
+}
+
+impl Eq for Key {}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.ctx.drops[self.id].fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct Val {
+    v: u64,
+    id: usize,
+    ctx: &'static Ctx,
+    // panic when dropped
+    bomb: AtomicBool,
+}
+
+impl Drop for Val {
+    fn drop(&mut self) {
+        self.ctx.drops[self.id].fetch_add(1, Ordering::SeqCst);
+        if self.bomb.load(Ordering::SeqCst) {
+            panic!("drop bomb");
+        }
+    }
+}
+
+fn panics<R>(f: impl FnOnce() -> R) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+}
+
+/// The map agrees with itself and holds live instances only
+fn check(ctx: &Ctx, map: &HashMap<Key, Val>) {
+    ctx.disarm();
+    assert_eq!(map.iter().count(), map.len());
+    for (key, val) in map {
+        assert_eq!(key.k, val.v);
+        assert_eq!((ctx.drops(key.id), ctx.drops(val.id)), (0, 0));
+        assert!(map.contains_key(key));
+    }
+}
+
+fn filled(ctx: &'static Ctx, n: u64) -> HashMap<Key, Val> {
+    (0..n).map(|k| ctx.entry(k)).collect()
+}
+
+#[test]
+fn test_hash_panic_during_resize() {
+    let ctx = Ctx::new();
+    // 4 entries in 4 buckets: the next insert grows the table
+    let mut map = filled(ctx, 4);
+    for n in 1..=4 {
+        let (key, val) = ctx.entry(100);
+        ctx.arm_hash(n);
+        assert!(panics(|| map.insert(key, val)));
+        check(ctx, &map);
+        assert_eq!(map.len(), 4);
+    }
+    let (key, val) = ctx.entry(100);
+    ctx.arm_hash(2);
+    assert!(panics(|| map.try_insert_reserve(key, val)));
+    check(ctx, &map);
+    assert_eq!(map.len(), 4);
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_eq_panic_during_insert() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 50);
+    for k in [7, 200] {
+        let (key, val) = ctx.entry(k);
+        ctx.arm_eq(1);
+        // a miss may not compare at all if the bucket holds no other key
+        let panicked = panics(|| map.insert(key, val));
+        check(ctx, &map);
+        assert_eq!(map.len(), 50 + (!panicked && k == 200) as usize);
+    }
+    let (key, val) = ctx.entry(7);
+    ctx.arm_eq(1);
+    assert!(panics(|| map.try_insert(key, val).is_ok()));
+    check(ctx, &map);
+    let key = ctx.key(7);
+    ctx.arm_eq(1);
+    assert!(panics(|| map.remove(&key)));
+    check(ctx, &map);
+    drop((key, map));
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_panic_in_retain() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 100);
+    let mut calls = 0;
+    assert!(panics(|| map.retain(|key, _| {
+        calls += 1;
+        assert!(calls < 60, "keep bomb");
+        key.k % 2 == 0
+    })));
+    check(ctx, &map);
+    assert!(map.len() < 100 && map.len() > 50);
+    map.retain(|key, _| key.k % 2 == 0);
+    assert_eq!(map.len(), 50);
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_drop_panic_in_retain() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 100);
+    for (key, val) in &map {
+        if key.k % 10 == 3 {
+            val.bomb.store(true, Ordering::SeqCst);
+        }
+    }
+    assert!(panics(|| map.retain(|key, _| key.k % 2 == 0)));
+    check(ctx, &map);
+    // the bombs are all odd, so the rest of retain can still go through
+    while panics(|| map.retain(|key, _| key.k % 2 == 0)) {}
+    check(ctx, &map);
+    assert_eq!(map.len(), 50);
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_panic_in_par_retain() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 1000);
+    assert!(panics(|| map.par_retain(4, |key, _| {
+        assert!(key.k != 321, "keep bomb");
+        key.k % 3 == 0
+    })));
+    check(ctx, &map);
+    map.par_retain(4, |key, _| key.k % 3 == 0);
+    check(ctx, &map);
+    assert_eq!(map.len(), 334);
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_hash_panic_in_par_extend() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 100);
+    for n in [50, 150] {
+        // the old keys are hashed first, then the new ones
+        let more: Vec<_> = (100..200).map(|k| ctx.entry(k)).collect();
+        ctx.arm_hash(n);
+        assert!(panics(|| map.par_extend(2, more)));
+        check(ctx, &map);
+        assert_eq!(map.len(), 100);
+    }
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_eq_panic_in_par_extend() {
+    let ctx = Ctx::new();
+    let mut map = filled(ctx, 100);
+    let before: Vec<_> = map.iter().map(|(k, v)| (k.id, v.id)).collect();
+    for n in [1, 25, 45] {
+        // half of the new keys are already in the map, each costing a
+        // comparison
+        let more: Vec<_> = (50..150).map(|k| ctx.entry(k)).collect();
+        ctx.arm_eq(n);
+        assert!(panics(|| map.par_extend(2, more)));
+        check(ctx, &map);
+        assert_eq!(map.len(), 100);
+        // the very same entries, in the same buckets and order
+        let after: Vec<_> = map.iter().map(|(k, v)| (k.id, v.id)).collect();
+        assert_eq!(after, before);
+    }
+    drop(map);
+    ctx.all_dropped_once();
+}
+
+#[test]
+fn test_into_iter_drops_the_rest() {
+    let ctx = Ctx::new();
+    let map = filled(ctx, 100);
+    let mut iter = map.into_iter();
+    let some: Vec<_> = iter.by_ref().take(40).collect();
+    drop(iter);
+    for (key, val) in &some {
+        assert_eq!((ctx.drops(key.id), ctx.drops(val.id)), (0, 0));
+    }
+    drop(some);
+    ctx.all_dropped_once();
+}
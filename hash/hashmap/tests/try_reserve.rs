@@ -0,0 +1,113 @@
+//! The fallible insert paths under an allocator that refuses to allocate
+//! on demand. Each test thread has its own switch, so tests running in
+//! parallel don't fail each other's allocations.
+#![cfg(feature = "std")]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use hashmap::{Entry, HashMap};
+
+struct FailingAlloc;
+
+thread_local! {
+    static FAIL: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if FAIL.with(Cell::get) {
+            return std::ptr::null_mut();
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if FAIL.with(Cell::get) {
+            return std::ptr::null_mut();
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: FailingAlloc = FailingAlloc;
+
+/// Run `f` with every allocation on this thread failing
+fn without_memory<R>(f: impl FnOnce() -> R) -> R {
+    FAIL.with(|fail| fail.set(true));
+    let result = f();
+    FAIL.with(|fail| fail.set(false));
+    result
+}
+
+fn snapshot(map: &HashMap<u64, u64>) -> Vec<(u64, u64)> {
+    map.iter().map(|(&k, &v)| (k, v)).collect()
+}
+
+#[test]
+fn test_failed_resize_leaves_map_intact() {
+    let mut map = HashMap::new();
+    // 4 items in 4 buckets: the next insert grows the table
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    let before = snapshot(&map);
+    assert!(without_memory(|| map.try_insert_reserve(100, 100)).is_err());
+    assert_eq!(snapshot(&map), before);
+    assert!(without_memory(|| map.try_entry(100)).is_err());
+    assert_eq!(snapshot(&map), before);
+
+    // once memory is back the same insert goes through
+    assert_eq!(map.try_insert_reserve(100, 100), Ok(None));
+    assert_eq!(map.len(), 5);
+    assert_eq!(map.get(&100), Some(&100));
+}
+
+#[test]
+fn test_overwrite_needs_no_memory() {
+    // 3 items in 4 buckets: below the load that triggers growth
+    let mut map: HashMap<_, _> = (0..3).map(|i| (i, i)).collect();
+    // a lookup hit then neither grows the table nor the bucket
+    assert_eq!(without_memory(|| map.try_insert_reserve(1, 2)), Ok(Some(1)));
+    assert_eq!(map.get(&1), Some(&2));
+}
+
+#[test]
+fn test_vacant_entry_is_reserved() {
+    let mut map = HashMap::new();
+    map.insert(1, 1);
+    match map.try_entry(2).unwrap() {
+        // try_entry made room, so inserting can't fail
+        Entry::Vacant(entry) => assert_eq!(without_memory(|| *entry.insert(2)), 2),
+        Entry::Occupied(_) => unreachable!(),
+    }
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn test_shed_load_under_pressure() {
+    let mut map = HashMap::new();
+    let mut rejected = 0;
+    for i in 0..1000u64 {
+        // memory runs out every so often
+        let result = if i % 7 == 0 {
+            without_memory(|| map.try_insert_reserve(i, i))
+        } else {
+            map.try_insert_reserve(i, i)
+        };
+        match result {
+            Ok(_) => {}
+            Err(_) => rejected += 1,
+        }
+    }
+    assert!(rejected > 0);
+    assert_eq!(map.len() + rejected, 1000);
+    assert!(map.iter().all(|(k, v)| k == v));
+}
@@ -0,0 +1,390 @@
+//! The keyspace and the commands that operate on it.
+//!
+//! Expiry is left to `TtlMap`: an expired key is removed when a command
+//! touches it, skipped by the commands that walk the keyspace, and purged
+//! in bulk by `DBSIZE`.
+
+use std::time::Duration;
+
+use hashmap::ttl::{Clock, SystemClock, TtlMap};
+
+use crate::resp::Reply;
+
+const COMMANDS: &[&str] = &[
+    "PING", "GET", "SET", "DEL", "EXISTS", "INCR", "KEYS", "SCAN", "EXPIRE", "DBSIZE",
+];
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+fn error(msg: &str) -> Reply {
+    Reply::Error(format!("ERR {msg}"))
+}
+
+fn not_an_integer() -> Reply {
+    error("value is not an integer or out of range")
+}
+
+fn parse_int(bytes: &[u8]) -> Option<i64> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Match `text` against a Redis glob pattern: `*`, `?`, `[abc]`, `[a-z]`,
+/// `[^abc]` and `\` to escape the next character.
+///
+/// Only the last `*` is ever backtracked to, so matching takes
+/// O(pattern * text) time whatever the pattern.
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // pattern position after the last `*` and the text position it has
+    // been stretched to
+    let mut star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if p < pattern.len() {
+            let (matched, len) = match_token(&pattern[p..], text[t]);
+            if matched {
+                p += len;
+                t += 1;
+                continue;
+            }
+        }
+        // let the last `*` swallow one more character and retry
+        let Some((after_star, stretched)) = star else {
+            return false;
+        };
+        p = after_star;
+        t = stretched + 1;
+        star = Some((after_star, t));
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Match `c` against the token at the start of `pattern`, which is not
+/// empty and doesn't start with `*`. Returns whether it matched and how
+/// long the token is.
+fn match_token(pattern: &[u8], c: u8) -> (bool, usize) {
+    match pattern {
+        [b'?', ..] => (true, 1),
+        [b'[', rest @ ..] => {
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    // unterminated class: treat it as ending here
+                    [] => break,
+                    [b']', tail @ ..] => {
+                        class = tail;
+                        break;
+                    }
+                    [b'\\', x, tail @ ..] => {
+                        matched |= *x == c;
+                        class = tail;
+                    }
+                    [lo, b'-', hi, tail @ ..] if *hi != b']' => {
+                        let (lo, hi) = (*lo.min(hi), *lo.max(hi));
+                        matched |= (lo..=hi).contains(&c);
+                        class = tail;
+                    }
+                    [x, tail @ ..] => {
+                        matched |= *x == c;
+                        class = tail;
+                    }
+                }
+            }
+            (matched != negate, pattern.len() - class.len())
+        }
+        [b'\\', x, ..] => (*x == c, 2),
+        [x, ..] => (*x == c, 1),
+        [] => (false, 0),
+    }
+}
+
+/// An in-memory keyspace of byte strings
+pub struct Db<C = SystemClock> {
+    entries: TtlMap<Vec<u8>, Vec<u8>, C>,
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock::default())
+    }
+}
+
+impl<C: Clock> Db<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            entries: TtlMap::with_clock(clock),
+        }
+    }
+
+    /// Run one command and return its reply. `args[0]` is the command
+    /// name, which is case-insensitive.
+    pub fn execute(&mut self, args: &[Vec<u8>]) -> Reply {
+        let Some((name, args)) = args.split_first() else {
+            return error("empty command");
+        };
+        let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+        match (name.as_str(), args) {
+            ("PING", []) => Reply::Simple("PONG"),
+            ("PING", [msg]) => Reply::Bulk(msg.clone()),
+            ("GET", [key]) => match self.entries.get(key) {
+                Some(value) => Reply::Bulk(value.clone()),
+                None => Reply::Null,
+            },
+            ("SET", [key, value, options @ ..]) => self.set(key, value, options),
+            ("DEL", keys) if !keys.is_empty() => {
+                let removed = keys.iter().filter(|k| self.entries.remove(*k).is_some());
+                Reply::Integer(removed.count() as i64)
+            }
+            ("EXISTS", keys) if !keys.is_empty() => {
+                let found = keys.iter().filter(|k| self.entries.contains_key(*k));
+                Reply::Integer(found.count() as i64)
+            }
+            ("INCR", [key]) => self.incr(key),
+            ("KEYS", [pattern]) => {
+                let keys = self
+                    .entries
+                    .iter()
+                    .filter(|(k, _)| glob_match(pattern, k))
+                    .map(|(k, _)| Reply::Bulk(k.clone()))
+                    .collect();
+                Reply::Array(keys)
+            }
+            ("SCAN", [cursor, options @ ..]) => self.scan(cursor, options),
+            ("EXPIRE", [key, seconds]) => self.expire(key, seconds),
+            ("DBSIZE", []) => {
+                self.entries.purge();
+                Reply::Integer(self.entries.len() as i64)
+            }
+            (name, _) if COMMANDS.contains(&name) => error(&format!(
+                "wrong number of arguments for '{}' command",
+                name.to_ascii_lowercase()
+            )),
+            (name, _) => error(&format!("unknown command '{name}'")),
+        }
+    }
+
+    /// `SET key value [EX seconds | PX milliseconds]`
+    fn set(&mut self, key: &[u8], value: &[u8], options: &[Vec<u8>]) -> Reply {
+        let ttl = match options {
+            [] => None,
+            [unit, amount] => {
+                let Some(amount) = parse_int(amount) else {
+                    return not_an_integer();
+                };
+                if amount <= 0 {
+                    return error("invalid expire time in 'set' command");
+                }
+                match unit.to_ascii_uppercase().as_slice() {
+                    b"EX" => Some(Duration::from_secs(amount as u64)),
+                    b"PX" => Some(Duration::from_millis(amount as u64)),
+                    _ => return error("syntax error"),
+                }
+            }
+            _ => return error("syntax error"),
+        };
+        match ttl {
+            Some(ttl) => self
+                .entries
+                .insert_with_ttl(key.to_vec(), value.to_vec(), ttl),
+            None => self.entries.insert(key.to_vec(), value.to_vec()),
+        };
+        Reply::ok()
+    }
+
+    fn incr(&mut self, key: &[u8]) -> Reply {
+        let Some(value) = self.entries.get_mut(key) else {
+            self.entries.insert(key.to_vec(), b"1".to_vec());
+            return Reply::Integer(1);
+        };
+        // the key keeps its expiry, like in Redis
+        match parse_int(value).and_then(|n| n.checked_add(1)) {
+            Some(n) => {
+                *value = n.to_string().into_bytes();
+                Reply::Integer(n)
+            }
+            None => not_an_integer(),
+        }
+    }
+
+    fn expire(&mut self, key: &[u8], seconds: &[u8]) -> Reply {
+        let Some(seconds) = parse_int(seconds) else {
+            return not_an_integer();
+        };
+        let found = if seconds <= 0 {
+            self.entries.remove(key).is_some()
+        } else {
+            let ttl = Duration::from_secs(seconds as u64);
+            self.entries.set_ttl(key, Some(ttl))
+        };
+        Reply::Integer(found as i64)
+    }
+
+    /// `SCAN cursor [MATCH pattern] [COUNT count]`
+    fn scan(&mut self, cursor: &[u8], mut options: &[Vec<u8>]) -> Reply {
+        let Some(cursor) = std::str::from_utf8(cursor)
+            .ok()
+            .and_then(|c| c.parse().ok())
+        else {
+            return error("invalid cursor");
+        };
+        let mut pattern: &[u8] = b"*";
+        let mut count = DEFAULT_SCAN_COUNT;
+        while let [option, arg, rest @ ..] = options {
+            match option.to_ascii_uppercase().as_slice() {
+                b"MATCH" => pattern = arg,
+                b"COUNT" => match parse_int(arg) {
+                    Some(n) if n > 0 => count = n as usize,
+                    _ => return error("syntax error"),
+                },
+                _ => return error("syntax error"),
+            }
+            options = rest;
+        }
+        if !options.is_empty() {
+            return error("syntax error");
+        }
+        let (next, entries) = self.entries.scan(cursor, count);
+        let keys = entries
+            .into_iter()
+            .filter(|(k, _)| glob_match(pattern, k))
+            .map(|(k, _)| Reply::Bulk(k.clone()))
+            .collect();
+        Reply::Array(vec![
+            Reply::Bulk(next.to_string().into_bytes()),
+            Reply::Array(keys),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashmap::ttl::ManualClock;
+
+    fn run<C: Clock>(db: &mut Db<C>, command: &str) -> Reply {
+        let args: Vec<_> = command
+            .split_whitespace()
+            .map(|a| a.as_bytes().to_vec())
+            .collect();
+        db.execute(&args)
+    }
+
+    fn bulk(s: &str) -> Reply {
+        Reply::Bulk(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut db = Db::new();
+        assert_eq!(run(&mut db, "GET foo"), Reply::Null);
+        assert_eq!(run(&mut db, "set foo bar"), Reply::ok());
+        assert_eq!(run(&mut db, "GET foo"), bulk("bar"));
+        assert_eq!(run(&mut db, "EXISTS foo foo nope"), Reply::Integer(2));
+        assert_eq!(run(&mut db, "DEL foo nope"), Reply::Integer(1));
+        assert_eq!(run(&mut db, "DBSIZE"), Reply::Integer(0));
+        assert_eq!(
+            run(&mut db, "GET"),
+            error("wrong number of arguments for 'get' command")
+        );
+        assert_eq!(run(&mut db, "FLY"), error("unknown command 'FLY'"));
+    }
+
+    #[test]
+    fn test_incr() {
+        let mut db = Db::new();
+        assert_eq!(run(&mut db, "INCR n"), Reply::Integer(1));
+        assert_eq!(run(&mut db, "INCR n"), Reply::Integer(2));
+        assert_eq!(run(&mut db, "GET n"), bulk("2"));
+        run(&mut db, "SET s abc");
+        assert_eq!(run(&mut db, "INCR s"), not_an_integer());
+        run(&mut db, &format!("SET max {}", i64::MAX));
+        assert_eq!(run(&mut db, "INCR max"), not_an_integer());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let clock = ManualClock::new();
+        let mut db = Db::with_clock(clock.clone());
+        run(&mut db, "SET a 1 EX 10");
+        run(&mut db, "SET b 2");
+        run(&mut db, "SET c 3 PX 1500");
+        assert_eq!(run(&mut db, "EXPIRE b 5"), Reply::Integer(1));
+        assert_eq!(run(&mut db, "EXPIRE nope 5"), Reply::Integer(0));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(run(&mut db, "GET c"), Reply::Null);
+        assert_eq!(run(&mut db, "DBSIZE"), Reply::Integer(2));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(run(&mut db, "KEYS *"), Reply::Array(vec![bulk("a")]));
+        assert_eq!(run(&mut db, "EXPIRE a 0"), Reply::Integer(1));
+        assert_eq!(run(&mut db, "EXISTS a"), Reply::Integer(0));
+    }
+
+    #[test]
+    fn test_scan_visits_all_keys() {
+        let mut db = Db::new();
+        for i in 0..100 {
+            run(&mut db, &format!("SET key:{i} {i}"));
+        }
+        run(&mut db, "SET other x");
+        let mut seen = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let reply = run(&mut db, &format!("SCAN {cursor} MATCH key:* COUNT 7"));
+            let Reply::Array(parts) = reply else {
+                panic!("unexpected reply {reply:?}")
+            };
+            let [Reply::Bulk(next), Reply::Array(keys)] = &parts[..] else {
+                panic!("unexpected reply {parts:?}")
+            };
+            seen.extend(keys.iter().cloned());
+            cursor = String::from_utf8(next.clone()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        seen.sort_by_key(|k| format!("{k:?}"));
+        seen.dedup();
+        assert_eq!(seen.len(), 100);
+        assert_eq!(run(&mut db, "SCAN x"), error("invalid cursor"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(!glob_match(b"user:*", b"session:1"));
+        assert!(glob_match(b"*[0-9]*x", b"ab1cdx"));
+        assert!(!glob_match(b"a*b", b"ab\\"));
+        assert!(glob_match(b"a\\", b"a\\"));
+        assert!(glob_match(b"a**", b"a"));
+    }
+
+    #[test]
+    fn test_glob_match_backtracks_linearly() {
+        let text = [b'a'; 100];
+        let pattern = "*a".repeat(30);
+        assert!(glob_match(pattern.as_bytes(), &text));
+        // every `*` recursing over every suffix would never finish
+        let pattern = pattern + "*b";
+        assert!(!glob_match(pattern.as_bytes(), &text));
+    }
+}
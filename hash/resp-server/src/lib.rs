@@ -0,0 +1,168 @@
+//! A small Redis-compatible key-value server backed by `hashmap::HashMap`.
+//!
+//! It speaks enough RESP for `redis-cli` and client libraries to run
+//! `GET`, `SET`, `DEL`, `EXISTS`, `INCR`, `KEYS`, `SCAN`, `EXPIRE` and
+//! `DBSIZE` against it. Every client gets its own thread and all of them
+//! share one keyspace behind a mutex.
+//!
+//! Pipelined commands are answered in order, and the replies are flushed
+//! together once the client has no more commands waiting to be read.
+
+use std::{
+    io::{self, BufReader, BufWriter, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+pub mod db;
+pub mod resp;
+
+use db::Db;
+use resp::Reply;
+
+/// Accept clients on `listener` until it fails, serving each one on its
+/// own thread
+pub fn serve(listener: TcpListener, db: Arc<Mutex<Db>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            // a client that goes away mid-command is not our problem
+            let _ = handle_client(stream, &db);
+        });
+    }
+    Ok(())
+}
+
+fn handle_client(stream: TcpStream, db: &Mutex<Db>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let command = match resp::read_command(&mut reader) {
+            Ok(Some(command)) => command,
+            Ok(None) => return writer.flush(),
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                // like Redis, report the error and drop the connection
+                Reply::Error(format!("ERR Protocol error: {e}")).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(e) => return Err(e),
+        };
+        if !command.is_empty() {
+            let reply = db
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .execute(&command);
+            reply.write_to(&mut writer)?;
+        }
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read};
+
+    fn start() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let db = Arc::new(Mutex::new(Db::new()));
+        thread::spawn(move || serve(listener, db));
+        addr
+    }
+
+    /// Encode a command the way redis-cli does
+    fn encode(command: &[&str]) -> Vec<u8> {
+        let mut out = format!("*{}\r\n", command.len()).into_bytes();
+        for arg in command {
+            out.extend(format!("${}\r\n{arg}\r\n", arg.len()).bytes());
+        }
+        out
+    }
+
+    /// Read one reply of any type and return its raw bytes
+    fn read_reply<R: BufRead>(r: &mut R) -> String {
+        let mut line = String::new();
+        r.read_line(&mut line).unwrap();
+        match line.as_bytes()[0] {
+            b'$' if line != "$-1\r\n" => {
+                let len: usize = line[1..].trim_end().parse().unwrap();
+                let mut data = vec![0; len + 2];
+                r.read_exact(&mut data).unwrap();
+                line + &String::from_utf8(data).unwrap()
+            }
+            b'*' => {
+                let len: usize = line[1..].trim_end().parse().unwrap();
+                (0..len).fold(line, |acc, _| acc + &read_reply(r))
+            }
+            _ => line,
+        }
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let mut stream = TcpStream::connect(start()).unwrap();
+        let mut batch = Vec::new();
+        for command in [
+            &["SET", "counter", "10"][..],
+            &["INCR", "counter"],
+            &["GET", "counter"],
+            &["GET", "missing"],
+            &["EXISTS", "counter", "missing"],
+            &["DEL", "counter"],
+            &["DBSIZE"],
+        ] {
+            batch.extend(encode(command));
+        }
+        // the whole batch goes out in one write, before any reply is read
+        stream.write_all(&batch).unwrap();
+        let mut reader = BufReader::new(stream);
+        let replies: Vec<_> = (0..7).map(|_| read_reply(&mut reader)).collect();
+        assert_eq!(
+            replies,
+            [
+                "+OK\r\n",
+                ":11\r\n",
+                "$2\r\n11\r\n",
+                "$-1\r\n",
+                ":1\r\n",
+                ":1\r\n",
+                ":0\r\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_clients() {
+        let addr = start();
+        let clients: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(move || {
+                    let mut stream = TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    for _ in 0..100 {
+                        stream.write_all(&encode(&["INCR", "hits"])).unwrap();
+                        read_reply(&mut reader);
+                    }
+                })
+            })
+            .collect();
+        clients.into_iter().for_each(|c| c.join().unwrap());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET hits\r\n").unwrap();
+        assert_eq!(read_reply(&mut BufReader::new(stream)), "$3\r\n800\r\n");
+    }
+
+    #[test]
+    fn test_protocol_error_closes_connection() {
+        let mut stream = TcpStream::connect(start()).unwrap();
+        stream.write_all(b"*1\r\n+PING\r\n").unwrap();
+        let mut rest = String::new();
+        BufReader::new(stream).read_to_string(&mut rest).unwrap();
+        assert!(rest.starts_with("-ERR Protocol error"));
+    }
+}
@@ -0,0 +1,38 @@
+//! Usage: resp-server [--port PORT]
+//!
+//! Listens on 127.0.0.1, port 6379 by default. Try it with
+//! `redis-cli -p PORT`.
+
+use std::{
+    env,
+    net::TcpListener,
+    process,
+    sync::{Arc, Mutex},
+};
+
+use resp_server::{db::Db, serve};
+
+const DEFAULT_PORT: u16 = 6379;
+
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next().map(|p| p.parse())) {
+            ("--port", Some(Ok(p))) => port = p,
+            _ => {
+                eprintln!("usage: resp-server [--port PORT]");
+                process::exit(2);
+            }
+        }
+    }
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        eprintln!("cannot listen on port {port}: {e}");
+        process::exit(1);
+    });
+    println!("listening on {}", listener.local_addr().unwrap());
+    if let Err(e) = serve(listener, Arc::new(Mutex::new(Db::new()))) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
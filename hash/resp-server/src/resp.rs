@@ -0,0 +1,192 @@
+//! Reading commands and writing replies in RESP, the Redis wire protocol.
+//!
+//! Clients send each command as an array of bulk strings, for example
+//! `*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n`. Plain text lines such as
+//! `GET foo\r\n` are accepted too, which is handy with telnet or netcat.
+
+use std::io::{self, BufRead, Read, Write};
+
+// same limits as Redis
+const MAX_ARGS: usize = 1024 * 1024;
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+// longest inline command or header line, without its CRLF
+const MAX_LINE_LEN: usize = 64 * 1024;
+// Lengths come from the client before any of the data, so only this much
+// is reserved up front; the rest grows as the bytes actually arrive.
+const MAX_PREALLOC: usize = 64 * 1024;
+
+/// A reply to a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Reply::Simple("OK")
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(out, "+{s}\r\n"),
+            Reply::Error(e) => write!(out, "-{e}\r\n"),
+            Reply::Integer(n) => write!(out, ":{n}\r\n"),
+            Reply::Bulk(bytes) => {
+                write!(out, "${}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            }
+            Reply::Null => out.write_all(b"$-1\r\n"),
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Read one line and strip the trailing CRLF. Returns None at end of input.
+/// Lines longer than `MAX_LINE_LEN` are an error, so a client can't make
+/// the server buffer bytes forever by never sending a newline.
+fn read_line<R: BufRead>(r: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let limit = MAX_LINE_LEN + 2;
+    let mut line = Vec::new();
+    if r.by_ref().take(limit as u64).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        if line.len() + 1 == limit {
+            return Err(protocol_error("line too long"));
+        }
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(bytes: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+/// Read the next command. Returns None when the client closed the
+/// connection, and an empty command for a blank inline line.
+pub fn read_command<R: BufRead>(r: &mut R) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(r)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let Some(count) = line.strip_prefix(b"*") else {
+        let args = line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        return Ok(Some(args));
+    };
+    let count = parse_len(count, MAX_ARGS)?;
+    let mut args = Vec::with_capacity(count.min(MAX_PREALLOC));
+    for _ in 0..count {
+        let header = read_line(r)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let len = header
+            .strip_prefix(b"$")
+            .ok_or_else(|| protocol_error("expected bulk string"))?;
+        let len = parse_len(len, MAX_BULK_LEN)?;
+        let mut arg = Vec::with_capacity((len + 2).min(MAX_PREALLOC));
+        r.by_ref().take(len as u64 + 2).read_to_end(&mut arg)?;
+        if arg.len() < len + 2 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &[u8]) -> io::Result<Option<Vec<Vec<u8>>>> {
+        read_command(&mut &input[..])
+    }
+
+    #[test]
+    fn test_read_array_and_inline() {
+        let mut input: &[u8] = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\nSET  k v\r\n\r\n";
+        let command = read_command(&mut input).unwrap().unwrap();
+        assert_eq!(command, [b"GET".to_vec(), b"foo".to_vec()]);
+        let command = read_command(&mut input).unwrap().unwrap();
+        assert_eq!(command, [b"SET".to_vec(), b"k".to_vec(), b"v".to_vec()]);
+        assert!(read_command(&mut input).unwrap().unwrap().is_empty());
+        assert!(read_command(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(parse(b"*1\r\n+GET\r\n").is_err());
+        assert!(parse(b"*1\r\n$3\r\nGETX\r\n").is_err());
+        assert!(parse(b"*x\r\n").is_err());
+        assert!(parse(b"*2\r\n$3\r\nGET\r\n").is_err());
+    }
+
+    #[test]
+    fn test_long_lines_are_rejected() {
+        let line = vec![b'a'; MAX_LINE_LEN];
+        let command = parse(&[&line[..], b"\r\n"].concat()).unwrap().unwrap();
+        assert_eq!(command, [line]);
+        // no newline at all, or one that comes too late
+        for input in [
+            vec![b'a'; 10 * MAX_LINE_LEN],
+            [&[b'a'; MAX_LINE_LEN + 1], &b"\r\n"[..]].concat(),
+        ] {
+            let err = parse(&input).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        let header = format!("*1\r\n${}\r\n", "1".repeat(MAX_LINE_LEN + 1));
+        let err = parse(header.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_huge_lengths_are_not_trusted() {
+        // the headers claim 1Mi arguments of 512MiB, but the input ends
+        // right after them
+        let input = format!("*{MAX_ARGS}\r\n${MAX_BULK_LEN}\r\nfoo");
+        let err = parse(input.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_write_reply() {
+        let reply = Reply::Array(vec![
+            Reply::Bulk(b"0".to_vec()),
+            Reply::Array(vec![Reply::Integer(-3), Reply::Null]),
+            Reply::ok(),
+            Reply::Error("ERR boom".to_string()),
+        ]);
+        let mut out = Vec::new();
+        reply.write_to(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"*4\r\n$1\r\n0\r\n*2\r\n:-3\r\n$-1\r\n+OK\r\n-ERR boom\r\n"
+        );
+    }
+}
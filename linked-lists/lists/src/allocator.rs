@@ -0,0 +1,227 @@
+//! Where the nodes of `sixth::LinkedList` come from.
+//!
+//! `Allocator` stands in for the unstable `std::alloc::Allocator` so the
+//! list can take one on stable. `Global` is the default and just calls the
+//! global allocator. `Bump` is an arena: nodes are carved out of big chunks
+//! and all of them go away at once when the arena is reset or dropped, which
+//! suits lists that only live for one request.
+//!
+//! The `hashmap` crate in `hash/hashmap` has the same types. This
+//! crate doesn't depend on it, so the list keeps its own smaller copy,
+//! which differs from the map's on purpose:
+//!
+//! - Nodes always have the same non-zero size, so there is no `grow`, and
+//!   `Global` doesn't need the map's special case for zero-sized layouts.
+//! - The map's arena takes back its most recent allocation when it is
+//!   freed. Here a node can be spliced into a list backed by another arena
+//!   and freed through that one, and if the first arena's chunk happens to
+//!   end right where the second one's free space starts, the node would
+//!   look like the second arena's latest allocation. So freeing a node
+//!   never hands its memory back.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::ptr::{self, NonNull};
+
+/// An allocator couldn't find memory for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory allocation failed")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// A source of memory for list nodes.
+///
+/// # Safety
+///
+/// Memory from `allocate` must stay valid, and not be handed out again,
+/// until it is given to `deallocate`, even if the allocator is dropped
+/// first. Splicing moves nodes from one list into another, so they can
+/// outlive the allocator they came from, and `deallocate` must accept
+/// memory from any allocator of the same type.
+pub unsafe trait Allocator {
+    /// Allocate uninitialized memory that fits `layout`, which is never
+    /// zero-sized.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    /// Give back memory from `allocate`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated with `layout` by an allocator of this
+    /// type, and must not be used afterwards.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+/// The global allocator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Global;
+
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+const MIN_CHUNK: usize = 4096;
+const CHUNK_ALIGN: usize = 16;
+
+/// An arena that hands out memory by bumping a pointer through chunks that
+/// double in size. Freeing a single allocation does nothing; `reset` and
+/// drop free everything.
+///
+/// Only `&Bump` is an allocator, so every list using the arena borrows it
+/// and none of them can outlive a `reset`.
+pub struct Bump {
+    // every chunk, the current one last
+    chunks: RefCell<Vec<(NonNull<u8>, Layout)>>,
+    // free space in the current chunk
+    ptr: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+}
+
+// The arena owns its chunks, and `&Bump` isn't Send because of the cells.
+unsafe impl Send for Bump {}
+
+impl Bump {
+    pub fn new() -> Self {
+        Bump {
+            chunks: RefCell::new(Vec::new()),
+            ptr: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Bytes taken from the global allocator, used or not
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks
+            .borrow()
+            .iter()
+            .map(|(_, layout)| layout.size())
+            .sum()
+    }
+
+    /// Free everything at once, keeping the biggest chunk around for reuse
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if let Some((last, layout)) = chunks.pop() {
+            for (chunk, layout) in chunks.drain(..) {
+                unsafe { Global.deallocate(chunk, layout) };
+            }
+            chunks.push((last, layout));
+            self.ptr.set(last.as_ptr());
+            self.end.set(unsafe { last.as_ptr().add(layout.size()) });
+        }
+    }
+
+    /// Carve `layout` out of the current chunk, if it fits
+    fn bump(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = self.ptr.get();
+        if ptr.is_null() {
+            return None;
+        }
+        let offset = ptr.align_offset(layout.align());
+        let room = self.end.get() as usize - ptr as usize;
+        if offset.checked_add(layout.size())? > room {
+            return None;
+        }
+        unsafe {
+            let start = ptr.add(offset);
+            self.ptr.set(start.add(layout.size()));
+            Some(NonNull::new_unchecked(start))
+        }
+    }
+}
+
+impl Default for Bump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bump")
+            .field("chunks", &self.chunks.borrow().len())
+            .field("allocated_bytes", &self.allocated_bytes())
+            .finish()
+    }
+}
+
+unsafe impl Allocator for &Bump {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        if let Some(ptr) = self.bump(layout) {
+            return Ok(ptr);
+        }
+        let mut chunks = self.chunks.borrow_mut();
+        let last = chunks.last().map_or(0, |(_, layout)| layout.size());
+        let size = (last * 2)
+            .max(MIN_CHUNK)
+            .max(layout.size() + layout.align());
+        let chunk_layout = Layout::from_size_align(size, CHUNK_ALIGN).map_err(|_| AllocError)?;
+        let chunk = Global.allocate(chunk_layout)?;
+        chunks.push((chunk, chunk_layout));
+        self.ptr.set(chunk.as_ptr());
+        self.end.set(unsafe { chunk.as_ptr().add(size) });
+        Ok(self.bump(layout).expect("a new chunk has room"))
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+        // Everything is freed together by reset or drop
+    }
+}
+
+impl Drop for Bump {
+    fn drop(&mut self) {
+        for (chunk, layout) in self.chunks.get_mut().drain(..) {
+            unsafe { Global.deallocate(chunk, layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Allocator, Bump, MIN_CHUNK};
+    use std::alloc::Layout;
+
+    #[test]
+    fn bump() {
+        let mut bump = Bump::new();
+        let a = (&bump).allocate(Layout::new::<u8>()).unwrap();
+        let b = (&bump).allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(b.as_ptr() as usize % 8, 0);
+        assert!(b.as_ptr() > a.as_ptr());
+        assert_eq!(bump.allocated_bytes(), MIN_CHUNK);
+
+        // Too big for the first chunk
+        let big = Layout::from_size_align(3 * MIN_CHUNK, 64).unwrap();
+        let c = (&bump).allocate(big).unwrap();
+        assert_eq!(c.as_ptr() as usize % 64, 0);
+        assert_eq!(bump.allocated_bytes(), MIN_CHUNK + 3 * MIN_CHUNK + 64);
+
+        bump.reset();
+        assert_eq!(bump.allocated_bytes(), 3 * MIN_CHUNK + 64);
+        let first = (&bump).allocate(Layout::new::<u8>()).unwrap();
+        assert_eq!(first, bump.chunks.borrow()[0].0);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod allocator;
 pub mod fifth;
 pub mod first;
 pub mod fourth;
@@ -1,14 +1,23 @@
+use std::alloc::{handle_alloc_error, Layout};
 use std::cmp::Ordering;
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 use std::marker::PhantomData;
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 
-pub struct LinkedList<T> {
+use crate::allocator::{Allocator, Global};
+
+/// A doubly-linked list whose nodes come from the allocator `A`.
+///
+/// With a `&Bump` the nodes live in an arena, so a request-scoped list is
+/// freed in one shot by resetting the arena. Elements are still dropped
+/// when the list is.
+pub struct LinkedList<T, A: Allocator = Global> {
     front: Link<T>,
     back: Link<T>,
     len: usize,
+    alloc: A,
     _boo: PhantomData<T>,
 }
 
@@ -34,34 +43,72 @@ pub struct IterMut<'a, T> {
     _boo: PhantomData<&'a mut T>,
 }
 
-pub struct IntoIter<T> {
-    list: LinkedList<T>,
+pub struct IntoIter<T, A: Allocator = Global> {
+    list: LinkedList<T, A>,
 }
 
-pub struct CursorMut<'a, T> {
-    list: &'a mut LinkedList<T>,
+pub struct CursorMut<'a, T, A: Allocator = Global> {
+    list: &'a mut LinkedList<T, A>,
     cur: Link<T>,
     index: Option<usize>,
 }
 
 impl<T> LinkedList<T> {
     pub fn new() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator> LinkedList<T, A> {
+    pub fn new_in(alloc: A) -> Self {
         Self {
             front: None,
             back: None,
             len: 0,
+            alloc,
             _boo: PhantomData,
         }
     }
 
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// What `Box::new` used to do for us: get memory for a node from the
+    /// allocator and move the node into it.
+    fn alloc_node(&self, node: Node<T>) -> NonNull<Node<T>> {
+        let layout = Layout::new::<Node<T>>();
+        match self.alloc.allocate(layout) {
+            Ok(ptr) => {
+                let ptr = ptr.cast::<Node<T>>();
+                // SAFETY: fresh memory with the layout of a node
+                unsafe { ptr.as_ptr().write(node) };
+                ptr
+            }
+            Err(_) => handle_alloc_error(layout),
+        }
+    }
+
+    /// And what `Box::from_raw` did: move the node back out and give its
+    /// memory back to the allocator.
+    ///
+    /// # Safety
+    ///
+    /// `node` came from `alloc_node` and nothing points at it anymore.
+    unsafe fn free_node(&self, node: NonNull<Node<T>>) -> Node<T> {
+        let result = ptr::read(node.as_ptr());
+        self.alloc.deallocate(node.cast(), Layout::new::<Node<T>>());
+        result
+    }
+
     pub fn push_front(&mut self, elem: T) {
         // SAFETY: it's a linked-list, what do you want?
         unsafe {
-            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+            let new = self.alloc_node(Node {
                 front: None,
                 back: None,
                 elem,
-            })));
+            });
             if let Some(old) = self.front {
                 // Put the new front before the old one
                 (*old.as_ptr()).front = Some(new);
@@ -80,11 +127,11 @@ impl<T> LinkedList<T> {
     pub fn push_back(&mut self, elem: T) {
         // SAFETY: it's a linked-list, what do you want?
         unsafe {
-            let new = NonNull::new_unchecked(Box::into_raw(Box::new(Node {
+            let new = self.alloc_node(Node {
                 back: None,
                 front: None,
                 elem,
-            })));
+            });
             if let Some(old) = self.back {
                 // Put the new back before the old one
                 (*old.as_ptr()).back = Some(new);
@@ -104,13 +151,13 @@ impl<T> LinkedList<T> {
         unsafe {
             // Only have to do stuff if there is a front node to pop.
             self.front.map(|node| {
-                // Bring the Box back to life so we can move out its value and
-                // Drop it (Box continues to magically understand this for us).
-                let boxed_node = Box::from_raw(node.as_ptr());
-                let result = boxed_node.elem;
+                // Move the node out and hand its memory back, so we can
+                // take its value.
+                let node = self.free_node(node);
+                let result = node.elem;
 
                 // Make the next node into the new front.
-                self.front = boxed_node.back;
+                self.front = node.back;
                 if let Some(new) = self.front {
                     // Cleanup its reference to the removed node
                     (*new.as_ptr()).front = None;
@@ -121,7 +168,7 @@ impl<T> LinkedList<T> {
 
                 self.len -= 1;
                 result
-                // Box gets implicitly freed here, knows there is no T.
+                // The node is already freed, and it moved its T out.
             })
         }
     }
@@ -130,13 +177,13 @@ impl<T> LinkedList<T> {
         unsafe {
             // Only have to do stuff if there is a back node to pop.
             self.back.map(|node| {
-                // Bring the Box front to life so we can move out its value and
-                // Drop it (Box continues to magically understand this for us).
-                let boxed_node = Box::from_raw(node.as_ptr());
-                let result = boxed_node.elem;
+                // Move the node out and hand its memory back, so we can
+                // take its value.
+                let node = self.free_node(node);
+                let result = node.elem;
 
                 // Make the next node into the new back.
-                self.back = boxed_node.front;
+                self.back = node.front;
                 if let Some(new) = self.back {
                     // Cleanup its reference to the removed node
                     (*new.as_ptr()).back = None;
@@ -147,7 +194,7 @@ impl<T> LinkedList<T> {
 
                 self.len -= 1;
                 result
-                // Box gets implicitly freed here, knows there is no T.
+                // The node is already freed, and it moved its T out.
             })
         }
     }
@@ -181,7 +228,7 @@ impl<T> LinkedList<T> {
         while self.pop_front().is_some() {}
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
             front: self.front,
             back: self.back,
@@ -190,7 +237,7 @@ impl<T> LinkedList<T> {
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
             front: self.front,
             back: self.back,
@@ -199,7 +246,7 @@ impl<T> LinkedList<T> {
         }
     }
 
-    pub fn cursor_mut(&mut self) -> CursorMut<T> {
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, A> {
         CursorMut {
             list: self,
             cur: None,
@@ -208,22 +255,22 @@ impl<T> LinkedList<T> {
     }
 }
 
-impl<T> Drop for LinkedList<T> {
+impl<T, A: Allocator> Drop for LinkedList<T, A> {
     fn drop(&mut self) {
         // Pop until we have to stop
         while self.pop_front().is_some() {}
     }
 }
 
-impl<T> Default for LinkedList<T> {
+impl<T, A: Allocator + Default> Default for LinkedList<T, A> {
     fn default() -> Self {
-        Self::new()
+        Self::new_in(A::default())
     }
 }
 
-impl<T: Clone> Clone for LinkedList<T> {
+impl<T: Clone, A: Allocator + Clone> Clone for LinkedList<T, A> {
     fn clone(&self) -> Self {
-        let mut new_list = Self::new();
+        let mut new_list = Self::new_in(self.alloc.clone());
         for item in self {
             new_list.push_back(item.clone());
         }
@@ -231,7 +278,7 @@ impl<T: Clone> Clone for LinkedList<T> {
     }
 }
 
-impl<T> Extend<T> for LinkedList<T> {
+impl<T, A: Allocator> Extend<T> for LinkedList<T, A> {
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for item in iter {
             self.push_back(item);
@@ -239,41 +286,41 @@ impl<T> Extend<T> for LinkedList<T> {
     }
 }
 
-impl<T> FromIterator<T> for LinkedList<T> {
+impl<T, A: Allocator + Default> FromIterator<T> for LinkedList<T, A> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut list = Self::new();
+        let mut list = Self::default();
         list.extend(iter);
         list
     }
 }
 
-impl<T: Debug> Debug for LinkedList<T> {
+impl<T: Debug, A: Allocator> Debug for LinkedList<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_list().entries(self).finish()
     }
 }
 
-impl<T: PartialEq> PartialEq for LinkedList<T> {
+impl<T: PartialEq, A: Allocator> PartialEq for LinkedList<T, A> {
     fn eq(&self, other: &Self) -> bool {
         self.len() == other.len() && self.iter().eq(other)
     }
 }
 
-impl<T: Eq> Eq for LinkedList<T> {}
+impl<T: Eq, A: Allocator> Eq for LinkedList<T, A> {}
 
-impl<T: PartialOrd> PartialOrd for LinkedList<T> {
+impl<T: PartialOrd, A: Allocator> PartialOrd for LinkedList<T, A> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.iter().partial_cmp(other)
     }
 }
 
-impl<T: Ord> Ord for LinkedList<T> {
+impl<T: Ord, A: Allocator> Ord for LinkedList<T, A> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.iter().cmp(other)
     }
 }
 
-impl<T: Hash> Hash for LinkedList<T> {
+impl<T: Hash, A: Allocator> Hash for LinkedList<T, A> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.len().hash(state);
         for item in self {
@@ -282,7 +329,7 @@ impl<T: Hash> Hash for LinkedList<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a LinkedList<T> {
+impl<'a, T, A: Allocator> IntoIterator for &'a LinkedList<T, A> {
     type IntoIter = Iter<'a, T>;
     type Item = &'a T;
 
@@ -335,7 +382,7 @@ impl<'a, T> ExactSizeIterator for Iter<'a, T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut LinkedList<T> {
+impl<'a, T, A: Allocator> IntoIterator for &'a mut LinkedList<T, A> {
     type IntoIter = IterMut<'a, T>;
     type Item = &'a mut T;
 
@@ -388,8 +435,8 @@ impl<'a, T> ExactSizeIterator for IterMut<'a, T> {
     }
 }
 
-impl<T> IntoIterator for LinkedList<T> {
-    type IntoIter = IntoIter<T>;
+impl<T, A: Allocator> IntoIterator for LinkedList<T, A> {
+    type IntoIter = IntoIter<T, A>;
     type Item = T;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -397,7 +444,7 @@ impl<T> IntoIterator for LinkedList<T> {
     }
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -409,19 +456,19 @@ impl<T> Iterator for IntoIter<T> {
     }
 }
 
-impl<T> DoubleEndedIterator for IntoIter<T> {
+impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.list.pop_back()
     }
 }
 
-impl<T> ExactSizeIterator for IntoIter<T> {
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {
     fn len(&self) -> usize {
         self.list.len
     }
 }
 
-impl<'a, T> CursorMut<'a, T> {
+impl<'a, T, A: Allocator> CursorMut<'a, T, A> {
     pub fn index(&self) -> Option<usize> {
         self.index
     }
@@ -502,7 +549,10 @@ impl<'a, T> CursorMut<'a, T> {
         }
     }
 
-    pub fn split_before(&mut self) -> LinkedList<T> {
+    pub fn split_before(&mut self) -> LinkedList<T, A>
+    where
+        A: Clone,
+    {
         // We have this:
         //
         //     list.front -> A <-> B <-> C <-> D <- list.back
@@ -554,17 +604,22 @@ impl<'a, T> CursorMut<'a, T> {
                     front: output_front,
                     back: output_back,
                     len: output_len,
+                    alloc: self.list.alloc.clone(),
                     _boo: PhantomData,
                 }
             }
         } else {
             // We're at the ghost, just replace our list with an empty one.
             // No other state needs to be changed.
-            std::mem::replace(self.list, LinkedList::new())
+            let empty = LinkedList::new_in(self.list.alloc.clone());
+            std::mem::replace(self.list, empty)
         }
     }
 
-    pub fn split_after(&mut self) -> LinkedList<T> {
+    pub fn split_after(&mut self) -> LinkedList<T, A>
+    where
+        A: Clone,
+    {
         // We have this:
         //
         //     list.front -> A <-> B <-> C <-> D <- list.back
@@ -616,17 +671,19 @@ impl<'a, T> CursorMut<'a, T> {
                     front: output_front,
                     back: output_back,
                     len: output_len,
+                    alloc: self.list.alloc.clone(),
                     _boo: PhantomData,
                 }
             }
         } else {
             // We're at the ghost, just replace our list with an empty one.
             // No other state needs to be changed.
-            std::mem::replace(self.list, LinkedList::new())
+            let empty = LinkedList::new_in(self.list.alloc.clone());
+            std::mem::replace(self.list, empty)
         }
     }
 
-    pub fn splice_before(&mut self, mut input: LinkedList<T>) {
+    pub fn splice_before(&mut self, mut input: LinkedList<T, A>) {
         // We have this:
         //
         // input.front -> 1 <-> 2 <- input.back
@@ -688,7 +745,7 @@ impl<'a, T> CursorMut<'a, T> {
         }
     }
 
-    pub fn splice_after(&mut self, mut input: LinkedList<T>) {
+    pub fn splice_after(&mut self, mut input: LinkedList<T, A>) {
         // We have this:
         //
         // input.front -> 1 <-> 2 <- input.back
@@ -750,8 +807,8 @@ impl<'a, T> CursorMut<'a, T> {
     }
 }
 
-unsafe impl<T: Send> Send for LinkedList<T> {}
-unsafe impl<T: Sync> Sync for LinkedList<T> {}
+unsafe impl<T: Send, A: Allocator + Send> Send for LinkedList<T, A> {}
+unsafe impl<T: Sync, A: Allocator + Sync> Sync for LinkedList<T, A> {}
 
 unsafe impl<'a, T: Send> Send for Iter<'a, T> {}
 unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
@@ -794,9 +851,16 @@ fn assert_properties() {
     fn iter_mut_invariant() {}
 }
 
+// Most of these tests come from std's LinkedList and are kept as they are
 #[cfg(test)]
+#[allow(
+    clippy::neg_cmp_op_on_partial_ord,
+    clippy::zero_divided_by_zero,
+    clippy::manual_next_back,
+    clippy::useless_vec
+)]
 mod test {
-    use super::LinkedList;
+    use super::{Allocator, LinkedList};
 
     fn generate_test() -> LinkedList<i32> {
         list_from(&[0, 1, 2, 3, 4, 5, 6])
@@ -1190,7 +1254,47 @@ mod test {
         );
     }
 
-    fn check_links<T: Eq + std::fmt::Debug>(list: &LinkedList<T>) {
+    #[test]
+    fn test_bump_allocator() {
+        use crate::allocator::Bump;
+        use std::rc::Rc;
+
+        let mut arena = Bump::new();
+        let other = Bump::new();
+        let live = Rc::new(());
+        {
+            let mut m = LinkedList::new_in(&arena);
+            m.extend((0..100).map(|i| (i, Rc::clone(&live))));
+            let used = arena.allocated_bytes();
+            assert!(used > 0);
+
+            // Nodes from another arena can be spliced in, and the pieces
+            // split off keep using the arena
+            let mut p = LinkedList::new_in(&other);
+            p.push_back((1000, Rc::clone(&live)));
+            let mut cursor = m.cursor_mut();
+            cursor.move_next();
+            cursor.splice_after(p);
+            let tail = cursor.split_after();
+            assert_eq!(tail.len(), 100);
+            assert_eq!(tail.front().unwrap().0, 1000);
+            check_links(&tail);
+            assert_eq!(Rc::strong_count(&live), 102);
+
+            assert_eq!(m.pop_front().unwrap().0, 0);
+            assert!(m.is_empty());
+            // Popping doesn't give memory back to the arena
+            assert_eq!(arena.allocated_bytes(), used);
+        }
+        // The elements are dropped with the lists, the memory stays
+        assert_eq!(Rc::strong_count(&live), 1);
+        arena.reset();
+        let mut m = LinkedList::new_in(&arena);
+        m.push_front("again");
+        assert_eq!(m.front(), Some(&"again"));
+    }
+
+    fn check_links<T: Eq + std::fmt::Debug, A: Allocator>(list: &LinkedList<T, A>) {
         let from_front: Vec<_> = list.iter().collect();
         let from_back: Vec<_> = list.iter().rev().collect();
         let re_reved: Vec<_> = from_back.into_iter().rev().collect();